@@ -0,0 +1 @@
+DROP TABLE user_dictionary_words;
@@ -0,0 +1,5 @@
+CREATE TABLE user_dictionary_words (
+    word TEXT PRIMARY KEY NOT NULL,
+    freq INT DEFAULT NULL,
+    tag TEXT DEFAULT NULL
+);
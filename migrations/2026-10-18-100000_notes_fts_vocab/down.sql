@@ -0,0 +1 @@
+DROP TABLE notes_fts_vocab;
@@ -0,0 +1 @@
+CREATE VIRTUAL TABLE notes_fts_vocab USING fts5vocab(notes_fts, row);
@@ -0,0 +1,12 @@
+DROP TRIGGER folders_after_insert;
+DROP TRIGGER folders_after_delete;
+DROP TRIGGER folders_after_update;
+DROP TABLE folders_fts;
+DROP TRIGGER tags_after_insert;
+DROP TRIGGER tags_after_delete;
+DROP TRIGGER tags_after_update;
+DROP TABLE tags_fts;
+DROP TRIGGER resources_after_insert;
+DROP TRIGGER resources_after_delete;
+DROP TRIGGER resources_after_update;
+DROP TABLE resources_fts;
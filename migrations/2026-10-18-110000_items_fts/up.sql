@@ -0,0 +1,48 @@
+CREATE VIRTUAL TABLE IF NOT EXISTS folders_fts USING fts5(content='folders', content_rowid=rowid, title, id UNINDEXED, tokenize="jieba");
+
+CREATE TRIGGER folders_after_insert AFTER INSERT ON folders BEGIN
+    INSERT INTO folders_fts(rowid, title, id) VALUES (new.rowid, new.title, new.id);
+END;
+CREATE TRIGGER folders_after_delete AFTER DELETE ON folders BEGIN
+    INSERT INTO folders_fts(folders_fts, rowid, title, id) VALUES ('delete', old.rowid, old.title, old.id);
+END;
+CREATE TRIGGER folders_after_update AFTER UPDATE ON folders BEGIN
+    INSERT INTO folders_fts(folders_fts, rowid, title, id) VALUES ('delete', old.rowid, old.title, old.id);
+    INSERT INTO folders_fts(rowid, title, id) VALUES (new.rowid, new.title, new.id);
+END;
+
+INSERT INTO folders_fts(folders_fts) VALUES('rebuild');
+
+---
+
+CREATE VIRTUAL TABLE IF NOT EXISTS tags_fts USING fts5(content='tags', content_rowid=rowid, title, id UNINDEXED, tokenize="jieba");
+
+CREATE TRIGGER tags_after_insert AFTER INSERT ON tags BEGIN
+    INSERT INTO tags_fts(rowid, title, id) VALUES (new.rowid, new.title, new.id);
+END;
+CREATE TRIGGER tags_after_delete AFTER DELETE ON tags BEGIN
+    INSERT INTO tags_fts(tags_fts, rowid, title, id) VALUES ('delete', old.rowid, old.title, old.id);
+END;
+CREATE TRIGGER tags_after_update AFTER UPDATE ON tags BEGIN
+    INSERT INTO tags_fts(tags_fts, rowid, title, id) VALUES ('delete', old.rowid, old.title, old.id);
+    INSERT INTO tags_fts(rowid, title, id) VALUES (new.rowid, new.title, new.id);
+END;
+
+INSERT INTO tags_fts(tags_fts) VALUES('rebuild');
+
+---
+
+CREATE VIRTUAL TABLE IF NOT EXISTS resources_fts USING fts5(content='resources', content_rowid=rowid, title, filename, id UNINDEXED, tokenize="jieba");
+
+CREATE TRIGGER resources_after_insert AFTER INSERT ON resources BEGIN
+    INSERT INTO resources_fts(rowid, title, filename, id) VALUES (new.rowid, new.title, new.filename, new.id);
+END;
+CREATE TRIGGER resources_after_delete AFTER DELETE ON resources BEGIN
+    INSERT INTO resources_fts(resources_fts, rowid, title, filename, id) VALUES ('delete', old.rowid, old.title, old.filename, old.id);
+END;
+CREATE TRIGGER resources_after_update AFTER UPDATE ON resources BEGIN
+    INSERT INTO resources_fts(resources_fts, rowid, title, filename, id) VALUES ('delete', old.rowid, old.title, old.filename, old.id);
+    INSERT INTO resources_fts(rowid, title, filename, id) VALUES (new.rowid, new.title, new.filename, new.id);
+END;
+
+INSERT INTO resources_fts(resources_fts) VALUES('rebuild');
@@ -0,0 +1,19 @@
+DROP TRIGGER resources_after_insert;
+DROP TRIGGER resources_after_delete;
+DROP TRIGGER resources_after_update;
+DROP TABLE resources_fts;
+
+CREATE VIRTUAL TABLE IF NOT EXISTS resources_fts USING fts5(content='resources', content_rowid=rowid, title, filename, id UNINDEXED, tokenize="jieba");
+
+CREATE TRIGGER resources_after_insert AFTER INSERT ON resources BEGIN
+    INSERT INTO resources_fts(rowid, title, filename, id) VALUES (new.rowid, new.title, new.filename, new.id);
+END;
+CREATE TRIGGER resources_after_delete AFTER DELETE ON resources BEGIN
+    INSERT INTO resources_fts(resources_fts, rowid, title, filename, id) VALUES ('delete', old.rowid, old.title, old.filename, old.id);
+END;
+CREATE TRIGGER resources_after_update AFTER UPDATE ON resources BEGIN
+    INSERT INTO resources_fts(resources_fts, rowid, title, filename, id) VALUES ('delete', old.rowid, old.title, old.filename, old.id);
+    INSERT INTO resources_fts(rowid, title, filename, id) VALUES (new.rowid, new.title, new.filename, new.id);
+END;
+
+INSERT INTO resources_fts(resources_fts) VALUES('rebuild');
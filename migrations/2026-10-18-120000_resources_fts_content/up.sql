@@ -0,0 +1,20 @@
+DROP TRIGGER resources_after_insert;
+DROP TRIGGER resources_after_delete;
+DROP TRIGGER resources_after_update;
+DROP TABLE resources_fts;
+
+-- Not an external content table, `content` is extracted from the resource file.
+CREATE VIRTUAL TABLE IF NOT EXISTS resources_fts USING fts5(title, filename, content, id UNINDEXED, tokenize="jieba");
+
+CREATE TRIGGER resources_after_insert AFTER INSERT ON resources BEGIN
+    DELETE FROM resources_fts WHERE id = new.id;
+    INSERT INTO resources_fts(rowid, title, filename, content, id) VALUES (new.rowid, new.title, new.filename, '', new.id);
+END;
+CREATE TRIGGER resources_after_delete AFTER DELETE ON resources BEGIN
+    DELETE FROM resources_fts WHERE rowid = old.rowid;
+END;
+CREATE TRIGGER resources_after_update AFTER UPDATE ON resources BEGIN
+    UPDATE resources_fts SET title = new.title, filename = new.filename, id = new.id WHERE rowid = old.rowid;
+END;
+
+INSERT INTO resources_fts(rowid, title, filename, content, id) SELECT rowid, title, filename, '', id FROM resources;
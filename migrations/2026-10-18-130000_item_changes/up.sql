@@ -0,0 +1,10 @@
+CREATE TABLE item_changes (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    item_type INT NOT NULL,
+    item_id TEXT NOT NULL,
+    change_type INT NOT NULL,
+    source INT NOT NULL,
+    created_time BIGINT NOT NULL
+);
+
+CREATE INDEX item_changes_item_id ON item_changes(item_id);
@@ -0,0 +1,11 @@
+DROP INDEX notes_todo_due;
+
+ALTER TABLE notes RENAME COLUMN todo_due TO todo_due_time;
+ALTER TABLE notes ADD COLUMN todo_due BOOLEAN NOT NULL DEFAULT FALSE;
+UPDATE notes SET todo_due = TRUE WHERE todo_due_time != 0;
+ALTER TABLE notes DROP COLUMN todo_due_time;
+
+ALTER TABLE notes RENAME COLUMN todo_completed TO todo_completed_time;
+ALTER TABLE notes ADD COLUMN todo_completed BOOLEAN NOT NULL DEFAULT FALSE;
+UPDATE notes SET todo_completed = TRUE WHERE todo_completed_time != 0;
+ALTER TABLE notes DROP COLUMN todo_completed_time;
@@ -0,0 +1,26 @@
+-- Joplin stores timestamps in both columns, 0 means no due date or not completed.
+--
+-- The previous flags dropped the timestamps, the real ones are only left on the sync target. The
+-- synced to-dos that had a flag get back a sync time no later than their updated time, so the next
+-- delta pulls their remote version instead of skipping it, and the delta context is reset so every
+-- item is listed again. To-dos with local changes not uploaded yet are left alone, their upload
+-- would otherwise be taken for a conflict. Until the sync, completed to-dos use their updated time.
+UPDATE sync_items
+SET sync_time = min(sync_time, (SELECT updated_time FROM notes WHERE notes.id = sync_items.item_id)),
+    update_time = min(sync_time, (SELECT updated_time FROM notes WHERE notes.id = sync_items.item_id))
+WHERE item_type = 1
+  AND sync_time > 0
+  AND sync_time >= update_time
+  AND item_id IN (SELECT id FROM notes WHERE is_todo AND (todo_due OR todo_completed));
+DELETE FROM settings WHERE key = 'file_api.delta_context';
+
+ALTER TABLE notes RENAME COLUMN todo_due TO todo_due_flag;
+ALTER TABLE notes ADD COLUMN todo_due BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE notes DROP COLUMN todo_due_flag;
+
+ALTER TABLE notes RENAME COLUMN todo_completed TO todo_completed_flag;
+ALTER TABLE notes ADD COLUMN todo_completed BIGINT NOT NULL DEFAULT 0;
+UPDATE notes SET todo_completed = updated_time WHERE todo_completed_flag;
+ALTER TABLE notes DROP COLUMN todo_completed_flag;
+
+CREATE INDEX notes_todo_due ON notes (todo_due);
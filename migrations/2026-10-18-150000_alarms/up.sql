@@ -0,0 +1,17 @@
+CREATE TABLE alarms (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    note_id TEXT NOT NULL UNIQUE,
+    trigger_time BIGINT NOT NULL,
+    fired_time BIGINT NOT NULL DEFAULT 0
+);
+
+CREATE INDEX alarms_trigger_time ON alarms(trigger_time);
+
+-- Overdue to-dos are not notified again.
+INSERT INTO alarms (note_id, trigger_time, fired_time)
+SELECT
+    id,
+    todo_due,
+    CASE WHEN todo_due <= CAST(strftime('%s', 'now') AS INTEGER) * 1000 THEN todo_due ELSE 0 END
+FROM notes
+WHERE is_todo AND todo_due != 0 AND todo_completed = 0 AND NOT is_conflict;
@@ -0,0 +1,11 @@
+CREATE TABLE smart_filters (
+    id TEXT PRIMARY KEY NOT NULL,
+    title TEXT NOT NULL,
+    query TEXT NOT NULL DEFAULT "",
+    created_time BIGINT NOT NULL,
+    updated_time BIGINT NOT NULL,
+    user_created_time BIGINT NOT NULL DEFAULT 0,
+    user_updated_time BIGINT NOT NULL DEFAULT 0,
+    encryption_cipher_text TEXT NOT NULL DEFAULT "",
+    encryption_applied BOOLEAN NOT NULL DEFAULT FALSE
+);
@@ -0,0 +1,8 @@
+-- Filled from the note bodies when the database is opened, see `NOTE_LINKS_VERSION`.
+CREATE TABLE note_links (
+    source_id TEXT NOT NULL,
+    target_id TEXT NOT NULL,
+    PRIMARY KEY (source_id, target_id)
+);
+
+CREATE INDEX note_links_target_id ON note_links(target_id);
@@ -0,0 +1,11 @@
+-- Filled from `note_links` when the database is opened, see `NOTE_LINKS_VERSION`.
+CREATE TABLE note_resources (
+    id INTEGER NOT NULL PRIMARY KEY AUTOINCREMENT,
+    note_id TEXT NOT NULL,
+    resource_id TEXT NOT NULL,
+    is_associated BOOLEAN NOT NULL,
+    last_seen_time BIGINT NOT NULL,
+    UNIQUE (note_id, resource_id)
+);
+
+CREATE INDEX note_resources_resource_id ON note_resources(resource_id);
@@ -0,0 +1,7 @@
+-- Content hashes of the imported resources, see `Database::import_resource`.
+CREATE TABLE resource_hashes (
+    resource_id TEXT NOT NULL PRIMARY KEY,
+    hash TEXT NOT NULL
+);
+
+CREATE INDEX resource_hashes_hash ON resource_hashes(hash);
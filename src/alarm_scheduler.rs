@@ -0,0 +1,115 @@
+use std::time::Duration;
+
+use tokio::{
+    sync::{broadcast::error::RecvError, mpsc},
+    task::JoinHandle,
+};
+
+use crate::{Alarm, AsyncDatabase, DatabaseResult, DateTimeTimestamp};
+
+const LOG_TARGET: &str = "AlarmScheduler";
+
+/// Alarms are checked again after this long, so clock changes and system sleep are caught up.
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone)]
+pub struct AlarmEvent {
+    pub alarm: Alarm,
+    pub note_title: String,
+    /// The alarm was due before the scheduler started, e.g. while the app was closed.
+    pub missed: bool,
+}
+
+/// Sends an `AlarmEvent` for every due alarm, it stops when dropped or when the receiver is dropped.
+///
+/// Alarms follow the to-do due dates, including the ones changed by sync, see `Database::load_alarms`.
+#[derive(Debug)]
+pub struct AlarmScheduler {
+    task: JoinHandle<()>,
+}
+
+impl AlarmScheduler {
+    /// Must be called from a tokio runtime, missed alarms are sent first.
+    pub fn start(db: AsyncDatabase) -> (Self, mpsc::UnboundedReceiver<AlarmEvent>) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let task = tokio::spawn(run(db, sender));
+        (Self { task }, receiver)
+    }
+}
+
+impl Drop for AlarmScheduler {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn run(db: AsyncDatabase, sender: mpsc::UnboundedSender<AlarmEvent>) {
+    // Subscribes before the first query so no due date change is missed.
+    let mut changes = db.subscribe_changes();
+    let started_time = DateTimeTimestamp::now();
+    loop {
+        let sleep = match fire_due_alarms(&db, &sender, started_time).await {
+            Ok(Some(sleep)) => sleep,
+            Ok(None) => return,
+            Err(e) => {
+                log::error!(target: LOG_TARGET, "retrying in {:?}: {}", MAX_SLEEP, e);
+                MAX_SLEEP
+            }
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(sleep) => {}
+            // Any write may have changed a due date, lagging only means several did.
+            change = changes.recv() => {
+                if let Err(RecvError::Closed) = change {
+                    return;
+                }
+            }
+            _ = sender.closed() => return,
+        }
+    }
+}
+
+/// Returns how long to sleep before the next check, `None` when the receiver is dropped.
+///
+/// An alarm is only marked fired once its event is built, the ones that failed are retried later.
+async fn fire_due_alarms(
+    db: &AsyncDatabase,
+    sender: &mpsc::UnboundedSender<AlarmEvent>,
+    started_time: DateTimeTimestamp,
+) -> DatabaseResult<Option<Duration>> {
+    let mut failed = false;
+    for alarm in db.load_due_alarms(DateTimeTimestamp::now()).await? {
+        let note_title = match db.load_note(alarm.note_id.clone()).await {
+            Ok(note) => note.title,
+            Err(e) => {
+                log::warn!(target: LOG_TARGET, "postpone alarm {}: {}", alarm.id, e);
+                failed = true;
+                continue;
+            }
+        };
+        db.set_alarm_fired(alarm.id).await?;
+        log::debug!(target: LOG_TARGET, "firing alarm of {}", alarm.note_id);
+        let missed = alarm.trigger_time < started_time;
+        let event = AlarmEvent {
+            alarm,
+            note_title,
+            missed,
+        };
+        if sender.send(event).is_err() {
+            return Ok(None);
+        }
+    }
+    if failed {
+        // The failed alarms are still due, checking again right away would spin.
+        return Ok(Some(MAX_SLEEP));
+    }
+    let sleep = match db.load_next_alarm().await? {
+        Some(alarm) => {
+            let millis =
+                alarm.trigger_time.timestamp_millis() - DateTimeTimestamp::now().timestamp_millis();
+            MAX_SLEEP.min(Duration::from_millis(millis.max(0) as u64))
+        }
+        None => MAX_SLEEP,
+    };
+    Ok(Some(sleep))
+}
@@ -1,34 +1,87 @@
+mod async_database;
+mod change;
 mod connection_options;
 mod error;
 mod jieba_tokenizer;
+mod markdown_export;
+mod note_links;
+mod note_list;
+mod resource_gc;
+mod resource_import;
+mod resource_text;
+mod search;
+mod smart_filter;
 mod sqlite3_fts5;
+mod thumbnail;
+mod tokenizer;
+mod transaction;
 
+pub use async_database::AsyncDatabase;
+pub use change::DatabaseChange;
 pub use error::DatabaseError;
+pub use markdown_export::{MarkdownExportReport, EXPORT_RESOURCE_DIR};
+pub use note_list::{
+    NoteListItem, NoteListRequest, NoteListResponse, NoteSortField, SortDirection, TodoFilter,
+};
+pub use resource_gc::ResourceGcReport;
+pub use resource_import::ImportedResource;
+pub use search::{
+    SearchBodyOption, SearchHighlight, SearchItem, SearchItemsRequest, SearchNotesRequest,
+    SearchNotesResponse, SearchRanking,
+};
+pub use smart_filter::{SmartFilterQuery, TimeRange};
+pub use thumbnail::MAX_THUMBNAIL_SIZE;
+pub use tokenizer::{HanSegmenter, TokenizerConfig};
+pub use transaction::Transaction;
 
 use diesel::{
-    dsl::exists,
+    dsl::{exists, not, sql},
+    query_builder::{BoxedSqlQuery, SqlQuery},
     r2d2::{ConnectionManager, Pool},
-    select, sql_query, ExpressionMethods, OptionalExtension, QueryDsl, RunQueryDsl,
-    SqliteConnection,
+    select, sql_query,
+    sql_types::{BigInt, Bool, Double, Integer, Nullable, Text},
+    sqlite::Sqlite,
+    AsExpression, BoolExpressionMethods, ExpressionMethods, FromSqlRow, OptionalExtension,
+    QueryDsl, RunQueryDsl, SqliteConnection,
 };
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 use std::{
-    fs,
+    collections::{HashMap, HashSet, VecDeque},
+    fs, io,
     path::{Path, PathBuf},
+    time::{Duration, SystemTime},
 };
+use tokio::sync::broadcast;
 
+use change::CHANGE_CHANNEL_CAPACITY;
 use connection_options::ConnectionOptions;
+use note_links::{extract_link_ids, NOTE_LINKS_VERSION};
+use note_list::{local_day_bounds, preview_text};
+use resource_gc::{remove_file, resource_id_of_file};
+use resource_import::{hash_file, ImportedFile};
+use resource_text::{extract_text, RESOURCE_TEXT_VERSION};
+use search::{
+    best_correction, fuzzy_word, max_edit_distance, SearchCount, VocabTerm, DEMOTION_FACTOR,
+    ITEMS_FTS_TABLES, SEARCH_ITEM_SNIPPET_TOKENS,
+};
+use thumbnail::remove_thumbnails;
+use tokenizer::{FtsTokenizerGlobal, FTS_TOKENIZER_VERSION};
 
 use crate::{
     models::Folder,
     new_id,
     sync::{ForSyncSerializer, SerializeForSync},
-    AbbrNote, DateTimeTimestamp, DeletedItem, ModelType, NewDeletedItem, NewSetting, NewSyncItem,
-    Note, NoteFts, NoteTag, NoteTagId, Resource, Setting, Status, SyncItem, Tag,
+    AbbrNote, Alarm, ChangeKind, DateTimeTimestamp, DeletedItem, ItemChange, ModelType,
+    NewDeletedItem, NewItemChange, NewSetting, NewSyncItem, Note, NoteFts, NoteLink, NoteResource,
+    NoteTag, NoteTagId, Placement, Resource, Setting, SmartFilter, Status, SyncItem, Tag, TagNode,
+    UserDictionaryWord, TAG_PATH_SEPARATOR,
 };
 
 pub type DatabaseResult<T> = Result<T, DatabaseError>;
 
+/// Gap between two notes when a folder is renumbered by `reorder_note`.
+const NOTE_ORDER_STEP: i64 = 1000;
+
 // use diesel::prelude::sql_function;
 // use diesel::sql_types::Text;
 // how to declare a sql_function?
@@ -36,7 +89,8 @@ pub type DatabaseResult<T> = Result<T, DatabaseError>;
 //     fn highlight(table: Text, column: Integer, before: Text, after: Text) -> Text;
 // }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Integer)]
 pub enum UpdateSource {
     RemoteSync,
     LocalEdit,
@@ -51,18 +105,14 @@ impl UpdateSource {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum SearchBodyOption {
-    Highlight,
-    Snippet { max_tokens: u8 },
-}
-
 #[derive(Debug)]
 pub struct Database {
     connection_pool: Pool<ConnectionManager<SqliteConnection>>,
     _path: PathBuf,
     _filename: String,
     resource_path: PathBuf,
+    tokenizer: FtsTokenizerGlobal,
+    changes: broadcast::Sender<DatabaseChange>,
 }
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations");
@@ -80,8 +130,12 @@ impl Database {
             .expect("database url error")
             .to_string();
         let manager = ConnectionManager::<SqliteConnection>::new(database_url);
+        let tokenizer = FtsTokenizerGlobal::default();
         let connection_pool = Pool::builder()
-            .connection_customizer(Box::<ConnectionOptions>::default())
+            .connection_customizer(Box::new(ConnectionOptions {
+                tokenizer: tokenizer.clone(),
+                ..Default::default()
+            }))
             .max_size(16)
             .build(manager)?;
 
@@ -90,6 +144,8 @@ impl Database {
             _path: data_dir.into(),
             _filename: filename.into(),
             resource_path: resource_path.to_path_buf(),
+            tokenizer,
+            changes: broadcast::channel(CHANGE_CHANNEL_CAPACITY).0,
         };
         db.init()?;
         Ok(db)
@@ -106,19 +162,55 @@ impl Database {
             DatabaseError::Migration(e)
         })?;
         diesel::sql_query("PRAGMA journal_mode = WAL").execute(&mut connection)?;
+        self.init_fts()?;
+        self.init_note_links()?;
+        Ok(())
+    }
+
+    fn init_note_links(&self) -> DatabaseResult<()> {
+        let version = self.get_setting_value(Setting::NOTE_LINKS_VERSION)?;
+        if version.map(|v| v.value).as_deref() != Some(NOTE_LINKS_VERSION) {
+            self.reindex_note_links()?;
+            self.replace_setting(Setting::NOTE_LINKS_VERSION, NOTE_LINKS_VERSION)?;
+        }
+        Ok(())
+    }
+
+    fn init_fts(&self) -> DatabaseResult<()> {
+        if let Some(setting) = self.get_setting_value(Setting::FTS_TOKENIZER_CONFIG)? {
+            match serde_json::from_str(&setting.value) {
+                Ok(config) => *self.tokenizer.config.write() = config,
+                Err(e) => log::warn!("invalid tokenizer config {}: {}", setting.value, e),
+            }
+        }
+        let words = self.load_user_dictionary_words()?;
+        // The tokenizer starts with the default dictionary, only rebuild it for user words.
+        if !words.is_empty() {
+            self.tokenizer.jieba.load_user_dictionary(&words);
+        }
+        let version = self.get_setting_value(Setting::FTS_TOKENIZER_VERSION)?;
+        if version.map(|v| v.value).as_deref() != Some(FTS_TOKENIZER_VERSION) {
+            self.rebuild_fts()?;
+            self.replace_setting(Setting::FTS_TOKENIZER_VERSION, FTS_TOKENIZER_VERSION)?;
+        }
+        let version = self.get_setting_value(Setting::FTS_RESOURCE_TEXT_VERSION)?;
+        if version.map(|v| v.value).as_deref() != Some(RESOURCE_TEXT_VERSION) {
+            self.reindex_resource_texts()?;
+            self.replace_setting(Setting::FTS_RESOURCE_TEXT_VERSION, RESOURCE_TEXT_VERSION)?;
+        }
         Ok(())
     }
 }
 
-impl Database {
-    pub fn insert_root_folder(&self, title: impl Into<String>) -> DatabaseResult<Folder> {
+impl Transaction<'_> {
+    pub fn insert_root_folder(&mut self, title: impl Into<String>) -> DatabaseResult<Folder> {
         let folder = Folder::new_root(title);
         self.replace_folder(&folder, UpdateSource::LocalEdit)?;
         Ok(folder)
     }
 
     pub fn insert_folder_with_parent(
-        &self,
+        &mut self,
         title: impl Into<String>,
         parent_id: impl Into<String>,
     ) -> DatabaseResult<Folder> {
@@ -128,7 +220,7 @@ impl Database {
     }
 
     pub fn replace_folder(
-        &self,
+        &mut self,
         folder: &Folder,
         update_source: UpdateSource,
     ) -> DatabaseResult<()> {
@@ -136,44 +228,80 @@ impl Database {
             UpdateSource::RemoteSync => folder.clone(),
             UpdateSource::LocalEdit => folder.updated(),
         };
-        let mut conn = self.connection_pool.get()?;
         use crate::schema::folders;
         diesel::replace_into(folders::table)
             .values(&folder)
-            .execute(&mut conn)?;
+            .execute(self.conn)?;
         self.replace_sync_item(ModelType::Folder, folder.id.as_str(), update_source)?;
         Ok(())
     }
 
-    pub fn load_folders(&self) -> DatabaseResult<Vec<Folder>> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn load_folders(&mut self) -> DatabaseResult<Vec<Folder>> {
         use crate::schema::folders;
         Ok(folders::table
             .select(Folder::SELECTION)
             .order(folders::title.asc())
-            .load(&mut conn)?)
+            .load(self.conn)?)
     }
 
-    pub fn load_folder(&self, id: &str) -> DatabaseResult<Folder> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn load_folder(&mut self, id: &str) -> DatabaseResult<Folder> {
         use crate::schema::folders;
         Ok(folders::table
             .filter(folders::id.eq(id))
             .select(Folder::SELECTION)
-            .first(&mut conn)?)
+            .first(self.conn)?)
     }
 
-    pub fn load_subfolders(&self, id: &str) -> DatabaseResult<Vec<Folder>> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn load_subfolders(&mut self, id: &str) -> DatabaseResult<Vec<Folder>> {
         use crate::schema::folders;
         Ok(folders::table
             .select(Folder::SELECTION)
             .filter(folders::parent_id.eq(id))
-            .load(&mut conn)?)
+            .load(self.conn)?)
     }
 
-    pub fn delete_folder(&self, id: &str, update_source: UpdateSource) -> DatabaseResult<()> {
-        let mut conn = self.connection_pool.get()?;
+    /// Moves the folder under `parent_id`, or to the root when it is `None`.
+    ///
+    /// Synced root folders have an empty `parent_id`, it is a root like `None`.
+    pub fn move_folder(&mut self, id: &str, parent_id: Option<&str>) -> DatabaseResult<()> {
+        use crate::schema::folders;
+        let folder = self.load_folder(id)?;
+        let parent_id = parent_id.filter(|parent_id| !parent_id.is_empty());
+        if folder.parent_id.as_deref().filter(|id| !id.is_empty()) == parent_id {
+            return Ok(());
+        }
+        if let Some(parent_id) = parent_id {
+            self.load_folder(parent_id)?;
+        }
+        let mut ancestor_id = parent_id.map(str::to_string);
+        let mut visited = HashSet::new();
+        while let Some(current_id) = ancestor_id.filter(|id| !id.is_empty()) {
+            if current_id == id {
+                return Err(DatabaseError::FolderCycle);
+            }
+            if !visited.insert(current_id.clone()) {
+                break;
+            }
+            // A parent that is not synced yet ends the walk.
+            ancestor_id = folders::table
+                .filter(folders::id.eq(&current_id))
+                .select(folders::parent_id)
+                .first::<Option<String>>(self.conn)
+                .optional()?
+                .flatten();
+        }
+        diesel::update(folders::table)
+            .filter(folders::id.eq(id))
+            .set((
+                folders::parent_id.eq(parent_id),
+                folders::updated_time.eq(DateTimeTimestamp::now()),
+            ))
+            .execute(self.conn)?;
+        self.replace_sync_item(ModelType::Folder, id, UpdateSource::LocalEdit)?;
+        Ok(())
+    }
+
+    pub fn delete_folder(&mut self, id: &str, update_source: UpdateSource) -> DatabaseResult<()> {
         use crate::schema::folders;
         if update_source.is_local_edit() {
             self.delete_notes_by_folder_id(id)?;
@@ -185,23 +313,22 @@ impl Database {
         self.delete_sync_item(id)?;
         diesel::delete(folders::table)
             .filter(folders::id.eq(id))
-            .execute(&mut conn)?;
+            .execute(self.conn)?;
         if update_source.is_local_edit() {
             self.insert_deleted_item(ModelType::Folder, id)?;
         }
+        self.record_changes(ModelType::Folder, &[id], ChangeKind::Deleted, update_source)?;
         Ok(())
     }
 
-    pub fn folder_count(&self) -> DatabaseResult<i64> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn folder_count(&mut self) -> DatabaseResult<i64> {
         use crate::schema::folders;
-        Ok(folders::table.count().get_result(&mut conn)?)
+        Ok(folders::table.count().get_result(self.conn)?)
     }
 }
 
-impl Database {
-    pub fn load_abbr_notes(&self, parent_id: Option<&str>) -> DatabaseResult<Vec<AbbrNote>> {
-        let mut conn = self.connection_pool.get()?;
+impl Transaction<'_> {
+    pub fn load_abbr_notes(&mut self, parent_id: Option<&str>) -> DatabaseResult<Vec<AbbrNote>> {
         use crate::schema::notes;
         let selection = (
             notes::id,
@@ -217,13 +344,12 @@ impl Database {
         Ok(match parent_id {
             Some(parent_id) => query_stmt
                 .filter(notes::parent_id.eq(parent_id))
-                .load(&mut conn),
-            None => query_stmt.load(&mut conn),
+                .load(self.conn),
+            None => query_stmt.load(self.conn),
         }?)
     }
 
-    pub fn load_abbr_conflict_notes(&self) -> DatabaseResult<Vec<AbbrNote>> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn load_abbr_conflict_notes(&mut self) -> DatabaseResult<Vec<AbbrNote>> {
         use crate::schema::notes;
         Ok(notes::table
             .select((
@@ -235,20 +361,72 @@ impl Database {
             ))
             .filter(notes::is_conflict.eq(true))
             .order(notes::user_updated_time.desc())
-            .load(&mut conn)?)
+            .load(self.conn)?)
     }
 
-    pub fn conflict_note_exists(&self) -> DatabaseResult<bool> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn load_note_list(
+        &mut self,
+        request: &NoteListRequest,
+    ) -> DatabaseResult<NoteListResponse> {
+        use crate::schema::notes;
+        let preview = match request.preview_length {
+            // Leaves room for the whitespace collapsed by `preview_text`.
+            Some(length) => format!("substr(body, 1, {})", length * 2),
+            None => "NULL".to_string(),
+        };
+        let mut query = notes::table
+            .select((
+                notes::id,
+                notes::parent_id,
+                notes::title,
+                notes::user_created_time,
+                notes::user_updated_time,
+                notes::is_todo,
+                notes::todo_due,
+                notes::todo_completed,
+                sql::<Nullable<Text>>(&preview),
+            ))
+            .filter(notes::is_conflict.eq(false))
+            .order(sql::<Text>(&format!(
+                "{column} {direction}, id {direction}",
+                column = request.sort_field.column(),
+                direction = request.sort_direction.keyword(),
+            )))
+            .offset(request.offset)
+            .limit(request.limit.unwrap_or(-1))
+            .into_boxed();
+        let mut count_query = notes::table
+            .filter(notes::is_conflict.eq(false))
+            .count()
+            .into_boxed();
+        if let Some(parent_id) = &request.parent_id {
+            query = query.filter(notes::parent_id.eq(parent_id));
+            count_query = count_query.filter(notes::parent_id.eq(parent_id));
+        }
+        let mut notes: Vec<NoteListItem> = query.load(self.conn)?;
+        if let Some(length) = request.preview_length {
+            for note in notes.iter_mut() {
+                note.preview = note
+                    .preview
+                    .as_deref()
+                    .map(|body| preview_text(body, length));
+            }
+        }
+        Ok(NoteListResponse {
+            total_count: count_query.get_result(self.conn)?,
+            notes,
+        })
+    }
+
+    pub fn conflict_note_exists(&mut self) -> DatabaseResult<bool> {
         use crate::schema::notes;
         Ok(
             select(exists(notes::table.filter(notes::is_conflict.eq(true))))
-                .get_result(&mut conn)?,
+                .get_result(self.conn)?,
         )
     }
 
-    pub fn load_note(&self, id: &str) -> DatabaseResult<Note> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn load_note(&mut self, id: &str) -> DatabaseResult<Note> {
         use crate::schema::notes;
         Ok(notes::table
             .filter(notes::id.eq(id))
@@ -282,11 +460,11 @@ impl Database {
                 notes::conflict_original_id,
                 notes::master_key_id,
             ))
-            .first(&mut conn)?)
+            .first(self.conn)?)
     }
 
     pub fn insert_note_with_parent(
-        &self,
+        &mut self,
         title: impl Into<String>,
         body: impl Into<String>,
         parent_id: impl Into<String>,
@@ -296,31 +474,31 @@ impl Database {
         Ok(note)
     }
 
-    pub fn replace_note(&self, note: &Note, update_source: UpdateSource) -> DatabaseResult<()> {
+    pub fn replace_note(&mut self, note: &Note, update_source: UpdateSource) -> DatabaseResult<()> {
         let note = match update_source {
             UpdateSource::RemoteSync => note.clone(),
             UpdateSource::LocalEdit => note.updated(),
         };
-        let mut conn = self.connection_pool.get()?;
         use crate::schema::notes;
         let note_exist: bool = select(exists(notes::table.filter(notes::id.eq(note.id.as_str()))))
-            .get_result(&mut conn)?;
+            .get_result(self.conn)?;
         if note_exist {
             diesel::update(notes::table)
                 .filter(notes::id.eq(note.id.as_str()))
                 .set(&note)
-                .execute(&mut conn)?;
+                .execute(self.conn)?;
         } else {
             diesel::insert_into(notes::table)
                 .values(&note)
-                .execute(&mut conn)?;
+                .execute(self.conn)?;
         }
+        self.update_note_alarm(&note.id)?;
+        self.update_note_links(&note.id, &note.body)?;
         self.replace_sync_item(ModelType::Note, note.id.as_str(), update_source)?;
         Ok(())
     }
 
-    pub fn update_note_body(&self, id: &str, body: &str) -> DatabaseResult<()> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn update_note_body(&mut self, id: &str, body: &str) -> DatabaseResult<()> {
         use crate::schema::notes;
         let dt = DateTimeTimestamp::now();
         diesel::update(notes::table)
@@ -330,13 +508,13 @@ impl Database {
                 notes::updated_time.eq(dt),
                 notes::user_updated_time.eq(dt),
             ))
-            .execute(&mut conn)?;
+            .execute(self.conn)?;
+        self.update_note_links(id, body)?;
         self.replace_sync_item(ModelType::Note, id, UpdateSource::LocalEdit)?;
         Ok(())
     }
 
-    pub fn update_note_title(&self, id: &str, title: &str) -> DatabaseResult<()> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn update_note_title(&mut self, id: &str, title: &str) -> DatabaseResult<()> {
         use crate::schema::notes;
         let dt = DateTimeTimestamp::now();
         diesel::update(notes::table)
@@ -346,170 +524,926 @@ impl Database {
                 notes::updated_time.eq(dt),
                 notes::user_updated_time.eq(dt),
             ))
-            .execute(&mut conn)?;
+            .execute(self.conn)?;
         self.replace_sync_item(ModelType::Note, id, UpdateSource::LocalEdit)?;
         Ok(())
     }
 
-    pub fn delete_note(&self, id: &str, update_source: UpdateSource) -> DatabaseResult<()> {
-        let mut conn = self.connection_pool.get()?;
+    /// Turning to-do mode off also clears the due date and the completion time.
+    pub fn set_note_todo(&mut self, id: &str, is_todo: bool) -> DatabaseResult<()> {
+        use crate::schema::notes;
+        let dt = DateTimeTimestamp::now();
+        let query = diesel::update(notes::table).filter(notes::id.eq(id));
+        if is_todo {
+            query
+                .set((
+                    notes::is_todo.eq(true),
+                    notes::updated_time.eq(dt),
+                    notes::user_updated_time.eq(dt),
+                ))
+                .execute(self.conn)?;
+        } else {
+            query
+                .set((
+                    notes::is_todo.eq(false),
+                    notes::todo_due.eq(DateTimeTimestamp::zero()),
+                    notes::todo_completed.eq(DateTimeTimestamp::zero()),
+                    notes::updated_time.eq(dt),
+                    notes::user_updated_time.eq(dt),
+                ))
+                .execute(self.conn)?;
+        }
+        self.update_note_alarm(id)?;
+        self.replace_sync_item(ModelType::Note, id, UpdateSource::LocalEdit)?;
+        Ok(())
+    }
+
+    pub fn complete_todo(&mut self, id: &str, completed: bool) -> DatabaseResult<()> {
+        use crate::schema::notes;
+        let dt = DateTimeTimestamp::now();
+        let completed_time = if completed {
+            dt
+        } else {
+            DateTimeTimestamp::zero()
+        };
+        diesel::update(notes::table)
+            .filter(notes::id.eq(id))
+            .set((
+                notes::todo_completed.eq(completed_time),
+                notes::updated_time.eq(dt),
+                notes::user_updated_time.eq(dt),
+            ))
+            .execute(self.conn)?;
+        self.update_note_alarm(id)?;
+        self.replace_sync_item(ModelType::Note, id, UpdateSource::LocalEdit)?;
+        Ok(())
+    }
+
+    /// `None` removes the due date.
+    pub fn set_todo_due(&mut self, id: &str, due: Option<DateTimeTimestamp>) -> DatabaseResult<()> {
+        use crate::schema::notes;
+        let dt = DateTimeTimestamp::now();
+        diesel::update(notes::table)
+            .filter(notes::id.eq(id))
+            .set((
+                notes::todo_due.eq(due.unwrap_or_else(DateTimeTimestamp::zero)),
+                notes::updated_time.eq(dt),
+                notes::user_updated_time.eq(dt),
+            ))
+            .execute(self.conn)?;
+        self.update_note_alarm(id)?;
+        self.replace_sync_item(ModelType::Note, id, UpdateSource::LocalEdit)?;
+        Ok(())
+    }
+
+    /// Uncompleted to-dos with a due date across every folder.
+    pub fn load_todos(&mut self, filter: TodoFilter) -> DatabaseResult<Vec<NoteListItem>> {
+        use crate::schema::notes;
+        let query = notes::table
+            .select((
+                notes::id,
+                notes::parent_id,
+                notes::title,
+                notes::user_created_time,
+                notes::user_updated_time,
+                notes::is_todo,
+                notes::todo_due,
+                notes::todo_completed,
+                sql::<Nullable<Text>>("NULL"),
+            ))
+            .filter(notes::is_conflict.eq(false))
+            .filter(notes::is_todo.eq(true))
+            .filter(notes::todo_completed.eq(DateTimeTimestamp::zero()))
+            .filter(notes::todo_due.ne(DateTimeTimestamp::zero()))
+            .order((notes::todo_due.asc(), notes::id))
+            .into_boxed();
+        let now = DateTimeTimestamp::now();
+        let query = match filter {
+            TodoFilter::Overdue => query.filter(notes::todo_due.lt(now)),
+            TodoFilter::DueToday => {
+                let (start, end) = local_day_bounds(now);
+                query
+                    .filter(notes::todo_due.ge(start))
+                    .filter(notes::todo_due.lt(end))
+            }
+            TodoFilter::Upcoming => query.filter(notes::todo_due.ge(now)),
+        };
+        Ok(query.load(self.conn)?)
+    }
+
+    pub fn move_note(&mut self, id: &str, parent_id: &str) -> DatabaseResult<()> {
+        self.move_notes(&[id], parent_id)
+    }
+
+    /// Moves the notes to the folder, keeping their order.
+    pub fn move_notes(&mut self, notes_id: &[&str], parent_id: &str) -> DatabaseResult<()> {
+        self.load_folder(parent_id)?;
+        for id in notes_id {
+            self.set_note_parent(id, Some(parent_id))?;
+        }
+        Ok(())
+    }
+
+    /// `None` moves the note to the root.
+    fn set_note_parent(&mut self, id: &str, parent_id: Option<&str>) -> DatabaseResult<()> {
+        use crate::schema::notes;
+        let now = DateTimeTimestamp::now();
+        let updated = diesel::update(notes::table)
+            .filter(notes::id.eq(id))
+            .set((
+                notes::parent_id.eq(parent_id),
+                notes::updated_time.eq(now),
+                notes::user_updated_time.eq(now),
+            ))
+            .execute(self.conn)?;
+        if updated == 0 {
+            return Err(diesel::result::Error::NotFound.into());
+        }
+        self.replace_sync_item(ModelType::Note, id, UpdateSource::LocalEdit)?;
+        Ok(())
+    }
+
+    /// Places the note before or after `anchor_id` in the custom order, moving it to the folder of the anchor.
+    ///
+    /// The order is listed by `load_note_list` with `NoteSortField::Order` and `SortDirection::Descending`,
+    /// the folder is renumbered when there is no gap left.
+    pub fn reorder_note(
+        &mut self,
+        id: &str,
+        anchor_id: &str,
+        placement: Placement,
+    ) -> DatabaseResult<()> {
+        use crate::schema::notes;
+        if id == anchor_id {
+            return Ok(());
+        }
+        let anchor = self.load_note(anchor_id)?;
+        let note = self.load_note(id)?;
+        if note.parent_id != anchor.parent_id {
+            self.set_note_parent(id, anchor.parent_id.as_deref())?;
+        }
+        let mut query = notes::table
+            .select((notes::id, notes::order))
+            .filter(notes::is_conflict.eq(false))
+            .filter(notes::id.ne(id))
+            // Same tie-break as `load_note_list`.
+            .order((notes::order.desc(), notes::id.desc()))
+            .into_boxed();
+        query = match &anchor.parent_id {
+            Some(parent_id) => query.filter(notes::parent_id.eq(parent_id)),
+            None => query.filter(notes::parent_id.is_null()),
+        };
+        let mut orders: Vec<(String, i64)> = query.load(self.conn)?;
+        let anchor_index = orders
+            .iter()
+            .position(|(id, _)| id == anchor_id)
+            .ok_or(DatabaseError::Select)?;
+        let index = match placement {
+            Placement::Before => anchor_index,
+            Placement::After => anchor_index + 1,
+        };
+        let above = index.checked_sub(1).map(|i| orders[i].1);
+        let below = orders.get(index).map(|(_, order)| *order);
+        let order = match (above, below) {
+            (Some(above), Some(below)) if above - below >= 2 => Some(below + (above - below) / 2),
+            (Some(_), Some(_)) => None,
+            (Some(above), None) => Some(above - NOTE_ORDER_STEP),
+            (None, Some(below)) => Some(below + NOTE_ORDER_STEP),
+            (None, None) => Some(note.order),
+        };
+        if let Some(order) = order {
+            return self.update_note_order(id, order);
+        }
+        orders.insert(index, (id.to_string(), note.order));
+        let top = DateTimeTimestamp::now().timestamp_millis();
+        for (i, (id, order)) in orders.iter().enumerate() {
+            let new_order = top - i as i64 * NOTE_ORDER_STEP;
+            if *order != new_order {
+                self.update_note_order(id, new_order)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn update_note_order(&mut self, id: &str, order: i64) -> DatabaseResult<()> {
+        use crate::schema::notes;
+        let now = DateTimeTimestamp::now();
+        diesel::update(notes::table)
+            .filter(notes::id.eq(id))
+            .set((
+                notes::order.eq(order),
+                notes::updated_time.eq(now),
+                notes::user_updated_time.eq(now),
+            ))
+            .execute(self.conn)?;
+        self.replace_sync_item(ModelType::Note, id, UpdateSource::LocalEdit)?;
+        Ok(())
+    }
+
+    pub fn delete_note(&mut self, id: &str, update_source: UpdateSource) -> DatabaseResult<()> {
         use crate::schema::notes;
         self.delete_sync_item(id)?;
         diesel::delete(notes::table)
             .filter(notes::id.eq(id))
-            .execute(&mut conn)?;
+            .execute(self.conn)?;
+        self.delete_note_alarms(&[id])?;
+        self.delete_note_links(&[id])?;
         if update_source.is_local_edit() {
             self.delete_note_tag_by_note_ids(&[id], UpdateSource::LocalEdit)?;
             self.insert_deleted_item(ModelType::Note, id)?;
         }
+        self.record_changes(ModelType::Note, &[id], ChangeKind::Deleted, update_source)?;
         Ok(())
     }
 
-    pub fn delete_notes(&self, notes_id: &[&str]) -> DatabaseResult<()> {
+    pub fn delete_notes(&mut self, notes_id: &[&str]) -> DatabaseResult<()> {
         if notes_id.is_empty() {
             return Ok(());
         }
-        let mut conn = self.connection_pool.get()?;
         use crate::schema::notes;
         self.delete_sync_items(notes_id)?;
         diesel::delete(notes::table)
             .filter(notes::id.eq_any(notes_id))
-            .execute(&mut conn)?;
+            .execute(self.conn)?;
+        self.delete_note_alarms(notes_id)?;
+        self.delete_note_links(notes_id)?;
         self.delete_note_tag_by_note_ids(notes_id, UpdateSource::LocalEdit)?;
         self.insert_deleted_items(ModelType::Note, notes_id)?;
+        self.record_changes(
+            ModelType::Note,
+            notes_id,
+            ChangeKind::Deleted,
+            UpdateSource::LocalEdit,
+        )?;
         Ok(())
     }
 
-    fn delete_notes_by_folder_id(&self, folder_id: &str) -> DatabaseResult<()> {
+    fn delete_notes_by_folder_id(&mut self, folder_id: &str) -> DatabaseResult<()> {
         let notes = self.load_abbr_notes(Some(folder_id))?;
         let note_ids: Vec<&str> = notes.iter().map(|n| n.id.as_str()).collect();
         self.delete_notes(&note_ids)?;
         Ok(())
     }
 
-    pub fn note_count(&self) -> DatabaseResult<i64> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn note_count(&mut self) -> DatabaseResult<i64> {
         use crate::schema::notes;
-        Ok(notes::table.count().get_result(&mut conn)?)
+        Ok(notes::table.count().get_result(self.conn)?)
     }
+}
 
-    pub fn rebuild_fts(&self) -> DatabaseResult<()> {
-        log::info!("rebuilding notes_fts");
-        let mut conn = self.connection_pool.get()?;
-        sql_query("INSERT INTO notes_fts(notes_fts) VALUES('rebuild')").execute(&mut conn)?;
+impl Transaction<'_> {
+    /// Replaces the links of the note with the ones in `body`, links to the note itself are skipped.
+    fn update_note_links(&mut self, note_id: &str, body: &str) -> DatabaseResult<()> {
+        use crate::schema::note_links;
+        self.delete_note_links(&[note_id])?;
+        let links: Vec<NoteLink> = extract_link_ids(body)
+            .into_iter()
+            .filter(|target_id| target_id != note_id)
+            .map(|target_id| NoteLink {
+                source_id: note_id.to_string(),
+                target_id,
+            })
+            .collect();
+        if !links.is_empty() {
+            diesel::insert_into(note_links::table)
+                .values(&links)
+                .execute(self.conn)?;
+        }
+        self.associate_note_resources("source_id", note_id)?;
         Ok(())
     }
 
-    pub fn search_notes(
-        &self,
-        search_term: &str,
-        option: Option<SearchBodyOption>,
-    ) -> DatabaseResult<Vec<NoteFts>> {
-        let mut conn = self.connection_pool.get()?;
-        use crate::notes_fts;
-        if let Some(option) = option {
-            let auxiliary_function = match option {
-                SearchBodyOption::Highlight => {
-                    "highlight(`notes_fts`, 1, '<b>', '</b>')".to_string()
-                }
-                SearchBodyOption::Snippet { max_tokens } => {
-                    assert!(max_tokens <= 64);
-                    format!("snippet(`notes_fts`, 1, '<b>', '</b>', '…', {max_tokens})")
-                }
-            };
-            let query = format!("SELECT `notes_fts`.`id`, highlight(`notes_fts`, 0, '<b>', '</b>') as `title`, {auxiliary_function} as `body` FROM `notes_fts` WHERE notes_fts MATCH '{search_term}' ORDER BY bm25(notes_fts);");
-            Ok(sql_query(query).load(&mut conn)?)
-        } else {
-            // let body = highlight(notes_fts_table, 2, "<b>", "</b>");
-            Ok(notes_fts::table
-                .select((notes_fts::id, notes_fts::title, notes_fts::body))
-                .filter(diesel::dsl::sql::<diesel::sql_types::Bool>(&format!(
-                    "notes_fts MATCH '{search_term}' ORDER BY bm25(notes_fts)"
-                )))
-                .load(&mut conn)?)
-        }
+    /// Only the links from the notes are deleted, the links to them become broken links.
+    ///
+    /// Their resources stay in `note_resources`, no longer associated, until `delete_orphan_resources`.
+    fn delete_note_links(&mut self, note_ids: &[&str]) -> DatabaseResult<()> {
+        use crate::schema::{note_links, note_resources};
+        diesel::delete(note_links::table)
+            .filter(note_links::source_id.eq_any(note_ids))
+            .execute(self.conn)?;
+        diesel::update(note_resources::table)
+            .filter(note_resources::note_id.eq_any(note_ids))
+            .filter(note_resources::is_associated.eq(true))
+            .set((
+                note_resources::is_associated.eq(false),
+                note_resources::last_seen_time.eq(DateTimeTimestamp::now()),
+            ))
+            .execute(self.conn)?;
+        Ok(())
     }
-}
 
-impl Database {
-    fn replace_sync_item(
-        &self,
-        item_type: ModelType,
-        item_id: &str,
-        update_source: UpdateSource,
-    ) -> DatabaseResult<()> {
-        let mut conn = self.connection_pool.get()?;
-        use crate::schema::sync_items;
-        let sync_item: Option<SyncItem> = sync_items::table
-            .filter(sync_items::item_id.eq(item_id))
-            .select((
-                sync_items::id,
-                sync_items::sync_target,
-                sync_items::sync_time,
-                sync_items::update_time,
-                sync_items::item_type,
-                sync_items::item_id,
-            ))
-            .first(&mut conn)
-            .ok();
-        match sync_item {
-            Some(mut sync_item) => {
-                match update_source {
-                    UpdateSource::RemoteSync => sync_item.sync_time = DateTimeTimestamp::now(),
-                    UpdateSource::LocalEdit => {
-                        sync_item.update_time = DateTimeTimestamp::now();
-                        // workaround: When the program runs fast enough, update_time may equal sync_time. May not happen in a real environment
-                        if sync_item.update_time <= sync_item.sync_time {
-                            sync_item.update_time = DateTimeTimestamp::from_timestamp_millis(
-                                sync_item.sync_time.timestamp_millis() + 1,
-                            );
-                        }
-                    }
-                }
-                diesel::replace_into(sync_items::table)
-                    .values(&sync_item)
-                    .execute(&mut conn)?;
-            }
-            None => {
-                let sync_item = NewSyncItem::new(item_type, item_id, update_source);
-                diesel::insert_into(sync_items::table)
-                    .values(&sync_item)
-                    .execute(&mut conn)?;
-            }
-        };
+    /// Associates the resources of the `note_links` rows whose `column` is `id`, `source_id` for
+    /// the links of a note and `target_id` for the links to a resource that was just added.
+    fn associate_note_resources(&mut self, column: &str, id: &str) -> DatabaseResult<()> {
+        sql_query(format!(
+            "INSERT INTO `note_resources` (`note_id`, `resource_id`, `is_associated`, `last_seen_time`) \
+             SELECT `source_id`, `target_id`, TRUE, ? FROM `note_links` \
+             JOIN `resources` ON `resources`.`id` = `note_links`.`target_id` \
+             WHERE `note_links`.`{column}` = ? \
+             ON CONFLICT (`note_id`, `resource_id`) \
+             DO UPDATE SET `is_associated` = TRUE, `last_seen_time` = `excluded`.`last_seen_time`"
+        ))
+        .bind::<BigInt, _>(DateTimeTimestamp::now().timestamp_millis())
+        .bind::<Text, _>(id)
+        .execute(self.conn)?;
         Ok(())
     }
 
-    pub fn load_sync_item(&self, item_id: &str) -> DatabaseResult<SyncItem> {
-        let mut conn = self.connection_pool.get()?;
-        use crate::schema::sync_items;
-        Ok(sync_items::table
-            .filter(sync_items::item_id.eq(item_id))
-            .select((
-                sync_items::id,
-                sync_items::sync_target,
-                sync_items::sync_time,
-                sync_items::update_time,
-                sync_items::item_type,
-                sync_items::item_id,
-            ))
-            .first(&mut conn)?)
+    /// Every resource the note referenced since the database was opened, see `NoteResource::is_associated`.
+    pub fn load_note_resources(&mut self, note_id: &str) -> DatabaseResult<Vec<NoteResource>> {
+        use crate::schema::note_resources;
+        Ok(note_resources::table
+            .filter(note_resources::note_id.eq(note_id))
+            .order(note_resources::resource_id)
+            .load(self.conn)?)
     }
 
-    pub fn set_sync_item_up_to_data(&self, item_id: &str) -> DatabaseResult<()> {
-        let mut conn = self.connection_pool.get()?;
-        use crate::schema::sync_items;
-        diesel::update(sync_items::table)
-            .filter(sync_items::item_id.eq(item_id))
-            .set(sync_items::sync_time.eq(DateTimeTimestamp::now()))
-            .execute(&mut conn)?;
+    pub fn reindex_note_links(&mut self) -> DatabaseResult<()> {
+        use crate::schema::{note_links, notes};
+        diesel::delete(note_links::table).execute(self.conn)?;
+        let bodies: Vec<(String, String)> = notes::table
+            .select((notes::id, notes::body))
+            .load(self.conn)?;
+        for (id, body) in bodies.iter() {
+            self.update_note_links(id, body)?;
+        }
         Ok(())
     }
 
-    pub fn load_sync_items(&self, item_ids: &[&str]) -> DatabaseResult<Vec<SyncItem>> {
-        let mut conn = self.connection_pool.get()?;
-        use crate::schema::sync_items;
-        Ok(sync_items::table
+    /// The ids referenced by the body of the note, to notes, resources or missing items.
+    pub fn load_note_links(&mut self, note_id: &str) -> DatabaseResult<Vec<NoteLink>> {
+        use crate::schema::note_links;
+        Ok(note_links::table
+            .filter(note_links::source_id.eq(note_id))
+            .order(note_links::target_id)
+            .load(self.conn)?)
+    }
+
+    /// The notes linked from the body of the note.
+    pub fn load_linked_notes(&mut self, note_id: &str) -> DatabaseResult<Vec<AbbrNote>> {
+        use crate::schema::{note_links, notes};
+        Ok(notes::table
+            .select((
+                notes::id,
+                notes::parent_id,
+                notes::title,
+                notes::user_created_time,
+                notes::user_updated_time,
+            ))
+            .filter(
+                notes::id.eq_any(
+                    note_links::table
+                        .select(note_links::target_id)
+                        .filter(note_links::source_id.eq(note_id)),
+                ),
+            )
+            .order(notes::title.asc())
+            .load(self.conn)?)
+    }
+
+    /// The notes linking to the note or resource `id`, conflict notes are left out.
+    pub fn load_backlinks(&mut self, id: &str) -> DatabaseResult<Vec<AbbrNote>> {
+        use crate::schema::{note_links, notes};
+        Ok(notes::table
+            .select((
+                notes::id,
+                notes::parent_id,
+                notes::title,
+                notes::user_created_time,
+                notes::user_updated_time,
+            ))
+            .filter(notes::is_conflict.eq(false))
+            .filter(
+                notes::id.eq_any(
+                    note_links::table
+                        .select(note_links::source_id)
+                        .filter(note_links::target_id.eq(id)),
+                ),
+            )
+            .order(notes::user_updated_time.desc())
+            .load(self.conn)?)
+    }
+
+    /// Links to items that are neither a note nor a resource, e.g. deleted or not synced yet.
+    pub fn load_broken_links(&mut self) -> DatabaseResult<Vec<NoteLink>> {
+        use crate::schema::{note_links, notes, resources};
+        Ok(note_links::table
+            .filter(not(
+                note_links::target_id.eq_any(notes::table.select(notes::id))
+            ))
+            .filter(not(
+                note_links::target_id.eq_any(resources::table.select(resources::id))
+            ))
+            .order((note_links::source_id, note_links::target_id))
+            .load(self.conn)?)
+    }
+}
+
+impl Transaction<'_> {
+    /// Keeps the alarm of the note in line with the due date of the to-do.
+    fn update_note_alarm(&mut self, note_id: &str) -> DatabaseResult<()> {
+        use crate::schema::{alarms, notes};
+        let trigger_time: Option<DateTimeTimestamp> = notes::table
+            .select(notes::todo_due)
+            .filter(notes::id.eq(note_id))
+            .filter(notes::is_todo.eq(true))
+            .filter(notes::is_conflict.eq(false))
+            .filter(notes::todo_completed.eq(DateTimeTimestamp::zero()))
+            .filter(notes::todo_due.ne(DateTimeTimestamp::zero()))
+            .first(self.conn)
+            .optional()?;
+        let alarm: Option<Alarm> = alarms::table
+            .filter(alarms::note_id.eq(note_id))
+            .first(self.conn)
+            .optional()?;
+        match (alarm, trigger_time) {
+            // Keeps the fired time while the due date is unchanged.
+            (Some(alarm), Some(trigger_time)) if alarm.trigger_time == trigger_time => {}
+            (_, Some(trigger_time)) => {
+                diesel::replace_into(alarms::table)
+                    .values((
+                        alarms::note_id.eq(note_id),
+                        alarms::trigger_time.eq(trigger_time),
+                    ))
+                    .execute(self.conn)?;
+            }
+            (Some(_), None) => self.delete_note_alarms(&[note_id])?,
+            (None, None) => {}
+        }
+        Ok(())
+    }
+
+    fn delete_note_alarms(&mut self, note_ids: &[&str]) -> DatabaseResult<()> {
+        use crate::schema::alarms;
+        diesel::delete(alarms::table)
+            .filter(alarms::note_id.eq_any(note_ids))
+            .execute(self.conn)?;
+        Ok(())
+    }
+
+    pub fn load_alarms(&mut self) -> DatabaseResult<Vec<Alarm>> {
+        use crate::schema::alarms;
+        Ok(alarms::table
+            .order(alarms::trigger_time.asc())
+            .load(self.conn)?)
+    }
+
+    /// The earliest alarm not fired yet.
+    pub fn load_next_alarm(&mut self) -> DatabaseResult<Option<Alarm>> {
+        use crate::schema::alarms;
+        Ok(alarms::table
+            .filter(alarms::fired_time.eq(DateTimeTimestamp::zero()))
+            .order(alarms::trigger_time.asc())
+            .first(self.conn)
+            .optional()?)
+    }
+
+    /// Alarms not fired yet that are due at `time`.
+    pub fn load_due_alarms(&mut self, time: DateTimeTimestamp) -> DatabaseResult<Vec<Alarm>> {
+        use crate::schema::alarms;
+        Ok(alarms::table
+            .filter(alarms::fired_time.eq(DateTimeTimestamp::zero()))
+            .filter(alarms::trigger_time.le(time))
+            .order(alarms::trigger_time.asc())
+            .load(self.conn)?)
+    }
+
+    pub fn set_alarm_fired(&mut self, id: i64) -> DatabaseResult<()> {
+        use crate::schema::alarms;
+        diesel::update(alarms::table)
+            .filter(alarms::id.eq(id))
+            .set(alarms::fired_time.eq(DateTimeTimestamp::now()))
+            .execute(self.conn)?;
+        Ok(())
+    }
+}
+
+impl Database {
+    /// Every created, updated and deleted item is sent to the receiver, including sync writes.
+    pub fn subscribe_changes(&self) -> broadcast::Receiver<DatabaseChange> {
+        self.changes.subscribe()
+    }
+
+    pub fn rebuild_fts(&self) -> DatabaseResult<()> {
+        let mut conn = self.connection_pool.get()?;
+        for (_, table) in ITEMS_FTS_TABLES {
+            log::info!("rebuilding {table}");
+            sql_query(format!("INSERT INTO {table}({table}) VALUES('rebuild')"))
+                .execute(&mut conn)?;
+        }
+        Ok(())
+    }
+
+    pub fn tokenizer_config(&self) -> TokenizerConfig {
+        self.tokenizer.config.read().clone()
+    }
+
+    pub fn replace_tokenizer_config(&self, config: TokenizerConfig) -> DatabaseResult<()> {
+        if *self.tokenizer.config.read() == config {
+            return Ok(());
+        }
+        self.replace_setting(
+            Setting::FTS_TOKENIZER_CONFIG,
+            &serde_json::to_string(&config).expect("tokenizer config to_string error"),
+        )?;
+        *self.tokenizer.config.write() = config;
+        self.rebuild_fts()
+    }
+
+    pub fn load_user_dictionary_words(&self) -> DatabaseResult<Vec<UserDictionaryWord>> {
+        let mut conn = self.connection_pool.get()?;
+        use crate::schema::user_dictionary_words;
+        Ok(user_dictionary_words::table
+            .order(user_dictionary_words::word)
+            .load(&mut conn)?)
+    }
+
+    /// Replaces the word if it exists, then rebuilds `notes_fts` with the new dictionary.
+    pub fn replace_user_dictionary_word(&self, word: &UserDictionaryWord) -> DatabaseResult<()> {
+        let mut conn = self.connection_pool.get()?;
+        use crate::schema::user_dictionary_words;
+        diesel::replace_into(user_dictionary_words::table)
+            .values(word)
+            .execute(&mut conn)?;
+        self.reload_user_dictionary()
+    }
+
+    pub fn delete_user_dictionary_word(&self, word: &str) -> DatabaseResult<()> {
+        let mut conn = self.connection_pool.get()?;
+        use crate::schema::user_dictionary_words;
+        let deleted = diesel::delete(user_dictionary_words::table)
+            .filter(user_dictionary_words::word.eq(word))
+            .execute(&mut conn)?;
+        if deleted == 0 {
+            return Ok(());
+        }
+        self.reload_user_dictionary()
+    }
+
+    fn reload_user_dictionary(&self) -> DatabaseResult<()> {
+        let words = self.load_user_dictionary_words()?;
+        self.tokenizer.jieba.load_user_dictionary(&words);
+        self.rebuild_fts()
+    }
+
+    pub fn search_notes(
+        &self,
+        search_term: &str,
+        option: Option<SearchBodyOption>,
+    ) -> DatabaseResult<Vec<NoteFts>> {
+        let mut request = SearchNotesRequest::new(search_term);
+        request.body_option = option;
+        let response = self.search_notes_with_request(&request)?;
+        Ok(response
+            .notes
+            .into_iter()
+            .map(|note| NoteFts {
+                id: note.id,
+                title: note.title,
+                body: note.body,
+            })
+            .collect())
+    }
+
+    pub fn search_notes_with_request(
+        &self,
+        request: &SearchNotesRequest,
+    ) -> DatabaseResult<SearchNotesResponse> {
+        let response = self.match_notes(request)?;
+        if response.total_count > 0 || !request.fuzzy {
+            return Ok(response);
+        }
+        let search_term = match self.correct_search_term(&request.search_term)? {
+            Some(search_term) => search_term,
+            None => return Ok(response),
+        };
+        let fuzzy_request = SearchNotesRequest {
+            search_term: search_term.clone(),
+            fuzzy: false,
+            ..request.clone()
+        };
+        let mut response = self.match_notes(&fuzzy_request)?;
+        response.suggestions.push(search_term);
+        Ok(response)
+    }
+
+    /// Replaces the words missing from `notes_fts_vocab` with their closest terms.
+    fn correct_search_term(&self, search_term: &str) -> DatabaseResult<Option<String>> {
+        let mut conn = self.connection_pool.get()?;
+        let mut corrected = false;
+        let mut words = Vec::new();
+        for word in search_term.split_whitespace() {
+            let lowercase = match fuzzy_word(word) {
+                Some(lowercase) => lowercase,
+                None => {
+                    words.push(word.to_string());
+                    continue;
+                }
+            };
+            let len = lowercase.chars().count() as i64;
+            let max_distance = max_edit_distance(&lowercase) as i64;
+            let terms: Vec<VocabTerm> = sql_query(
+                "SELECT `term`, `doc` FROM `notes_fts_vocab` WHERE length(`term`) BETWEEN ? AND ?",
+            )
+            .bind::<BigInt, _>(len - max_distance)
+            .bind::<BigInt, _>(len + max_distance)
+            .load(&mut conn)?;
+            if terms.iter().any(|t| t.term == lowercase) {
+                words.push(word.to_string());
+                continue;
+            }
+            match best_correction(&lowercase, &terms) {
+                Some(term) => {
+                    corrected = true;
+                    words.push(term.to_string());
+                }
+                None => words.push(word.to_string()),
+            }
+        }
+        Ok(corrected.then(|| words.join(" ")))
+    }
+
+    /// Items are ordered by their score relative to the best match of the same type, since the
+    /// bm25 scores of different tables are not comparable: the best note, folder, tag and resource
+    /// all score 1.0 and the order between item types is only approximate.
+    pub fn search_items(&self, request: &SearchItemsRequest) -> DatabaseResult<Vec<SearchItem>> {
+        let mut conn = self.connection_pool.get()?;
+        let highlight = &request.highlight;
+        let mut query = sql_query("").into_boxed();
+        for (i, (item_type, table)) in ITEMS_FTS_TABLES.into_iter().enumerate() {
+            if i > 0 {
+                query = query.sql(" UNION ALL ");
+            }
+            // bm25() can't be used in a window function, `LIMIT -1` keeps the subquery from being flattened.
+            query = query
+                .sql(format!(
+                    "SELECT {} AS `item_type`, `id`, `title`, `snippet`, coalesce(`score` / nullif(min(`score`) OVER (), 0), 1.0) AS `score` FROM (SELECT `id`, highlight(`{table}`, 0, ?, ?) AS `title`, snippet(`{table}`, -1, ?, ?, ?, {SEARCH_ITEM_SNIPPET_TOKENS}) AS `snippet`, bm25(`{table}`) AS `score` FROM `{table}` WHERE `{table}` MATCH ? LIMIT -1)",
+                    item_type as i32
+                ))
+                .bind::<Text, _>(&highlight.open)
+                .bind::<Text, _>(&highlight.close)
+                .bind::<Text, _>(&highlight.open)
+                .bind::<Text, _>(&highlight.close)
+                .bind::<Text, _>(&highlight.ellipsis)
+                .bind::<Text, _>(&request.search_term);
+        }
+        Ok(query
+            .sql(" ORDER BY `score` DESC LIMIT ? OFFSET ?")
+            .bind::<BigInt, _>(request.limit.unwrap_or(-1))
+            .bind::<BigInt, _>(request.offset)
+            .load(&mut conn)?)
+    }
+
+    fn match_notes(&self, request: &SearchNotesRequest) -> DatabaseResult<SearchNotesResponse> {
+        let mut conn = self.connection_pool.get()?;
+        let highlight = &request.highlight;
+        let total_count = if request.include_resources {
+            sql_query("SELECT COUNT(*) AS `count` FROM (SELECT `rowid` FROM `notes_fts` WHERE `notes_fts` MATCH ? UNION SELECT `notes`.`rowid` FROM `resources_fts` JOIN `note_links` ON `note_links`.`target_id` = `resources_fts`.`id` JOIN `notes` ON `notes`.`id` = `note_links`.`source_id` WHERE `resources_fts` MATCH ?)")
+                .bind::<Text, _>(&request.search_term)
+                .bind::<Text, _>(&request.search_term)
+                .get_result::<SearchCount>(&mut conn)?
+                .count
+        } else {
+            sql_query("SELECT COUNT(*) AS `count` FROM `notes_fts` WHERE `notes_fts` MATCH ?")
+                .bind::<Text, _>(&request.search_term)
+                .get_result::<SearchCount>(&mut conn)?
+                .count
+        };
+        let mut query = match request.body_option {
+            Some(_) => {
+                sql_query("SELECT `notes_fts`.`id`, highlight(`notes_fts`, 0, ?, ?) AS `title`, ")
+                    .into_boxed()
+                    .bind::<Text, _>(&highlight.open)
+                    .bind::<Text, _>(&highlight.close)
+            }
+            None => sql_query("SELECT `notes_fts`.`id`, `notes_fts`.`title`, ").into_boxed(),
+        };
+        query = match request.body_option {
+            Some(SearchBodyOption::Highlight) => query
+                .sql("highlight(`notes_fts`, 1, ?, ?) AS `body`, ")
+                .bind::<Text, _>(&highlight.open)
+                .bind::<Text, _>(&highlight.close),
+            Some(SearchBodyOption::Snippet { max_tokens }) => {
+                let max_tokens = max_tokens.clamp(1, 64);
+                query
+                    .sql(format!(
+                        "snippet(`notes_fts`, 1, ?, ?, ?, {max_tokens}) AS `body`, "
+                    ))
+                    .bind::<Text, _>(&highlight.open)
+                    .bind::<Text, _>(&highlight.close)
+                    .bind::<Text, _>(&highlight.ellipsis)
+            }
+            None => query.sql("`notes_fts`.`body`, "),
+        };
+        let ranking = &request.ranking;
+        query = query
+            .sql("`notes`.`parent_id`, `notes`.`user_updated_time`, bm25(`notes_fts`, ?, ?)")
+            .bind::<Double, _>(ranking.title_weight)
+            .bind::<Double, _>(ranking.body_weight);
+        query = rank_factors(query, ranking)
+            .sql(" AS `score` FROM `notes_fts` JOIN `notes` ON `notes`.`rowid` = `notes_fts`.`rowid` WHERE `notes_fts` MATCH ?")
+            .bind::<Text, _>(&request.search_term);
+        if request.include_resources {
+            // The term is not in the body, a snippet comes from the best matching resource instead.
+            let body = match request.body_option {
+                Some(SearchBodyOption::Snippet { .. }) => "`matched`.`snippet`",
+                _ => "`notes`.`body`",
+            };
+            query = query.sql(format!(" UNION ALL SELECT `notes`.`id`, `notes`.`title`, {body}, `notes`.`parent_id`, `notes`.`user_updated_time`, min(`matched`.`score`)"));
+            query = rank_factors(query, ranking).sql(" AS `score` FROM (SELECT `id`, ");
+            query = match request.body_option {
+                Some(SearchBodyOption::Snippet { max_tokens }) => {
+                    let max_tokens = max_tokens.clamp(1, 64);
+                    query
+                        .sql(format!(
+                            "snippet(`resources_fts`, -1, ?, ?, ?, {max_tokens}) AS `snippet`, "
+                        ))
+                        .bind::<Text, _>(&highlight.open)
+                        .bind::<Text, _>(&highlight.close)
+                        .bind::<Text, _>(&highlight.ellipsis)
+                }
+                _ => query.sql("'' AS `snippet`, "),
+            };
+            // bm25() can't be used in an aggregate, `LIMIT -1` keeps the subquery from being flattened.
+            // The bare `snippet` comes from the row of min(`score`).
+            query = query
+                .sql(" bm25(`resources_fts`) AS `score` FROM `resources_fts` WHERE `resources_fts` MATCH ? LIMIT -1) AS `matched` JOIN `note_links` ON `note_links`.`target_id` = `matched`.`id` JOIN `notes` ON `notes`.`id` = `note_links`.`source_id` WHERE `notes`.`rowid` NOT IN (SELECT `rowid` FROM `notes_fts` WHERE `notes_fts` MATCH ?) GROUP BY `notes`.`id`")
+                .bind::<Text, _>(&request.search_term)
+                .bind::<Text, _>(&request.search_term);
+        }
+        let notes = query
+            .sql(" ORDER BY `score` LIMIT ? OFFSET ?")
+            .bind::<BigInt, _>(request.limit.unwrap_or(-1))
+            .bind::<BigInt, _>(request.offset)
+            .load(&mut conn)?;
+        Ok(SearchNotesResponse {
+            total_count,
+            notes,
+            suggestions: Vec::new(),
+        })
+    }
+}
+
+/// Multiplies the bm25 score selected last, scores are negative so smaller factors demote a note.
+fn rank_factors<'a>(
+    mut query: BoxedSqlQuery<'a, Sqlite, SqlQuery>,
+    ranking: &SearchRanking,
+) -> BoxedSqlQuery<'a, Sqlite, SqlQuery> {
+    if let Some(days) = ranking.recency_days {
+        let days = days.max(f64::MIN_POSITIVE);
+        query = query
+            .sql(" * (1.0 + ? / (? + max(0, strftime('%s', 'now') * 1000 - `notes`.`user_updated_time`) / 86400000.0))")
+            .bind::<Double, _>(days)
+            .bind::<Double, _>(days);
+    }
+    if ranking.demote_conflicts {
+        query = query.sql(format!(
+            " * CASE WHEN `notes`.`is_conflict` THEN {DEMOTION_FACTOR:?} ELSE 1.0 END"
+        ));
+    }
+    if ranking.demote_completed_todos {
+        query = query.sql(format!(
+            " * CASE WHEN `notes`.`is_todo` AND `notes`.`todo_completed` THEN {DEMOTION_FACTOR:?} ELSE 1.0 END"
+        ));
+    }
+    query
+}
+
+impl Transaction<'_> {
+    fn replace_sync_item(
+        &mut self,
+        item_type: ModelType,
+        item_id: &str,
+        update_source: UpdateSource,
+    ) -> DatabaseResult<()> {
+        use crate::schema::sync_items;
+        let sync_item: Option<SyncItem> = sync_items::table
+            .filter(sync_items::item_id.eq(item_id))
+            .select((
+                sync_items::id,
+                sync_items::sync_target,
+                sync_items::sync_time,
+                sync_items::update_time,
+                sync_items::item_type,
+                sync_items::item_id,
+            ))
+            .first(self.conn)
+            .ok();
+        let kind = match sync_item {
+            Some(mut sync_item) => {
+                match update_source {
+                    UpdateSource::RemoteSync => sync_item.sync_time = DateTimeTimestamp::now(),
+                    UpdateSource::LocalEdit => {
+                        sync_item.update_time = DateTimeTimestamp::now();
+                        // workaround: When the program runs fast enough, update_time may equal sync_time. May not happen in a real environment
+                        if sync_item.update_time <= sync_item.sync_time {
+                            sync_item.update_time = DateTimeTimestamp::from_timestamp_millis(
+                                sync_item.sync_time.timestamp_millis() + 1,
+                            );
+                        }
+                    }
+                }
+                diesel::replace_into(sync_items::table)
+                    .values(&sync_item)
+                    .execute(self.conn)?;
+                ChangeKind::Updated
+            }
+            None => {
+                let sync_item = NewSyncItem::new(item_type, item_id, update_source);
+                diesel::insert_into(sync_items::table)
+                    .values(&sync_item)
+                    .execute(self.conn)?;
+                ChangeKind::Created
+            }
+        };
+        self.record_changes(item_type, &[item_id], kind, update_source)
+    }
+
+    /// Appends the changes to `item_changes`, subscribers get them once the transaction is committed.
+    fn record_changes(
+        &mut self,
+        item_type: ModelType,
+        item_ids: &[&str],
+        kind: ChangeKind,
+        update_source: UpdateSource,
+    ) -> DatabaseResult<()> {
+        if item_ids.is_empty() {
+            return Ok(());
+        }
+        use crate::schema::item_changes;
+        let item_changes = NewItemChange::new_items(item_type, item_ids, kind, update_source);
+        diesel::insert_into(item_changes::table)
+            .values(&item_changes)
+            .execute(self.conn)?;
+        self.changes
+            .extend(item_ids.iter().map(|item_id| DatabaseChange {
+                item_type,
+                item_id: item_id.to_string(),
+                kind,
+                update_source,
+            }));
+        Ok(())
+    }
+
+    /// Changes recorded after `counter`, oldest first. Pass `0` to read from the start.
+    pub fn load_item_changes_since(
+        &mut self,
+        counter: i64,
+        limit: Option<i64>,
+    ) -> DatabaseResult<Vec<ItemChange>> {
+        use crate::schema::item_changes;
+        Ok(item_changes::table
+            .filter(item_changes::id.gt(counter))
+            .order(item_changes::id)
+            .limit(limit.unwrap_or(-1))
+            .load(self.conn)?)
+    }
+
+    /// Counter of the latest change, `0` when nothing has changed yet.
+    pub fn latest_item_change_counter(&mut self) -> DatabaseResult<i64> {
+        use crate::schema::item_changes;
+        Ok(item_changes::table
+            .select(diesel::dsl::max(item_changes::id))
+            .first::<Option<i64>>(self.conn)?
+            .unwrap_or(0))
+    }
+
+    /// Deletes the changes up to and including `counter`, e.g. once every consumer has processed them.
+    pub fn delete_item_changes_until(&mut self, counter: i64) -> DatabaseResult<()> {
+        use crate::schema::item_changes;
+        diesel::delete(item_changes::table)
+            .filter(item_changes::id.le(counter))
+            .execute(self.conn)?;
+        Ok(())
+    }
+
+    pub fn load_sync_item(&mut self, item_id: &str) -> DatabaseResult<SyncItem> {
+        use crate::schema::sync_items;
+        Ok(sync_items::table
+            .filter(sync_items::item_id.eq(item_id))
+            .select((
+                sync_items::id,
+                sync_items::sync_target,
+                sync_items::sync_time,
+                sync_items::update_time,
+                sync_items::item_type,
+                sync_items::item_id,
+            ))
+            .first(self.conn)?)
+    }
+
+    pub fn set_sync_item_up_to_data(&mut self, item_id: &str) -> DatabaseResult<()> {
+        use crate::schema::sync_items;
+        diesel::update(sync_items::table)
+            .filter(sync_items::item_id.eq(item_id))
+            .set(sync_items::sync_time.eq(DateTimeTimestamp::now()))
+            .execute(self.conn)?;
+        Ok(())
+    }
+
+    pub fn load_sync_items(&mut self, item_ids: &[&str]) -> DatabaseResult<Vec<SyncItem>> {
+        use crate::schema::sync_items;
+        Ok(sync_items::table
             .filter(sync_items::item_id.eq_any(item_ids))
             .select((
                 sync_items::id,
@@ -519,11 +1453,10 @@ impl Database {
                 sync_items::item_type,
                 sync_items::item_id,
             ))
-            .load(&mut conn)?)
+            .load(self.conn)?)
     }
 
-    pub fn load_all_sync_items(&self) -> DatabaseResult<Vec<SyncItem>> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn load_all_sync_items(&mut self) -> DatabaseResult<Vec<SyncItem>> {
         use crate::schema::sync_items;
         Ok(sync_items::table
             .select((
@@ -534,11 +1467,10 @@ impl Database {
                 sync_items::item_type,
                 sync_items::item_id,
             ))
-            .load(&mut conn)?)
+            .load(self.conn)?)
     }
 
-    pub fn load_need_upload_sync_items(&self) -> DatabaseResult<Vec<SyncItem>> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn load_need_upload_sync_items(&mut self) -> DatabaseResult<Vec<SyncItem>> {
         use crate::schema::sync_items;
         Ok(sync_items::table
             .filter(sync_items::sync_time.lt(sync_items::update_time))
@@ -550,11 +1482,11 @@ impl Database {
                 sync_items::item_type,
                 sync_items::item_id,
             ))
-            .load(&mut conn)?)
+            .load(self.conn)?)
     }
 
     pub fn load_sync_item_content(
-        &self,
+        &mut self,
         sync_item: &SyncItem,
     ) -> DatabaseResult<ForSyncSerializer> {
         match sync_item.item_type {
@@ -568,66 +1500,70 @@ impl Database {
                 .load_resource(&sync_item.item_id)
                 .map(|x| x.serialize()),
             ModelType::Tag => self.load_tag(&sync_item.item_id).map(|x| x.serialize()),
+            ModelType::SmartFilter => self
+                .load_smart_filter(&sync_item.item_id)
+                .map(|x| x.serialize()),
             ModelType::NoteTag => self
                 .load_note_tag(&sync_item.item_id)
                 .map(|x| x.serialize()),
-            ModelType::Unsupported => {
+            ModelType::Alarm
+            | ModelType::NoteResource
+            | ModelType::ItemChange
+            | ModelType::Unsupported => {
                 panic!("cannot load unsupported type");
             }
         }
     }
 
-    pub fn delete_sync_item(&self, item_id: &str) -> DatabaseResult<()> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn delete_sync_item(&mut self, item_id: &str) -> DatabaseResult<()> {
         use crate::schema::sync_items;
         diesel::delete(sync_items::table)
             .filter(sync_items::item_id.eq(item_id))
-            .execute(&mut conn)?;
+            .execute(self.conn)?;
         Ok(())
     }
 
-    pub fn delete_sync_items(&self, item_id: &[&str]) -> DatabaseResult<()> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn delete_sync_items(&mut self, item_id: &[&str]) -> DatabaseResult<()> {
         use crate::schema::sync_items;
         diesel::delete(sync_items::table)
             .filter(sync_items::item_id.eq_any(item_id))
-            .execute(&mut conn)?;
+            .execute(self.conn)?;
         Ok(())
     }
 }
 
-impl Database {
-    fn insert_deleted_item(&self, item_type: ModelType, item_id: &str) -> DatabaseResult<()> {
-        let mut conn = self.connection_pool.get()?;
+impl Transaction<'_> {
+    fn insert_deleted_item(&mut self, item_type: ModelType, item_id: &str) -> DatabaseResult<()> {
         use crate::schema::deleted_items;
         let deleted_item = NewDeletedItem::new(item_type, item_id);
         diesel::insert_into(deleted_items::table)
             .values(&deleted_item)
-            .execute(&mut conn)?;
+            .execute(self.conn)?;
         Ok(())
     }
 
-    fn insert_deleted_items(&self, item_type: ModelType, item_ids: &[&str]) -> DatabaseResult<()> {
-        let mut conn = self.connection_pool.get()?;
+    fn insert_deleted_items(
+        &mut self,
+        item_type: ModelType,
+        item_ids: &[&str],
+    ) -> DatabaseResult<()> {
         use crate::schema::deleted_items;
         let deleted_items = NewDeletedItem::new_items(item_type, item_ids);
         diesel::insert_into(deleted_items::table)
             .values(&deleted_items)
-            .execute(&mut conn)?;
+            .execute(self.conn)?;
         Ok(())
     }
 
-    pub fn delete_deleted_item(&self, deleted_item: DeletedItem) -> DatabaseResult<()> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn delete_deleted_item(&mut self, deleted_item: DeletedItem) -> DatabaseResult<()> {
         use crate::schema::deleted_items;
         diesel::delete(deleted_items::table)
             .filter(deleted_items::id.eq(deleted_item.id))
-            .execute(&mut conn)?;
+            .execute(self.conn)?;
         Ok(())
     }
 
-    pub fn load_deleted_items(&self) -> DatabaseResult<Vec<DeletedItem>> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn load_deleted_items(&mut self) -> DatabaseResult<Vec<DeletedItem>> {
         use crate::schema::deleted_items;
         Ok(deleted_items::table
             .select((
@@ -636,41 +1572,38 @@ impl Database {
                 deleted_items::item_id,
                 deleted_items::deleted_time,
             ))
-            .load(&mut conn)?)
+            .load(self.conn)?)
     }
 }
 
-impl Database {
-    pub fn get_setting_value(&self, key: &str) -> DatabaseResult<Option<Setting>> {
-        let mut conn = self.connection_pool.get()?;
+impl Transaction<'_> {
+    pub fn get_setting_value(&mut self, key: &str) -> DatabaseResult<Option<Setting>> {
         use crate::schema::settings;
         Ok(settings::table
             .filter(settings::key.eq(key))
             .select((settings::key, settings::value))
-            .first(&mut conn)
+            .first(self.conn)
             .optional()?)
     }
 
-    pub fn replace_setting(&self, key: &str, value: &str) -> DatabaseResult<()> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn replace_setting(&mut self, key: &str, value: &str) -> DatabaseResult<()> {
         use crate::schema::settings;
         let new_setting = NewSetting::new(key, value);
         diesel::replace_into(settings::table)
             .values(&new_setting)
-            .execute(&mut conn)?;
+            .execute(self.conn)?;
         Ok(())
     }
 
-    pub fn delete_setting(&self, key: &str) -> DatabaseResult<()> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn delete_setting(&mut self, key: &str) -> DatabaseResult<()> {
         use crate::schema::settings;
         diesel::delete(settings::table)
             .filter(settings::key.eq(key))
-            .execute(&mut conn)?;
+            .execute(self.conn)?;
         Ok(())
     }
 
-    pub fn get_client_id(&self) -> DatabaseResult<String> {
+    pub fn get_client_id(&mut self) -> DatabaseResult<String> {
         let setting = self.get_setting_value(Setting::CLIENT_ID)?;
         match setting {
             Some(s) => Ok(s.value),
@@ -683,98 +1616,424 @@ impl Database {
     }
 }
 
-impl Database {
-    pub fn load_tag(&self, id: &str) -> DatabaseResult<Tag> {
-        let mut conn = self.connection_pool.get()?;
+impl Transaction<'_> {
+    pub fn load_tag(&mut self, id: &str) -> DatabaseResult<Tag> {
         use crate::schema::tags;
         Ok(tags::table
             .filter(tags::id.eq(id))
             .select(Tag::SELECTION)
-            .first(&mut conn)?)
+            .first(self.conn)?)
     }
 
-    pub fn load_all_tags(&self) -> DatabaseResult<Vec<Tag>> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn load_all_tags(&mut self) -> DatabaseResult<Vec<Tag>> {
         use crate::schema::tags;
-        Ok(tags::table.select(Tag::SELECTION).load(&mut conn)?)
+        Ok(tags::table.select(Tag::SELECTION).load(self.conn)?)
     }
 
-    pub fn load_tags(&self, ids: &[&str]) -> DatabaseResult<Vec<Tag>> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn load_tags(&mut self, ids: &[&str]) -> DatabaseResult<Vec<Tag>> {
         use crate::schema::tags;
         Ok(tags::table
             .filter(tags::id.eq_any(ids))
             .select(Tag::SELECTION)
-            .load(&mut conn)?)
+            .load(self.conn)?)
     }
 
-    pub fn replace_tag(&self, tag: &Tag, update_source: UpdateSource) -> DatabaseResult<()> {
+    /// Local edits fail with `DatabaseError::DuplicateTag` when a sibling has the same title, ignoring case.
+    ///
+    /// Titles are only unique among siblings. Synced tags are stored as they are, the duplicates are
+    /// merged by `merge_duplicate_tags` once the sync has pulled their note tags.
+    pub fn replace_tag(&mut self, tag: &Tag, update_source: UpdateSource) -> DatabaseResult<()> {
+        if update_source.is_local_edit() {
+            let parent_id = tag.parent_id.as_deref().filter(|id| !id.is_empty());
+            if let Some(existing) = self.find_sibling_tag(parent_id, &tag.title, Some(&tag.id))? {
+                return Err(DatabaseError::DuplicateTag(existing.title));
+            }
+        }
         let tag = match update_source {
             UpdateSource::RemoteSync => tag.clone(),
             UpdateSource::LocalEdit => tag.updated(),
         };
-        let mut conn = self.connection_pool.get()?;
         use crate::schema::tags;
         diesel::replace_into(tags::table)
             .values(&tag)
-            .execute(&mut conn)?;
+            .execute(self.conn)?;
         self.replace_sync_item(ModelType::Tag, tag.id.as_str(), update_source)?;
         Ok(())
     }
 
-    pub fn delete_tag(&self, id: &str, update_source: UpdateSource) -> DatabaseResult<()> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn delete_tag(&mut self, id: &str, update_source: UpdateSource) -> DatabaseResult<()> {
         use crate::schema::tags;
         self.delete_sync_item(id)?;
         diesel::delete(tags::table)
             .filter(tags::id.eq(id))
-            .execute(&mut conn)?;
+            .execute(self.conn)?;
         if update_source.is_local_edit() {
             self.insert_deleted_item(ModelType::Tag, id)?;
         }
+        self.record_changes(ModelType::Tag, &[id], ChangeKind::Deleted, update_source)?;
         Ok(())
     }
 
-    pub fn tag_count(&self) -> DatabaseResult<i64> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn tag_count(&mut self) -> DatabaseResult<i64> {
         use crate::schema::tags;
-        Ok(tags::table.count().get_result(&mut conn)?)
+        Ok(tags::table.count().get_result(self.conn)?)
+    }
+
+    /// Finds the tag at `path`, e.g. `project/alpha`, creating the missing ones. Titles are matched case-insensitively.
+    pub fn insert_tag_path(&mut self, path: &str) -> DatabaseResult<Tag> {
+        let titles: Vec<&str> = path
+            .split(TAG_PATH_SEPARATOR)
+            .map(str::trim)
+            .filter(|title| !title.is_empty())
+            .collect();
+        if titles.is_empty() {
+            return Err(DatabaseError::InvalidTagPath(path.to_string()));
+        }
+        let mut parent: Option<Tag> = None;
+        for title in titles {
+            let parent_id = parent.as_ref().map(|tag| tag.id.clone());
+            let tag = match self.find_sibling_tag(parent_id.as_deref(), title, None)? {
+                Some(tag) => tag,
+                None => {
+                    let tag = Tag::new_with_parent(title, parent_id);
+                    self.replace_tag(&tag, UpdateSource::LocalEdit)?;
+                    tag
+                }
+            };
+            parent = Some(tag);
+        }
+        Ok(parent.expect("tag path is not empty"))
     }
 
-    pub fn load_note_tag(&self, id: &str) -> DatabaseResult<NoteTag> {
-        let mut conn = self.connection_pool.get()?;
+    fn load_child_tags(&mut self, parent_id: Option<&str>) -> DatabaseResult<Vec<Tag>> {
+        use crate::schema::tags;
+        let query = tags::table.select(Tag::SELECTION).into_boxed();
+        Ok(match parent_id {
+            Some(parent_id) => query.filter(tags::parent_id.eq(parent_id)),
+            // Joplin writes an empty `parent_id` for root tags.
+            None => query.filter(tags::parent_id.is_null().or(tags::parent_id.eq(""))),
+        }
+        .load(self.conn)?)
+    }
+
+    /// The tag under `parent_id` titled `title`, ignoring case, other than `except_id`.
+    fn find_sibling_tag(
+        &mut self,
+        parent_id: Option<&str>,
+        title: &str,
+        except_id: Option<&str>,
+    ) -> DatabaseResult<Option<Tag>> {
+        let lower_title = title.to_lowercase();
+        Ok(self.load_child_tags(parent_id)?.into_iter().find(|tag| {
+            Some(tag.id.as_str()) != except_id && tag.title.to_lowercase() == lower_title
+        }))
+    }
+
+    /// The titles from the root tag down to `id`, joined by `TAG_PATH_SEPARATOR`.
+    pub fn load_tag_path(&mut self, id: &str) -> DatabaseResult<String> {
+        let mut titles = Vec::new();
+        let mut visited = HashSet::new();
+        let mut current_id = Some(id.to_string());
+        while let Some(id) = current_id.filter(|id| !id.is_empty()) {
+            if !visited.insert(id.clone()) {
+                break;
+            }
+            let tag = if titles.is_empty() {
+                self.load_tag(&id)?
+            } else {
+                // A parent that is not synced yet ends the path.
+                match self.load_tags(&[&id])?.pop() {
+                    Some(tag) => tag,
+                    None => break,
+                }
+            };
+            titles.push(tag.title);
+            current_id = tag.parent_id;
+        }
+        titles.reverse();
+        Ok(titles.join(&TAG_PATH_SEPARATOR.to_string()))
+    }
+
+    pub fn load_tag_tree(&mut self) -> DatabaseResult<Vec<TagNode>> {
+        Ok(TagNode::build_tree(self.load_all_tags()?))
+    }
+
+    /// The ids of the tags nested under `id`, at any depth, not including `id`.
+    fn load_descendant_tag_ids(&mut self, id: &str) -> DatabaseResult<Vec<String>> {
+        let mut children: HashMap<String, Vec<String>> = HashMap::new();
+        for tag in self.load_all_tags()? {
+            if let Some(parent_id) = tag.parent_id {
+                children.entry(parent_id).or_default().push(tag.id);
+            }
+        }
+        let mut visited = HashSet::from([id.to_string()]);
+        let mut queue = VecDeque::from([id.to_string()]);
+        let mut descendants = Vec::new();
+        while let Some(current_id) = queue.pop_front() {
+            for child_id in children.remove(&current_id).unwrap_or_default() {
+                if visited.insert(child_id.clone()) {
+                    descendants.push(child_id.clone());
+                    queue.push_back(child_id);
+                }
+            }
+        }
+        Ok(descendants)
+    }
+
+    /// Notes tagged with `tag_id`, and with any tag nested under it when `include_descendants` is set.
+    pub fn load_abbr_notes_with_tag(
+        &mut self,
+        tag_id: &str,
+        include_descendants: bool,
+    ) -> DatabaseResult<Vec<AbbrNote>> {
+        use crate::schema::{note_tags, notes};
+        let mut tag_ids = vec![tag_id.to_string()];
+        if include_descendants {
+            tag_ids.extend(self.load_descendant_tag_ids(tag_id)?);
+        }
+        Ok(notes::table
+            .select((
+                notes::id,
+                notes::parent_id,
+                notes::title,
+                notes::user_created_time,
+                notes::user_updated_time,
+            ))
+            .filter(notes::is_conflict.eq(false))
+            .filter(
+                notes::id.eq_any(
+                    note_tags::table
+                        .select(note_tags::note_id)
+                        .filter(note_tags::tag_id.eq_any(tag_ids)),
+                ),
+            )
+            .order(notes::user_updated_time.desc())
+            .load(self.conn)?)
+    }
+
+    /// Moves the tag under `parent_id`, or to the root when it is `None`.
+    pub fn move_tag(&mut self, id: &str, parent_id: Option<&str>) -> DatabaseResult<()> {
+        use crate::schema::tags;
+        let tag = self.load_tag(id)?;
+        if tag.parent_id.as_deref() == parent_id {
+            return Ok(());
+        }
+        if let Some(parent_id) = parent_id {
+            self.load_tag(parent_id)?;
+            if parent_id == id
+                || self
+                    .load_descendant_tag_ids(id)?
+                    .iter()
+                    .any(|x| x == parent_id)
+            {
+                return Err(DatabaseError::TagCycle);
+            }
+        }
+        if let Some(existing) = self.find_sibling_tag(parent_id, &tag.title, Some(id))? {
+            return Err(DatabaseError::DuplicateTag(existing.title));
+        }
+        let dt = DateTimeTimestamp::now();
+        diesel::update(tags::table)
+            .filter(tags::id.eq(id))
+            .set((
+                tags::parent_id.eq(parent_id),
+                tags::updated_time.eq(dt),
+                tags::user_updated_time.eq(dt),
+            ))
+            .execute(self.conn)?;
+        self.replace_sync_item(ModelType::Tag, id, UpdateSource::LocalEdit)?;
+        Ok(())
+    }
+
+    /// Renames the tag, or merges it into the sibling already titled `title`, ignoring case. Returns the remaining tag.
+    ///
+    /// The title is trimmed, it fails with `DatabaseError::InvalidTagPath` when it is empty or
+    /// contains `TAG_PATH_SEPARATOR`.
+    pub fn rename_tag(&mut self, id: &str, title: &str) -> DatabaseResult<Tag> {
+        use crate::schema::tags;
+        let title = title.trim();
+        if title.is_empty() || title.contains(TAG_PATH_SEPARATOR) {
+            return Err(DatabaseError::InvalidTagPath(title.to_string()));
+        }
+        let tag = self.load_tag(id)?;
+        let parent_id = tag.parent_id.as_deref().filter(|id| !id.is_empty());
+        if let Some(existing) = self.find_sibling_tag(parent_id, title, Some(id))? {
+            self.merge_tags(&[id], &existing.id)?;
+            return self.load_tag(&existing.id);
+        }
+        let dt = DateTimeTimestamp::now();
+        diesel::update(tags::table)
+            .filter(tags::id.eq(id))
+            .set((
+                tags::title.eq(title),
+                tags::updated_time.eq(dt),
+                tags::user_updated_time.eq(dt),
+            ))
+            .execute(self.conn)?;
+        self.replace_sync_item(ModelType::Tag, id, UpdateSource::LocalEdit)?;
+        self.load_tag(id)
+    }
+
+    /// Moves the notes and the nested tags of `sources` to `target`, then deletes `sources`.
+    ///
+    /// A note tagged with both keeps a single `NoteTag`, nested tags with the same title are merged too.
+    pub fn merge_tags(&mut self, sources: &[&str], target: &str) -> DatabaseResult<()> {
+        use crate::schema::note_tags;
+        self.load_tag(target)?;
+        let mut sources = sources.to_vec();
+        sources.sort_unstable();
+        sources.dedup();
+        sources.retain(|id| *id != target);
+        for source in sources.iter() {
+            if self
+                .load_descendant_tag_ids(source)?
+                .iter()
+                .any(|id| id == target)
+            {
+                return Err(DatabaseError::TagCycle);
+            }
+        }
+        for source in sources {
+            let mut tagged_note_ids: HashSet<String> = note_tags::table
+                .filter(note_tags::tag_id.eq(target))
+                .select(note_tags::note_id)
+                .load::<String>(self.conn)?
+                .into_iter()
+                .collect();
+            let source_note_tags: Vec<NoteTag> = note_tags::table
+                .filter(note_tags::tag_id.eq(source))
+                .select(NoteTag::SELECTION)
+                .load(self.conn)?;
+            let mut duplicate_ids = Vec::new();
+            for mut note_tag in source_note_tags {
+                if tagged_note_ids.insert(note_tag.note_id.clone()) {
+                    note_tag.tag_id = target.to_string();
+                    self.replace_note_tag(&note_tag, UpdateSource::LocalEdit)?;
+                } else {
+                    duplicate_ids.push(note_tag.id);
+                }
+            }
+            self.delete_note_tags(
+                &duplicate_ids.iter().map(String::as_str).collect::<Vec<_>>(),
+                UpdateSource::LocalEdit,
+            )?;
+            for child in self.load_child_tags(Some(source))? {
+                match self.find_sibling_tag(Some(target), &child.title, Some(&child.id))? {
+                    Some(existing) => self.merge_tags(&[&child.id], &existing.id)?,
+                    None => self.move_tag(&child.id, Some(target))?,
+                }
+            }
+            self.delete_tag(source, UpdateSource::LocalEdit)?;
+        }
+        Ok(())
+    }
+
+    /// Merges the sibling tags whose titles only differ in case, e.g. created on two devices, into
+    /// the oldest one so every device keeps the same tag. Returns how many tags were merged, nested
+    /// ones included.
+    ///
+    /// `Synchronizer` runs it after pulling the remote changes, the merges are uploaded by the next sync.
+    pub fn merge_duplicate_tags(&mut self) -> DatabaseResult<usize> {
+        let tag_count = self.tag_count()?;
+        loop {
+            let mut groups: HashMap<(&str, String), Vec<&Tag>> = HashMap::new();
+            let tags = self.load_all_tags()?;
+            for tag in tags.iter() {
+                let parent_id = tag.parent_id.as_deref().unwrap_or_default();
+                groups
+                    .entry((parent_id, tag.title.to_lowercase()))
+                    .or_default()
+                    .push(tag);
+            }
+            // Merging nested tags can change the other groups, so they are computed again.
+            let mut duplicates = match groups.into_values().find(|group| group.len() > 1) {
+                Some(duplicates) => duplicates,
+                None => return Ok((tag_count - self.tag_count()?) as usize),
+            };
+            duplicates.sort_by(|a, b| (a.created_time, &a.id).cmp(&(b.created_time, &b.id)));
+            let sources: Vec<&str> = duplicates[1..].iter().map(|tag| tag.id.as_str()).collect();
+            self.merge_tags(&sources, &duplicates[0].id)?;
+        }
+    }
+
+    /// Deletes the tags that neither tag a note nor have a nested tag that does, returns how many were deleted.
+    ///
+    /// Their `note_tags` rows left by deleted notes are deleted too.
+    pub fn delete_unused_tags(&mut self) -> DatabaseResult<usize> {
+        use crate::schema::{note_tags, notes};
+        let tags = self.load_all_tags()?;
+        let parent_ids: HashMap<&str, &str> = tags
+            .iter()
+            .filter_map(|tag| Some((tag.id.as_str(), tag.parent_id.as_deref()?)))
+            .collect();
+        let used_tag_ids: Vec<String> = note_tags::table
+            .filter(note_tags::note_id.eq_any(notes::table.select(notes::id)))
+            .select(note_tags::tag_id)
+            .distinct()
+            .load(self.conn)?;
+        let mut used = HashSet::new();
+        for tag_id in used_tag_ids.iter() {
+            let mut current_id = Some(tag_id.as_str());
+            while let Some(id) = current_id {
+                if !used.insert(id) {
+                    break;
+                }
+                current_id = parent_ids.get(id).copied();
+            }
+        }
+        let unused_ids: Vec<&str> = tags
+            .iter()
+            .map(|tag| tag.id.as_str())
+            .filter(|id| !used.contains(id))
+            .collect();
+        let note_tag_ids: Vec<String> = note_tags::table
+            .filter(note_tags::tag_id.eq_any(&unused_ids))
+            .select(note_tags::id)
+            .load(self.conn)?;
+        self.delete_note_tags(
+            &note_tag_ids.iter().map(String::as_str).collect::<Vec<_>>(),
+            UpdateSource::LocalEdit,
+        )?;
+        for id in unused_ids.iter() {
+            self.delete_tag(id, UpdateSource::LocalEdit)?;
+        }
+        Ok(unused_ids.len())
+    }
+
+    pub fn load_note_tag(&mut self, id: &str) -> DatabaseResult<NoteTag> {
         use crate::schema::note_tags;
         Ok(note_tags::table
             .filter(note_tags::id.eq(id))
             .select(NoteTag::SELECTION)
-            .first(&mut conn)?)
+            .first(self.conn)?)
     }
 
-    pub fn load_all_note_tags(&self) -> DatabaseResult<Vec<NoteTag>> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn load_all_note_tags(&mut self) -> DatabaseResult<Vec<NoteTag>> {
         use crate::schema::note_tags;
         Ok(note_tags::table
             .select(NoteTag::SELECTION)
-            .load(&mut conn)?)
+            .load(self.conn)?)
     }
 
-    pub fn load_note_tag_on_note(&self, note_id: &str, tag_id: &str) -> DatabaseResult<NoteTag> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn load_note_tag_on_note(
+        &mut self,
+        note_id: &str,
+        tag_id: &str,
+    ) -> DatabaseResult<NoteTag> {
         use crate::schema::note_tags;
         Ok(note_tags::table
             .filter(note_tags::note_id.eq(note_id))
             .filter(note_tags::tag_id.eq(tag_id))
             .select(NoteTag::SELECTION)
-            .first(&mut conn)?)
+            .first(self.conn)?)
     }
 
-    pub fn get_note_tags(&self, note_id: &str) -> DatabaseResult<Vec<Tag>> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn get_note_tags(&mut self, note_id: &str) -> DatabaseResult<Vec<Tag>> {
         use crate::schema::note_tags;
         let note_tags: Vec<NoteTag> = note_tags::table
             .filter(note_tags::note_id.eq(note_id))
             .select(NoteTag::SELECTION)
-            .load(&mut conn)?;
+            .load(self.conn)?;
         self.load_tags(
             &note_tags
                 .iter()
@@ -783,14 +2042,14 @@ impl Database {
         )
     }
 
-    pub fn add_tag_on_note(&self, note_id: &str, tag_id: &str) -> DatabaseResult<()> {
+    pub fn add_tag_on_note(&mut self, note_id: &str, tag_id: &str) -> DatabaseResult<()> {
         let note_tag = NoteTag::new(note_id, tag_id);
         self.replace_note_tag(&note_tag, UpdateSource::LocalEdit)?;
         Ok(())
     }
 
     pub fn replace_note_tag(
-        &self,
+        &mut self,
         note_tag: &NoteTag,
         update_source: UpdateSource,
     ) -> DatabaseResult<()> {
@@ -798,30 +2057,34 @@ impl Database {
             UpdateSource::RemoteSync => note_tag.clone(),
             UpdateSource::LocalEdit => note_tag.updated(),
         };
-        let mut conn = self.connection_pool.get()?;
         use crate::schema::note_tags;
         diesel::replace_into(note_tags::table)
             .values(&note_tag)
-            .execute(&mut conn)?;
+            .execute(self.conn)?;
         self.replace_sync_item(ModelType::NoteTag, note_tag.id.as_str(), update_source)?;
         Ok(())
     }
 
-    pub fn delete_note_tag(&self, id: &str, update_source: UpdateSource) -> DatabaseResult<()> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn delete_note_tag(&mut self, id: &str, update_source: UpdateSource) -> DatabaseResult<()> {
         use crate::schema::note_tags;
         self.delete_sync_item(id)?;
         diesel::delete(note_tags::table)
             .filter(note_tags::id.eq(id))
-            .execute(&mut conn)?;
+            .execute(self.conn)?;
         if update_source.is_local_edit() {
             self.insert_deleted_item(ModelType::NoteTag, id)?;
         }
+        self.record_changes(
+            ModelType::NoteTag,
+            &[id],
+            ChangeKind::Deleted,
+            update_source,
+        )?;
         Ok(())
     }
 
     pub fn delete_note_tag_by_note_id_and_tag_id(
-        &self,
+        &mut self,
         note_id: &str,
         tag_id: &str,
     ) -> DatabaseResult<()> {
@@ -831,33 +2094,32 @@ impl Database {
     }
 
     pub fn delete_note_tags(
-        &self,
+        &mut self,
         ids: &[&str],
         update_source: UpdateSource,
     ) -> DatabaseResult<()> {
-        let mut conn = self.connection_pool.get()?;
         use crate::schema::note_tags;
         self.delete_sync_items(ids)?;
         diesel::delete(note_tags::table)
             .filter(note_tags::id.eq_any(ids))
-            .execute(&mut conn)?;
+            .execute(self.conn)?;
         if update_source.is_local_edit() {
             self.insert_deleted_items(ModelType::NoteTag, ids)?;
         }
+        self.record_changes(ModelType::NoteTag, ids, ChangeKind::Deleted, update_source)?;
         Ok(())
     }
 
     pub fn delete_note_tags_by_note_id(
-        &self,
+        &mut self,
         note_id: &str,
         update_source: UpdateSource,
     ) -> DatabaseResult<()> {
-        let mut conn = self.connection_pool.get()?;
         use crate::schema::note_tags;
         let tag_ids: Vec<NoteTagId> = note_tags::table
             .filter(note_tags::note_id.eq(note_id))
             .select((note_tags::id,))
-            .load(&mut conn)?;
+            .load(self.conn)?;
         self.delete_note_tags(
             &tag_ids.iter().map(|x| x.id.as_str()).collect::<Vec<&str>>(),
             update_source,
@@ -866,16 +2128,15 @@ impl Database {
     }
 
     pub fn delete_note_tag_by_note_ids(
-        &self,
+        &mut self,
         note_id: &[&str],
         update_source: UpdateSource,
     ) -> DatabaseResult<()> {
-        let mut conn = self.connection_pool.get()?;
         use crate::schema::note_tags;
         let tag_ids: Vec<NoteTagId> = note_tags::table
             .filter(note_tags::note_id.eq_any(note_id))
             .select((note_tags::id,))
-            .load(&mut conn)?;
+            .load(self.conn)?;
         self.delete_note_tags(
             &tag_ids.iter().map(|x| x.id.as_str()).collect::<Vec<&str>>(),
             update_source,
@@ -883,16 +2144,238 @@ impl Database {
         Ok(())
     }
 
-    pub fn note_tag_count(&self) -> DatabaseResult<i64> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn note_tag_count(&mut self) -> DatabaseResult<i64> {
         use crate::schema::note_tags;
-        Ok(note_tags::table.count().get_result(&mut conn)?)
+        Ok(note_tags::table.count().get_result(self.conn)?)
     }
 }
 
-impl Database {
-    pub fn load_resource(&self, id: &str) -> DatabaseResult<Resource> {
-        let mut conn = self.connection_pool.get()?;
+impl Transaction<'_> {
+    pub fn insert_smart_filter(&mut self, title: &str, query: &str) -> DatabaseResult<SmartFilter> {
+        let smart_filter = SmartFilter::new(title, query);
+        self.replace_smart_filter(&smart_filter, UpdateSource::LocalEdit)?;
+        Ok(smart_filter)
+    }
+
+    /// Local edits fail with `DatabaseError::InvalidSmartFilter` when the query can't be parsed.
+    pub fn replace_smart_filter(
+        &mut self,
+        smart_filter: &SmartFilter,
+        update_source: UpdateSource,
+    ) -> DatabaseResult<()> {
+        let smart_filter = match update_source {
+            UpdateSource::RemoteSync => smart_filter.clone(),
+            UpdateSource::LocalEdit => {
+                SmartFilterQuery::parse(&smart_filter.query)?;
+                smart_filter.updated()
+            }
+        };
+        use crate::schema::smart_filters;
+        diesel::replace_into(smart_filters::table)
+            .values(&smart_filter)
+            .execute(self.conn)?;
+        self.replace_sync_item(
+            ModelType::SmartFilter,
+            smart_filter.id.as_str(),
+            update_source,
+        )?;
+        Ok(())
+    }
+
+    pub fn load_smart_filter(&mut self, id: &str) -> DatabaseResult<SmartFilter> {
+        use crate::schema::smart_filters;
+        Ok(smart_filters::table
+            .filter(smart_filters::id.eq(id))
+            .select(SmartFilter::SELECTION)
+            .first(self.conn)?)
+    }
+
+    pub fn load_smart_filters(&mut self) -> DatabaseResult<Vec<SmartFilter>> {
+        use crate::schema::smart_filters;
+        Ok(smart_filters::table
+            .select(SmartFilter::SELECTION)
+            .order(smart_filters::title.asc())
+            .load(self.conn)?)
+    }
+
+    pub fn delete_smart_filter(
+        &mut self,
+        id: &str,
+        update_source: UpdateSource,
+    ) -> DatabaseResult<()> {
+        use crate::schema::smart_filters;
+        self.delete_sync_item(id)?;
+        diesel::delete(smart_filters::table)
+            .filter(smart_filters::id.eq(id))
+            .execute(self.conn)?;
+        if update_source.is_local_edit() {
+            self.insert_deleted_item(ModelType::SmartFilter, id)?;
+        }
+        self.record_changes(
+            ModelType::SmartFilter,
+            &[id],
+            ChangeKind::Deleted,
+            update_source,
+        )?;
+        Ok(())
+    }
+
+    pub fn evaluate_smart_filter(&mut self, id: &str) -> DatabaseResult<Vec<NoteListItem>> {
+        let smart_filter = self.load_smart_filter(id)?;
+        self.evaluate_smart_filter_query(&smart_filter.query)
+    }
+
+    /// The notes matching `query`, most recently updated first, conflict notes are left out.
+    pub fn evaluate_smart_filter_query(
+        &mut self,
+        query: &str,
+    ) -> DatabaseResult<Vec<NoteListItem>> {
+        use crate::schema::{note_tags, notes};
+        let query = SmartFilterQuery::parse(query)?;
+        let mut notes_query = notes::table
+            .select((
+                notes::id,
+                notes::parent_id,
+                notes::title,
+                notes::user_created_time,
+                notes::user_updated_time,
+                notes::is_todo,
+                notes::todo_due,
+                notes::todo_completed,
+                sql::<Nullable<Text>>("NULL"),
+            ))
+            .filter(notes::is_conflict.eq(false))
+            .order((notes::user_updated_time.desc(), notes::id))
+            .into_boxed();
+        if !query.text.is_empty() {
+            notes_query = notes_query.filter(
+                sql::<Bool>(
+                    "`notes`.`rowid` IN (SELECT `rowid` FROM `notes_fts` WHERE `notes_fts` MATCH ",
+                )
+                .bind::<Text, _>(query.text.clone())
+                .sql(")"),
+            );
+        }
+        if !query.excluded_text.is_empty() {
+            notes_query = notes_query.filter(
+                sql::<Bool>(
+                    "`notes`.`rowid` NOT IN (SELECT `rowid` FROM `notes_fts` WHERE `notes_fts` MATCH ",
+                )
+                .bind::<Text, _>(query.excluded_text.clone())
+                .sql(")"),
+            );
+        }
+        for tag in query.tags.iter() {
+            let tag_ids = self.load_tag_ids_by_filter(tag)?;
+            notes_query = notes_query.filter(
+                notes::id.eq_any(
+                    note_tags::table
+                        .select(note_tags::note_id)
+                        .filter(note_tags::tag_id.eq_any(tag_ids)),
+                ),
+            );
+        }
+        for tag in query.excluded_tags.iter() {
+            let tag_ids = self.load_tag_ids_by_filter(tag)?;
+            notes_query = notes_query.filter(not(notes::id.eq_any(
+                note_tags::table
+                    .select(note_tags::note_id)
+                    .filter(note_tags::tag_id.eq_any(tag_ids)),
+            )));
+        }
+        if !query.notebooks.is_empty() {
+            let folder_ids = self.load_folder_ids_by_titles(&query.notebooks)?;
+            notes_query = notes_query.filter(notes::parent_id.eq_any(folder_ids));
+        }
+        if let Some(start) = query.created.start {
+            notes_query = notes_query.filter(notes::user_created_time.ge(start));
+        }
+        if let Some(end) = query.created.end {
+            notes_query = notes_query.filter(notes::user_created_time.lt(end));
+        }
+        if let Some(start) = query.updated.start {
+            notes_query = notes_query.filter(notes::user_updated_time.ge(start));
+        }
+        if let Some(end) = query.updated.end {
+            notes_query = notes_query.filter(notes::user_updated_time.lt(end));
+        }
+        if !query.due.is_empty() {
+            notes_query = notes_query
+                .filter(notes::is_todo.eq(true))
+                .filter(notes::todo_due.ne(DateTimeTimestamp::zero()));
+            if let Some(start) = query.due.start {
+                notes_query = notes_query.filter(notes::todo_due.ge(start));
+            }
+            if let Some(end) = query.due.end {
+                notes_query = notes_query.filter(notes::todo_due.lt(end));
+            }
+        }
+        if let Some(is_todo) = query.is_todo {
+            notes_query = notes_query.filter(notes::is_todo.eq(is_todo));
+        }
+        if let Some(is_completed) = query.is_completed {
+            notes_query = notes_query.filter(notes::is_todo.eq(true));
+            notes_query = if is_completed {
+                notes_query.filter(notes::todo_completed.ne(DateTimeTimestamp::zero()))
+            } else {
+                notes_query.filter(notes::todo_completed.eq(DateTimeTimestamp::zero()))
+            };
+        }
+        Ok(notes_query.load(self.conn)?)
+    }
+
+    /// The tags titled `filter`, or at the path `filter`, and the tags nested under them.
+    fn load_tag_ids_by_filter(&mut self, filter: &str) -> DatabaseResult<Vec<String>> {
+        let mut tag_ids = Vec::new();
+        if filter.contains(TAG_PATH_SEPARATOR) {
+            let mut parent_id: Option<String> = None;
+            for title in filter.split(TAG_PATH_SEPARATOR).map(str::trim) {
+                match self.find_sibling_tag(parent_id.as_deref(), title, None)? {
+                    Some(tag) => parent_id = Some(tag.id),
+                    None => return Ok(Vec::new()),
+                }
+            }
+            tag_ids.extend(parent_id);
+        } else {
+            let lower_filter = filter.to_lowercase();
+            tag_ids.extend(
+                self.load_all_tags()?
+                    .into_iter()
+                    .filter(|tag| tag.title.to_lowercase() == lower_filter)
+                    .map(|tag| tag.id),
+            );
+        }
+        for id in tag_ids.clone() {
+            tag_ids.extend(self.load_descendant_tag_ids(&id)?);
+        }
+        Ok(tag_ids)
+    }
+
+    /// The folders titled one of `titles`, ignoring case, and their subfolders.
+    fn load_folder_ids_by_titles(&mut self, titles: &[String]) -> DatabaseResult<Vec<String>> {
+        let lower_titles: HashSet<String> = titles.iter().map(|x| x.to_lowercase()).collect();
+        let folders = self.load_folders()?;
+        let mut folder_ids: HashSet<&str> = folders
+            .iter()
+            .filter(|folder| lower_titles.contains(&folder.title.to_lowercase()))
+            .map(|folder| folder.id.as_str())
+            .collect();
+        let mut queue: VecDeque<&str> = folder_ids.iter().copied().collect();
+        while let Some(parent_id) = queue.pop_front() {
+            for folder in folders.iter() {
+                if folder.parent_id.as_deref() == Some(parent_id)
+                    && folder_ids.insert(folder.id.as_str())
+                {
+                    queue.push_back(folder.id.as_str());
+                }
+            }
+        }
+        Ok(folder_ids.into_iter().map(str::to_string).collect())
+    }
+}
+
+impl Transaction<'_> {
+    pub fn load_resource(&mut self, id: &str) -> DatabaseResult<Resource> {
         use crate::schema::resources;
         Ok(resources::table
             .filter(resources::id.eq(id))
@@ -914,15 +2397,16 @@ impl Database {
                 resources::share_id,
                 resources::master_key_id,
             ))
-            .first(&mut conn)?)
+            .first(self.conn)?)
     }
 
     pub fn replace_resource(
-        &self,
+        &mut self,
         resource: &Resource,
         update_source: UpdateSource,
     ) -> DatabaseResult<()> {
         let resource_file = self
+            .db
             .resource_path
             .join(&resource.id)
             .with_extension(&resource.file_extension);
@@ -933,37 +2417,308 @@ impl Database {
                 resource.updated()
             }
         };
-        let mut conn = self.connection_pool.get()?;
-        use crate::schema::resources;
+        use crate::schema::{resource_hashes, resources};
         diesel::replace_into(resources::table)
             .values(&resource)
-            .execute(&mut conn)?;
+            .execute(self.conn)?;
+        if !update_source.is_local_edit() {
+            // The file may have been replaced, it is hashed again when imported.
+            diesel::delete(resource_hashes::table)
+                .filter(resource_hashes::resource_id.eq(&resource.id))
+                .execute(self.conn)?;
+        }
+        self.index_resource_text(&resource)?;
+        // The notes may have been synced before the resource.
+        self.associate_note_resources("target_id", &resource.id)?;
         self.replace_sync_item(ModelType::Resource, resource.id.as_str(), update_source)?;
         Ok(())
     }
 
-    pub fn delete_resource(&self, id: &str, update_source: UpdateSource) -> DatabaseResult<()> {
-        let mut conn = self.connection_pool.get()?;
+    /// Stores the imported file as a new resource, or returns the resource with the same content.
+    fn insert_imported_file(
+        &mut self,
+        file: ImportedFile,
+        file_name: &str,
+    ) -> DatabaseResult<ImportedResource> {
+        use crate::schema::{resource_hashes, resources};
+        let same_content: Vec<Resource> = resources::table
+            .filter(
+                resources::id.eq_any(
+                    resource_hashes::table
+                        .select(resource_hashes::resource_id)
+                        .filter(resource_hashes::hash.eq(&file.hash)),
+                ),
+            )
+            .order(resources::created_time.asc())
+            .load(self.conn)?;
+        let existing = same_content
+            .into_iter()
+            .find(|resource| resource.resource_file_path(&self.db.resource_path).exists());
+        if let Some(resource) = existing {
+            return Ok(ImportedResource {
+                markdown_link: resource.markdown_link(),
+                resource,
+                reused: true,
+            });
+        }
+        let resource = Resource::new(
+            file_name,
+            file.mime.as_str(),
+            file.file_extension.as_str(),
+            resource_size(file.size),
+        );
+        let hash = file.hash.clone();
+        file.persist(&resource.resource_file_path(&self.db.resource_path))?;
+        self.replace_resource(&resource, UpdateSource::LocalEdit)?;
+        diesel::replace_into(resource_hashes::table)
+            .values((
+                resource_hashes::resource_id.eq(&resource.id),
+                resource_hashes::hash.eq(hash),
+            ))
+            .execute(self.conn)?;
+        let resource = self.load_resource(&resource.id)?;
+        Ok(ImportedResource {
+            markdown_link: resource.markdown_link(),
+            resource,
+            reused: false,
+        })
+    }
+
+    /// Stores the text of text based resources in `resources_fts`, a missing file is skipped.
+    fn index_resource_text(&mut self, resource: &Resource) -> DatabaseResult<()> {
+        let path = resource.resource_file_path(&self.db.resource_path);
+        let text = match extract_text(&resource.mime, &resource.file_extension, &path) {
+            Ok(Some(text)) => text,
+            Ok(None) => return Ok(()),
+            Err(e) => {
+                log::warn!("failed to extract text of resource {}: {}", resource.id, e);
+                return Ok(());
+            }
+        };
+        sql_query("UPDATE `resources_fts` SET `content` = ? WHERE `id` = ?")
+            .bind::<Text, _>(text)
+            .bind::<Text, _>(&resource.id)
+            .execute(self.conn)?;
+        Ok(())
+    }
+
+    pub fn reindex_resource_texts(&mut self) -> DatabaseResult<()> {
         use crate::schema::resources;
+        let resources: Vec<Resource> = resources::table.load(self.conn)?;
+        for resource in resources.iter() {
+            self.index_resource_text(resource)?;
+        }
+        Ok(())
+    }
+
+    pub fn delete_resource(&mut self, id: &str, update_source: UpdateSource) -> DatabaseResult<()> {
+        use crate::schema::{note_resources, resource_hashes, resources};
         self.delete_sync_item(id)?;
         diesel::delete(resources::table)
             .filter(resources::id.eq(id))
-            .execute(&mut conn)?;
+            .execute(self.conn)?;
+        diesel::delete(resource_hashes::table)
+            .filter(resource_hashes::resource_id.eq(id))
+            .execute(self.conn)?;
+        diesel::delete(note_resources::table)
+            .filter(note_resources::resource_id.eq(id))
+            .execute(self.conn)?;
+        if let Err(e) = remove_thumbnails(&self.db.resource_path, id, None) {
+            log::warn!("failed to remove the thumbnails of resource {}: {}", id, e);
+        }
         if update_source.is_local_edit() {
             self.insert_deleted_item(ModelType::Resource, id)?;
         }
+        self.record_changes(
+            ModelType::Resource,
+            &[id],
+            ChangeKind::Deleted,
+            update_source,
+        )?;
         Ok(())
     }
 
-    pub fn resource_count(&self) -> DatabaseResult<i64> {
-        let mut conn = self.connection_pool.get()?;
+    pub fn resource_count(&mut self) -> DatabaseResult<i64> {
         use crate::schema::resources;
-        Ok(resources::table.count().get_result(&mut conn)?)
+        Ok(resources::table.count().get_result(self.conn)?)
+    }
+
+    /// Resources that no note has referenced for `grace_period`, oldest first.
+    ///
+    /// Like in Joplin, a resource that was never referenced is kept, e.g. when its note is not synced yet.
+    pub fn load_orphan_resources(
+        &mut self,
+        grace_period: Duration,
+    ) -> DatabaseResult<Vec<Resource>> {
+        use crate::schema::{note_resources, resources};
+        let cutoff = grace_cutoff(grace_period);
+        Ok(resources::table
+            .filter(resources::id.eq_any(note_resources::table.select(note_resources::resource_id)))
+            .filter(not(resources::id.eq_any(
+                note_resources::table
+                    .select(note_resources::resource_id)
+                    .filter(
+                        note_resources::is_associated
+                            .eq(true)
+                            .or(note_resources::last_seen_time.ge(cutoff)),
+                    ),
+            )))
+            .order(resources::updated_time.asc())
+            .load(self.conn)?)
+    }
+}
+
+impl Database {
+    /// Deletes the orphan resources with their files, then the files of the resource directory
+    /// that have no resource and were not modified for `grace_period`.
+    ///
+    /// Files are only removed once the deletions are committed, the ones that fail are logged and
+    /// left for the next run.
+    pub fn delete_orphan_resources(
+        &self,
+        grace_period: Duration,
+    ) -> DatabaseResult<ResourceGcReport> {
+        let (deleted_resources, resource_ids) = self.transaction(|tx| {
+            use crate::schema::resources;
+            let orphans = tx.load_orphan_resources(grace_period)?;
+            for resource in orphans.iter() {
+                tx.delete_resource(&resource.id, UpdateSource::LocalEdit)?;
+            }
+            let resource_ids: HashSet<String> = resources::table
+                .select(resources::id)
+                .load::<String>(tx.conn)?
+                .into_iter()
+                .collect();
+            Ok((orphans, resource_ids))
+        })?;
+        let mut report = ResourceGcReport::default();
+        for resource in deleted_resources {
+            let path = resource.resource_file_path(&self.resource_path);
+            match remove_file(&path) {
+                Ok(size) => report.reclaimed_bytes += size,
+                Err(e) => log::warn!("failed to remove {}: {}", path.display(), e),
+            }
+            report.deleted_resources.push(resource);
+        }
+        let entries = match fs::read_dir(&self.resource_path) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(report),
+            Err(e) => return Err(e.into()),
+        };
+        let cutoff = SystemTime::now()
+            .checked_sub(grace_period)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+        for entry in entries {
+            let path = match entry {
+                Ok(entry) => entry.path(),
+                Err(e) => {
+                    log::warn!("failed to read {}: {}", self.resource_path.display(), e);
+                    continue;
+                }
+            };
+            match resource_id_of_file(&path) {
+                Some(id) if !resource_ids.contains(id) => {}
+                _ => continue,
+            }
+            let is_stale = fs::metadata(&path)
+                .and_then(|metadata| Ok(metadata.is_file() && metadata.modified()? <= cutoff));
+            match is_stale {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(e) => {
+                    log::warn!("failed to read {}: {}", path.display(), e);
+                    continue;
+                }
+            }
+            match remove_file(&path) {
+                Ok(size) => {
+                    report.reclaimed_bytes += size;
+                    report.deleted_files.push(path);
+                }
+                Err(e) => log::warn!("failed to remove {}: {}", path.display(), e),
+            }
+        }
+        Ok(report)
     }
 }
 
 impl Database {
-    pub fn status(&self) -> DatabaseResult<Status> {
+    /// Copies the file into the resource directory, the name of the file is the title of the resource.
+    ///
+    /// The resource with the same content is reused, see `ImportedResource::reused`.
+    pub fn import_resource(&self, path: &Path) -> DatabaseResult<ImportedResource> {
+        let file_name = path
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .unwrap_or_default()
+            .to_string();
+        self.import_resource_from(fs::File::open(path)?, &file_name)
+    }
+
+    /// Like `import_resource`, `file_name` gives the title and the extension.
+    pub fn import_resource_bytes(
+        &self,
+        bytes: &[u8],
+        file_name: &str,
+    ) -> DatabaseResult<ImportedResource> {
+        self.import_resource_from(bytes, file_name)
+    }
+
+    /// The file is copied before the transaction, so writers are not blocked while it is written.
+    fn import_resource_from(
+        &self,
+        reader: impl io::Read,
+        file_name: &str,
+    ) -> DatabaseResult<ImportedResource> {
+        let file = ImportedFile::write(reader, file_name, &self.resource_path)?;
+        self.hash_resources_of_size(file.size)?;
+        self.transaction(|tx| tx.insert_imported_file(file, file_name))
+    }
+
+    /// Stores the hash of the resources of `size` bytes that have none, e.g. the synced ones,
+    /// so an import can reuse them. Files not downloaded yet are skipped.
+    fn hash_resources_of_size(&self, size: u64) -> DatabaseResult<()> {
+        use crate::schema::{resource_hashes, resources};
+        let mut conn = self.connection_pool.get()?;
+        let unhashed: Vec<Resource> = resources::table
+            .filter(resources::size.eq(resource_size(size)))
+            .filter(resources::encryption_blob_encrypted.eq(false))
+            .filter(not(resources::id.eq_any(
+                resource_hashes::table.select(resource_hashes::resource_id),
+            )))
+            .load(&mut conn)?;
+        for resource in unhashed {
+            let hash = match hash_file(&resource.resource_file_path(&self.resource_path)) {
+                Ok(hash) => hash,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            diesel::insert_or_ignore_into(resource_hashes::table)
+                .values((
+                    resource_hashes::resource_id.eq(&resource.id),
+                    resource_hashes::hash.eq(hash),
+                ))
+                .execute(&mut conn)?;
+        }
+        Ok(())
+    }
+}
+
+fn resource_size(size: u64) -> i32 {
+    i32::try_from(size).unwrap_or(i32::MAX)
+}
+
+fn grace_cutoff(grace_period: Duration) -> DateTimeTimestamp {
+    let millis = i64::try_from(grace_period.as_millis()).unwrap_or(i64::MAX);
+    DateTimeTimestamp::from_timestamp_millis(
+        DateTimeTimestamp::now()
+            .timestamp_millis()
+            .saturating_sub(millis),
+    )
+}
+
+impl Transaction<'_> {
+    pub fn status(&mut self) -> DatabaseResult<Status> {
         Ok(Status {
             note_count: self.note_count()?,
             folder_count: self.folder_count()?,
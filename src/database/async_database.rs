@@ -0,0 +1,206 @@
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use tokio::sync::broadcast;
+
+use crate::{
+    sync::ForSyncSerializer, AbbrNote, Alarm, DateTimeTimestamp, DeletedItem, Folder, ItemChange,
+    Note, NoteFts, NoteLink, NoteResource, NoteTag, Placement, Resource, Setting, SmartFilter,
+    Status, SyncItem, Tag, TagNode, UserDictionaryWord,
+};
+
+use super::{
+    Database, DatabaseChange, DatabaseError, DatabaseResult, ImportedResource,
+    MarkdownExportReport, NoteListItem, NoteListRequest, NoteListResponse, ResourceGcReport,
+    SearchBodyOption, SearchItem, SearchItemsRequest, SearchNotesRequest, SearchNotesResponse,
+    TodoFilter, TokenizerConfig, Transaction, UpdateSource,
+};
+
+/// Runs `Database` calls on the tokio blocking pool, so async callers don't block the runtime threads.
+#[derive(Debug, Clone)]
+pub struct AsyncDatabase(Arc<Database>);
+
+impl AsyncDatabase {
+    pub fn new(db: Arc<Database>) -> Self {
+        Self(db)
+    }
+
+    pub fn database(&self) -> &Arc<Database> {
+        &self.0
+    }
+
+    /// Runs `f` on the blocking pool, a panic in `f` is resumed in the caller.
+    pub async fn run<T, F>(&self, f: F) -> DatabaseResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Database) -> DatabaseResult<T> + Send + 'static,
+    {
+        let db = self.0.clone();
+        match tokio::task::spawn_blocking(move || f(&db)).await {
+            Ok(result) => result,
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(_) => Err(DatabaseError::Cancelled),
+        }
+    }
+
+    /// See `Database::transaction`.
+    pub async fn transaction<T, F>(&self, f: F) -> DatabaseResult<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Transaction) -> DatabaseResult<T> + Send + 'static,
+    {
+        self.run(move |db| db.transaction(f)).await
+    }
+
+    pub fn subscribe_changes(&self) -> broadcast::Receiver<DatabaseChange> {
+        self.0.subscribe_changes()
+    }
+
+    pub fn tokenizer_config(&self) -> TokenizerConfig {
+        self.0.tokenizer_config()
+    }
+}
+
+impl From<Arc<Database>> for AsyncDatabase {
+    fn from(db: Arc<Database>) -> Self {
+        Self::new(db)
+    }
+}
+
+fn as_strs(ids: &[String]) -> Vec<&str> {
+    ids.iter().map(String::as_str).collect()
+}
+
+/// Declares async methods that take owned arguments and run `$body` on the blocking pool.
+macro_rules! run_blocking {
+    ($(pub async fn $name:ident(&self $(, $arg:ident: $ty:ty)*) -> $ret:ty => |$db:ident| $body:expr;)*) => {
+        $(
+            pub async fn $name(&self $(, $arg: $ty)*) -> $ret {
+                self.run(move |$db| $body).await
+            }
+        )*
+    };
+}
+
+impl AsyncDatabase {
+    run_blocking! {
+        pub async fn rebuild_fts(&self) -> DatabaseResult<()> => |db| db.rebuild_fts();
+        pub async fn replace_tokenizer_config(&self, config: TokenizerConfig) -> DatabaseResult<()> => |db| db.replace_tokenizer_config(config);
+        pub async fn load_user_dictionary_words(&self) -> DatabaseResult<Vec<UserDictionaryWord>> => |db| db.load_user_dictionary_words();
+        pub async fn replace_user_dictionary_word(&self, word: UserDictionaryWord) -> DatabaseResult<()> => |db| db.replace_user_dictionary_word(&word);
+        pub async fn delete_user_dictionary_word(&self, word: String) -> DatabaseResult<()> => |db| db.delete_user_dictionary_word(&word);
+        pub async fn search_notes(&self, search_term: String, option: Option<SearchBodyOption>) -> DatabaseResult<Vec<NoteFts>> => |db| db.search_notes(&search_term, option);
+        pub async fn search_notes_with_request(&self, request: SearchNotesRequest) -> DatabaseResult<SearchNotesResponse> => |db| db.search_notes_with_request(&request);
+        pub async fn search_items(&self, request: SearchItemsRequest) -> DatabaseResult<Vec<SearchItem>> => |db| db.search_items(&request);
+
+        pub async fn insert_root_folder(&self, title: String) -> DatabaseResult<Folder> => |db| db.insert_root_folder(title);
+        pub async fn insert_folder_with_parent(&self, title: String, parent_id: String) -> DatabaseResult<Folder> => |db| db.insert_folder_with_parent(title, parent_id);
+        pub async fn replace_folder(&self, folder: Folder, update_source: UpdateSource) -> DatabaseResult<()> => |db| db.replace_folder(&folder, update_source);
+        pub async fn load_folders(&self) -> DatabaseResult<Vec<Folder>> => |db| db.load_folders();
+        pub async fn load_folder(&self, id: String) -> DatabaseResult<Folder> => |db| db.load_folder(&id);
+        pub async fn load_subfolders(&self, id: String) -> DatabaseResult<Vec<Folder>> => |db| db.load_subfolders(&id);
+        pub async fn move_folder(&self, id: String, parent_id: Option<String>) -> DatabaseResult<()> => |db| db.move_folder(&id, parent_id.as_deref());
+        pub async fn delete_folder(&self, id: String, update_source: UpdateSource) -> DatabaseResult<()> => |db| db.delete_folder(&id, update_source);
+        pub async fn folder_count(&self) -> DatabaseResult<i64> => |db| db.folder_count();
+
+        pub async fn load_abbr_notes(&self, parent_id: Option<String>) -> DatabaseResult<Vec<AbbrNote>> => |db| db.load_abbr_notes(parent_id.as_deref());
+        pub async fn load_note_list(&self, request: NoteListRequest) -> DatabaseResult<NoteListResponse> => |db| db.load_note_list(&request);
+        pub async fn load_todos(&self, filter: TodoFilter) -> DatabaseResult<Vec<NoteListItem>> => |db| db.load_todos(filter);
+        pub async fn load_abbr_conflict_notes(&self) -> DatabaseResult<Vec<AbbrNote>> => |db| db.load_abbr_conflict_notes();
+        pub async fn conflict_note_exists(&self) -> DatabaseResult<bool> => |db| db.conflict_note_exists();
+        pub async fn load_note(&self, id: String) -> DatabaseResult<Note> => |db| db.load_note(&id);
+        pub async fn insert_note_with_parent(&self, title: String, body: String, parent_id: String) -> DatabaseResult<Note> => |db| db.insert_note_with_parent(title, body, parent_id);
+        pub async fn replace_note(&self, note: Note, update_source: UpdateSource) -> DatabaseResult<()> => |db| db.replace_note(&note, update_source);
+        pub async fn update_note_body(&self, id: String, body: String) -> DatabaseResult<()> => |db| db.update_note_body(&id, &body);
+        pub async fn update_note_title(&self, id: String, title: String) -> DatabaseResult<()> => |db| db.update_note_title(&id, &title);
+        pub async fn set_note_todo(&self, id: String, is_todo: bool) -> DatabaseResult<()> => |db| db.set_note_todo(&id, is_todo);
+        pub async fn complete_todo(&self, id: String, completed: bool) -> DatabaseResult<()> => |db| db.complete_todo(&id, completed);
+        pub async fn set_todo_due(&self, id: String, due: Option<DateTimeTimestamp>) -> DatabaseResult<()> => |db| db.set_todo_due(&id, due);
+        pub async fn move_note(&self, id: String, parent_id: String) -> DatabaseResult<()> => |db| db.move_note(&id, &parent_id);
+        pub async fn move_notes(&self, notes_id: Vec<String>, parent_id: String) -> DatabaseResult<()> => |db| db.move_notes(&as_strs(&notes_id), &parent_id);
+        pub async fn reorder_note(&self, id: String, anchor_id: String, placement: Placement) -> DatabaseResult<()> => |db| db.reorder_note(&id, &anchor_id, placement);
+        pub async fn delete_note(&self, id: String, update_source: UpdateSource) -> DatabaseResult<()> => |db| db.delete_note(&id, update_source);
+        pub async fn delete_notes(&self, notes_id: Vec<String>) -> DatabaseResult<()> => |db| db.delete_notes(&as_strs(&notes_id));
+        pub async fn note_count(&self) -> DatabaseResult<i64> => |db| db.note_count();
+
+        pub async fn reindex_note_links(&self) -> DatabaseResult<()> => |db| db.reindex_note_links();
+        pub async fn load_note_links(&self, note_id: String) -> DatabaseResult<Vec<NoteLink>> => |db| db.load_note_links(&note_id);
+        pub async fn load_linked_notes(&self, note_id: String) -> DatabaseResult<Vec<AbbrNote>> => |db| db.load_linked_notes(&note_id);
+        pub async fn load_backlinks(&self, id: String) -> DatabaseResult<Vec<AbbrNote>> => |db| db.load_backlinks(&id);
+        pub async fn load_broken_links(&self) -> DatabaseResult<Vec<NoteLink>> => |db| db.load_broken_links();
+        pub async fn load_note_resources(&self, note_id: String) -> DatabaseResult<Vec<NoteResource>> => |db| db.load_note_resources(&note_id);
+        pub async fn load_alarms(&self) -> DatabaseResult<Vec<Alarm>> => |db| db.load_alarms();
+        pub async fn load_next_alarm(&self) -> DatabaseResult<Option<Alarm>> => |db| db.load_next_alarm();
+        pub async fn load_due_alarms(&self, time: DateTimeTimestamp) -> DatabaseResult<Vec<Alarm>> => |db| db.load_due_alarms(time);
+        pub async fn set_alarm_fired(&self, id: i64) -> DatabaseResult<()> => |db| db.set_alarm_fired(id);
+
+        pub async fn load_item_changes_since(&self, counter: i64, limit: Option<i64>) -> DatabaseResult<Vec<ItemChange>> => |db| db.load_item_changes_since(counter, limit);
+        pub async fn latest_item_change_counter(&self) -> DatabaseResult<i64> => |db| db.latest_item_change_counter();
+        pub async fn delete_item_changes_until(&self, counter: i64) -> DatabaseResult<()> => |db| db.delete_item_changes_until(counter);
+
+        pub async fn load_sync_item(&self, item_id: String) -> DatabaseResult<SyncItem> => |db| db.load_sync_item(&item_id);
+        pub async fn set_sync_item_up_to_data(&self, item_id: String) -> DatabaseResult<()> => |db| db.set_sync_item_up_to_data(&item_id);
+        pub async fn load_sync_items(&self, item_ids: Vec<String>) -> DatabaseResult<Vec<SyncItem>> => |db| db.load_sync_items(&as_strs(&item_ids));
+        pub async fn load_all_sync_items(&self) -> DatabaseResult<Vec<SyncItem>> => |db| db.load_all_sync_items();
+        pub async fn load_need_upload_sync_items(&self) -> DatabaseResult<Vec<SyncItem>> => |db| db.load_need_upload_sync_items();
+        pub async fn load_sync_item_content(&self, sync_item: SyncItem) -> DatabaseResult<ForSyncSerializer> => |db| db.load_sync_item_content(&sync_item);
+        pub async fn delete_sync_item(&self, item_id: String) -> DatabaseResult<()> => |db| db.delete_sync_item(&item_id);
+        pub async fn delete_sync_items(&self, item_ids: Vec<String>) -> DatabaseResult<()> => |db| db.delete_sync_items(&as_strs(&item_ids));
+
+        pub async fn delete_deleted_item(&self, deleted_item: DeletedItem) -> DatabaseResult<()> => |db| db.delete_deleted_item(deleted_item);
+        pub async fn load_deleted_items(&self) -> DatabaseResult<Vec<DeletedItem>> => |db| db.load_deleted_items();
+
+        pub async fn get_setting_value(&self, key: String) -> DatabaseResult<Option<Setting>> => |db| db.get_setting_value(&key);
+        pub async fn replace_setting(&self, key: String, value: String) -> DatabaseResult<()> => |db| db.replace_setting(&key, &value);
+        pub async fn delete_setting(&self, key: String) -> DatabaseResult<()> => |db| db.delete_setting(&key);
+        pub async fn get_client_id(&self) -> DatabaseResult<String> => |db| db.get_client_id();
+
+        pub async fn load_tag(&self, id: String) -> DatabaseResult<Tag> => |db| db.load_tag(&id);
+        pub async fn load_all_tags(&self) -> DatabaseResult<Vec<Tag>> => |db| db.load_all_tags();
+        pub async fn load_tags(&self, ids: Vec<String>) -> DatabaseResult<Vec<Tag>> => |db| db.load_tags(&as_strs(&ids));
+        pub async fn replace_tag(&self, tag: Tag, update_source: UpdateSource) -> DatabaseResult<()> => |db| db.replace_tag(&tag, update_source);
+        pub async fn delete_tag(&self, id: String, update_source: UpdateSource) -> DatabaseResult<()> => |db| db.delete_tag(&id, update_source);
+        pub async fn tag_count(&self) -> DatabaseResult<i64> => |db| db.tag_count();
+        pub async fn insert_tag_path(&self, path: String) -> DatabaseResult<Tag> => |db| db.insert_tag_path(&path);
+        pub async fn load_tag_path(&self, id: String) -> DatabaseResult<String> => |db| db.load_tag_path(&id);
+        pub async fn load_tag_tree(&self) -> DatabaseResult<Vec<TagNode>> => |db| db.load_tag_tree();
+        pub async fn load_abbr_notes_with_tag(&self, tag_id: String, include_descendants: bool) -> DatabaseResult<Vec<AbbrNote>> => |db| db.load_abbr_notes_with_tag(&tag_id, include_descendants);
+        pub async fn move_tag(&self, id: String, parent_id: Option<String>) -> DatabaseResult<()> => |db| db.move_tag(&id, parent_id.as_deref());
+        pub async fn rename_tag(&self, id: String, title: String) -> DatabaseResult<Tag> => |db| db.rename_tag(&id, &title);
+        pub async fn merge_tags(&self, sources: Vec<String>, target: String) -> DatabaseResult<()> => |db| db.merge_tags(&as_strs(&sources), &target);
+        pub async fn merge_duplicate_tags(&self) -> DatabaseResult<usize> => |db| db.merge_duplicate_tags();
+        pub async fn delete_unused_tags(&self) -> DatabaseResult<usize> => |db| db.delete_unused_tags();
+        pub async fn load_note_tag(&self, id: String) -> DatabaseResult<NoteTag> => |db| db.load_note_tag(&id);
+        pub async fn load_all_note_tags(&self) -> DatabaseResult<Vec<NoteTag>> => |db| db.load_all_note_tags();
+        pub async fn load_note_tag_on_note(&self, note_id: String, tag_id: String) -> DatabaseResult<NoteTag> => |db| db.load_note_tag_on_note(&note_id, &tag_id);
+        pub async fn get_note_tags(&self, note_id: String) -> DatabaseResult<Vec<Tag>> => |db| db.get_note_tags(&note_id);
+        pub async fn add_tag_on_note(&self, note_id: String, tag_id: String) -> DatabaseResult<()> => |db| db.add_tag_on_note(&note_id, &tag_id);
+        pub async fn replace_note_tag(&self, note_tag: NoteTag, update_source: UpdateSource) -> DatabaseResult<()> => |db| db.replace_note_tag(&note_tag, update_source);
+        pub async fn delete_note_tag(&self, id: String, update_source: UpdateSource) -> DatabaseResult<()> => |db| db.delete_note_tag(&id, update_source);
+        pub async fn delete_note_tag_by_note_id_and_tag_id(&self, note_id: String, tag_id: String) -> DatabaseResult<()> => |db| db.delete_note_tag_by_note_id_and_tag_id(&note_id, &tag_id);
+        pub async fn delete_note_tags(&self, ids: Vec<String>, update_source: UpdateSource) -> DatabaseResult<()> => |db| db.delete_note_tags(&as_strs(&ids), update_source);
+        pub async fn delete_note_tags_by_note_id(&self, note_id: String, update_source: UpdateSource) -> DatabaseResult<()> => |db| db.delete_note_tags_by_note_id(&note_id, update_source);
+        pub async fn delete_note_tag_by_note_ids(&self, note_ids: Vec<String>, update_source: UpdateSource) -> DatabaseResult<()> => |db| db.delete_note_tag_by_note_ids(&as_strs(&note_ids), update_source);
+        pub async fn note_tag_count(&self) -> DatabaseResult<i64> => |db| db.note_tag_count();
+
+        pub async fn insert_smart_filter(&self, title: String, query: String) -> DatabaseResult<SmartFilter> => |db| db.insert_smart_filter(&title, &query);
+        pub async fn replace_smart_filter(&self, smart_filter: SmartFilter, update_source: UpdateSource) -> DatabaseResult<()> => |db| db.replace_smart_filter(&smart_filter, update_source);
+        pub async fn delete_smart_filter(&self, id: String, update_source: UpdateSource) -> DatabaseResult<()> => |db| db.delete_smart_filter(&id, update_source);
+        pub async fn load_smart_filter(&self, id: String) -> DatabaseResult<SmartFilter> => |db| db.load_smart_filter(&id);
+        pub async fn load_smart_filters(&self) -> DatabaseResult<Vec<SmartFilter>> => |db| db.load_smart_filters();
+        pub async fn evaluate_smart_filter(&self, id: String) -> DatabaseResult<Vec<NoteListItem>> => |db| db.evaluate_smart_filter(&id);
+        pub async fn evaluate_smart_filter_query(&self, query: String) -> DatabaseResult<Vec<NoteListItem>> => |db| db.evaluate_smart_filter_query(&query);
+        pub async fn load_resource(&self, id: String) -> DatabaseResult<Resource> => |db| db.load_resource(&id);
+        pub async fn replace_resource(&self, resource: Resource, update_source: UpdateSource) -> DatabaseResult<()> => |db| db.replace_resource(&resource, update_source);
+        pub async fn import_resource(&self, path: PathBuf) -> DatabaseResult<ImportedResource> => |db| db.import_resource(&path);
+        pub async fn import_resource_bytes(&self, bytes: Vec<u8>, file_name: String) -> DatabaseResult<ImportedResource> => |db| db.import_resource_bytes(&bytes, &file_name);
+        pub async fn load_thumbnail(&self, resource_id: String, size: u32) -> DatabaseResult<PathBuf> => |db| db.load_thumbnail(&resource_id, size);
+        pub async fn reindex_resource_texts(&self) -> DatabaseResult<()> => |db| db.reindex_resource_texts();
+        pub async fn delete_resource(&self, id: String, update_source: UpdateSource) -> DatabaseResult<()> => |db| db.delete_resource(&id, update_source);
+        pub async fn resource_count(&self) -> DatabaseResult<i64> => |db| db.resource_count();
+        pub async fn load_orphan_resources(&self, grace_period: Duration) -> DatabaseResult<Vec<Resource>> => |db| db.load_orphan_resources(grace_period);
+        pub async fn delete_orphan_resources(&self, grace_period: Duration) -> DatabaseResult<ResourceGcReport> => |db| db.delete_orphan_resources(grace_period);
+
+        pub async fn export_markdown(&self, dir: PathBuf) -> DatabaseResult<MarkdownExportReport> => |db| db.export_markdown(&dir);
+        pub async fn status(&self) -> DatabaseResult<Status> => |db| db.status();
+    }
+}
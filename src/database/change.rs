@@ -0,0 +1,15 @@
+use crate::{ChangeKind, ModelType};
+
+use super::UpdateSource;
+
+/// Changes are dropped for subscribers lagging further behind.
+pub(crate) const CHANGE_CHANNEL_CAPACITY: usize = 1024;
+
+/// Emitted after an item has been written, see `Database::subscribe_changes`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DatabaseChange {
+    pub item_type: ModelType,
+    pub item_id: String,
+    pub kind: ChangeKind,
+    pub update_source: UpdateSource,
+}
@@ -5,18 +5,20 @@ use diesel::sqlite::SqliteConnection;
 
 use crate::database::sqlite3_fts5::register_tokenizer;
 
-use super::jieba_tokenizer::JiebaTokenizer;
+use super::tokenizer::{FtsTokenizer, FtsTokenizerGlobal, FTS_TOKENIZER_NAME};
 
 #[derive(Debug)]
 pub struct ConnectionOptions {
     pub busy_timeout: Option<Duration>,
+    pub tokenizer: FtsTokenizerGlobal,
 }
 
 impl ConnectionOptions {
     pub fn apply(&self, conn: &mut SqliteConnection) -> QueryResult<()> {
         conn.batch_execute("PRAGMA foreign_keys = ON;")?;
 
-        register_tokenizer::<JiebaTokenizer>(conn, (), "jieba").expect("register tokenizer failed");
+        register_tokenizer::<FtsTokenizer>(conn, self.tokenizer.clone(), FTS_TOKENIZER_NAME)
+            .expect("register tokenizer failed");
 
         if let Some(duration) = self.busy_timeout {
             conn.batch_execute(&format!(
@@ -32,6 +34,7 @@ impl Default for ConnectionOptions {
     fn default() -> Self {
         Self {
             busy_timeout: Some(Duration::milliseconds(500)),
+            tokenizer: FtsTokenizerGlobal::default(),
         }
     }
 }
@@ -26,6 +26,23 @@ pub enum DatabaseError {
     Vacuum,
     #[error("r2d2 error")]
     R2d2Error(#[from] r2d2::Error),
+    #[error("Folder cannot be moved into itself or one of its subfolders")]
+    FolderCycle,
+    #[error("Tag cannot be moved into itself or one of its descendants")]
+    TagCycle,
+    /// A sibling tag has the same title, only checked on local edits, see `Database::merge_duplicate_tags`.
+    #[error("Tag {0:?} already exists")]
+    DuplicateTag(String),
+    #[error("Invalid tag path {0:?}")]
+    InvalidTagPath(String),
+    #[error("Invalid smart filter: {0}")]
+    InvalidSmartFilter(String),
+    #[error("Cannot create a thumbnail of {0:?}")]
+    UnsupportedImage(String),
+    #[error("Image error {0}")]
+    Image(#[from] image::ImageError),
+    #[error("Database task cancelled")]
+    Cancelled,
     #[error("Unknown Error")]
     Unknown,
 }
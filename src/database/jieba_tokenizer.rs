@@ -1,35 +1,11 @@
 mod unicode_segmentation_tables;
 
-use super::sqlite3_fts5::{SqliteError, TokenizeReason, Tokenizer};
-use jieba_rs::Jieba;
-
-pub struct JiebaTokenizer(JiebaTokenizerImpl);
-
-impl Tokenizer for JiebaTokenizer {
-    type Global = ();
+use std::{fmt::Debug, sync::Arc};
 
-    fn new(&(): &Self::Global, _args: Vec<String>) -> Result<Self, SqliteError> {
-        Ok(Self(JiebaTokenizerImpl::new()))
-    }
+use jieba_rs::Jieba;
+use parking_lot::RwLock;
 
-    fn tokenize<TKF>(
-        &mut self,
-        _reason: TokenizeReason,
-        text: &[u8],
-        mut push_token: TKF,
-    ) -> Result<(), SqliteError>
-    where
-        TKF: FnMut(&[u8], std::ops::Range<usize>, bool) -> Result<(), SqliteError>,
-    {
-        let text = String::from_utf8_lossy(text);
-        let tokens = self.0.tokenize(&text);
-        for token in tokens {
-            let range = token.start..token.end;
-            push_token(token.word.as_bytes(), range, false)?;
-        }
-        Ok(())
-    }
-}
+use crate::UserDictionaryWord;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Token<'a> {
@@ -41,15 +17,42 @@ pub struct Token<'a> {
     pub end: usize,
 }
 
-pub struct JiebaTokenizerImpl(Jieba);
+/// Shared by every FTS5 tokenizer instance, the dictionary is only loaded once.
+#[derive(Clone)]
+pub struct JiebaTokenizerImpl(Arc<RwLock<Jieba>>);
+
+impl Debug for JiebaTokenizerImpl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("JiebaTokenizerImpl")
+    }
+}
+
+impl Default for JiebaTokenizerImpl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl JiebaTokenizerImpl {
     pub fn new() -> Self {
-        Self(Jieba::new())
+        Self(Arc::new(RwLock::new(Jieba::new())))
+    }
+
+    /// Reloads the default dictionary with the user words, removed words are dropped by the reload.
+    pub fn load_user_dictionary(&self, words: &[UserDictionaryWord]) {
+        let mut jieba = Jieba::new();
+        for word in words {
+            jieba.add_word(
+                &word.word,
+                word.freq.map(|freq| freq.max(0) as usize),
+                word.tag.as_deref(),
+            );
+        }
+        *self.0.write() = jieba;
     }
 
     pub fn tokenize<'a>(&self, sentence: &'a str) -> Vec<Token<'a>> {
-        let words = self.0.cut(sentence, false);
+        let words = self.0.read().cut(sentence, false);
         let mut tokens = Vec::with_capacity(words.len());
         let mut start = 0;
         for word in words {
@@ -70,6 +73,7 @@ impl JiebaTokenizerImpl {
 #[cfg(test)]
 mod tests {
     use super::JiebaTokenizerImpl;
+    use crate::UserDictionaryWord;
 
     #[test]
     fn test_jieba_tokenizer_impl() {
@@ -83,6 +87,23 @@ mod tests {
             assert_eq!(token.word, &text[token.start..token.end]);
         }
     }
+
+    #[test]
+    fn test_jieba_user_dictionary() {
+        let jieba_tokenizer_impl = JiebaTokenizerImpl::new();
+        let words = |text| -> Vec<String> {
+            jieba_tokenizer_impl
+                .tokenize(text)
+                .into_iter()
+                .map(|t| t.word.to_string())
+                .collect()
+        };
+        assert_ne!(vec!["拖拉机学院"], words("拖拉机学院"));
+        jieba_tokenizer_impl.load_user_dictionary(&[UserDictionaryWord::new("拖拉机学院")]);
+        assert_eq!(vec!["拖拉机学院"], words("拖拉机学院"));
+        jieba_tokenizer_impl.load_user_dictionary(&[]);
+        assert_ne!(vec!["拖拉机学院"], words("拖拉机学院"));
+    }
 }
 
 #[inline]
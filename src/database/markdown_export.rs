@@ -0,0 +1,456 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use diesel::{QueryDsl, RunQueryDsl};
+
+use crate::{DateTimeRFC333, Folder, Note, Resource};
+
+use super::{
+    note_links::{extract_link_ids, replace_links},
+    DatabaseResult, Transaction,
+};
+
+/// Directory of the attachments, at the root of the export.
+pub const EXPORT_RESOURCE_DIR: &str = "_resources";
+
+/// Directory of the conflict notes, at the root of the export.
+const EXPORT_CONFLICT_DIR: &str = "Conflicts";
+
+/// In UTF-8 bytes, without the extension, it leaves room for ` (N)` and the extension under
+/// the 255 bytes allowed by most file systems.
+const MAX_FILE_NAME_LENGTH: usize = 200;
+
+/// Returned by `Database::export_markdown`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MarkdownExportReport {
+    pub folder_count: usize,
+    pub note_count: usize,
+    pub resource_count: usize,
+    /// Linked resources without a readable file, e.g. not downloaded yet or still encrypted,
+    /// their links are left as is.
+    pub missing_resources: Vec<String>,
+}
+
+/// The YAML front matter of the exported notes.
+struct FrontMatter<'a> {
+    title: &'a str,
+    id: &'a str,
+    created: DateTimeRFC333,
+    updated: DateTimeRFC333,
+    /// Tag paths, e.g. `work/project`.
+    tags: Vec<String>,
+    todo: bool,
+    due: Option<DateTimeRFC333>,
+    completed: Option<DateTimeRFC333>,
+    source_url: &'a str,
+    author: &'a str,
+}
+
+impl<'a> FrontMatter<'a> {
+    fn new(note: &'a Note, tags: Vec<String>) -> Self {
+        let time = |time: crate::DateTimeTimestamp| (!time.is_zero()).then(|| time.into());
+        Self {
+            title: &note.title,
+            id: &note.id,
+            created: note.user_created_time.into(),
+            updated: note.user_updated_time.into(),
+            tags,
+            todo: note.is_todo,
+            due: time(note.todo_due).filter(|_| note.is_todo),
+            completed: time(note.todo_completed).filter(|_| note.is_todo),
+            source_url: &note.source_url,
+            author: &note.author,
+        }
+    }
+
+    /// The fields without a value are left out, dates are plain RFC 3339 timestamps.
+    fn to_yaml(&self) -> String {
+        let mut lines = vec![
+            format!("title: {}", yaml_string(self.title)),
+            format!("id: {}", yaml_string(self.id)),
+            format!("created: {}", self.created.as_string()),
+            format!("updated: {}", self.updated.as_string()),
+        ];
+        if !self.tags.is_empty() {
+            lines.push("tags:".to_string());
+            for tag in self.tags.iter() {
+                lines.push(format!("- {}", yaml_string(tag)));
+            }
+        }
+        if self.todo {
+            lines.push("todo: true".to_string());
+        }
+        if let Some(due) = &self.due {
+            lines.push(format!("due: {}", due.as_string()));
+        }
+        if let Some(completed) = &self.completed {
+            lines.push(format!("completed: {}", completed.as_string()));
+        }
+        if !self.source_url.is_empty() {
+            lines.push(format!("source_url: {}", yaml_string(self.source_url)));
+        }
+        if !self.author.is_empty() {
+            lines.push(format!("author: {}", yaml_string(self.author)));
+        }
+        let mut yaml = lines.join("\n");
+        yaml.push('\n');
+        yaml
+    }
+}
+
+/// `value` as a YAML string: plain when it cannot be read as YAML syntax or as another type,
+/// single-quoted otherwise, and double-quoted when it has control characters.
+fn yaml_string(value: &str) -> String {
+    if value.chars().any(char::is_control) {
+        let mut quoted = String::with_capacity(value.len() + 2);
+        quoted.push('"');
+        for c in value.chars() {
+            match c {
+                '"' => quoted.push_str("\\\""),
+                '\\' => quoted.push_str("\\\\"),
+                '\n' => quoted.push_str("\\n"),
+                '\t' => quoted.push_str("\\t"),
+                c if c.is_control() => quoted.push_str(&format!("\\u{:04X}", c as u32)),
+                c => quoted.push(c),
+            }
+        }
+        quoted.push('"');
+        return quoted;
+    }
+    let first = match value.chars().next() {
+        Some(first) => first,
+        None => return "''".to_string(),
+    };
+    let is_special = value.trim() != value
+        || "-?:,[]{}#&*!|>'\"%@`.+".contains(first)
+        || value.contains(": ")
+        || value.contains(" #")
+        || value.ends_with(':')
+        || matches!(
+            value.to_ascii_lowercase().as_str(),
+            "true" | "false" | "yes" | "no" | "on" | "off" | "y" | "n" | "null" | "~"
+        )
+        // Numbers, dates and times.
+        || (first.is_ascii_digit()
+            && (value.starts_with("0x")
+                || value.starts_with("0o")
+                || value
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || "+-.:_eEtTzZ ".contains(c))));
+    if is_special {
+        format!("'{}'", value.replace('\'', "''"))
+    } else {
+        value.to_string()
+    }
+}
+
+/// The names already taken in each directory of the export, case insensitively.
+#[derive(Default)]
+struct DirNames(HashMap<PathBuf, HashSet<String>>);
+
+impl DirNames {
+    fn reserve(&mut self, dir: &Path, name: &str) {
+        self.0
+            .entry(dir.to_path_buf())
+            .or_default()
+            .insert(name.to_lowercase());
+    }
+
+    /// A file name made of `title` and `extension`, numbered when taken, e.g. `Title (2).md`.
+    fn unique(&mut self, dir: &Path, title: &str, extension: &str) -> String {
+        let names = self.0.entry(dir.to_path_buf()).or_default();
+        let base = file_name_of_title(title);
+        let mut name = format!("{base}{extension}");
+        let mut number = 1;
+        while !names.insert(name.to_lowercase()) {
+            number += 1;
+            name = format!("{base} ({number}){extension}");
+        }
+        name
+    }
+}
+
+/// Replaces the characters that are invalid in file names on Windows, macOS or Linux, and cuts
+/// long titles at a character boundary.
+fn file_name_of_title(title: &str) -> String {
+    let mut name = String::new();
+    for c in title.chars() {
+        let c = match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        };
+        if name.len() + c.len_utf8() > MAX_FILE_NAME_LENGTH {
+            break;
+        }
+        name.push(c);
+    }
+    let name = name
+        .trim()
+        .trim_start_matches('.')
+        .trim_end_matches(['.', ' ']);
+    if name.is_empty() {
+        "Untitled".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+/// `path` relative to the directory `from`, both relative to the root of the export, as a link.
+fn relative_link(from: &Path, path: &Path) -> String {
+    let from: Vec<_> = from.components().collect();
+    let path: Vec<_> = path.components().collect();
+    let common = from
+        .iter()
+        .zip(&path[..path.len().saturating_sub(1)])
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut link = "../".repeat(from.len() - common);
+    let components: Vec<_> = path[common..]
+        .iter()
+        .map(|component| percent_encode(&component.as_os_str().to_string_lossy()))
+        .collect();
+    link.push_str(&components.join("/"));
+    link
+}
+
+/// Keeps the unreserved characters of RFC 3986, so links with spaces or parentheses stay valid.
+fn percent_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+            encoded.push(b as char);
+        } else {
+            encoded.push_str(&format!("%{b:02X}"));
+        }
+    }
+    encoded
+}
+
+/// The directories of the folders, relative to the root of the export. Orphans and folders in a
+/// cycle are placed at the root.
+fn folder_dirs<'a>(folders: &'a [Folder], names: &mut DirNames) -> HashMap<&'a str, PathBuf> {
+    let ids: HashSet<&str> = folders.iter().map(|folder| folder.id.as_str()).collect();
+    let parent_of = |folder: &'a Folder| {
+        folder
+            .parent_id
+            .as_deref()
+            .filter(|parent_id| *parent_id != folder.id && ids.contains(parent_id))
+    };
+    let mut children: HashMap<&str, Vec<&Folder>> = HashMap::new();
+    let mut queue = VecDeque::new();
+    for folder in folders {
+        match parent_of(folder) {
+            Some(parent_id) => children.entry(parent_id).or_default().push(folder),
+            None => queue.push_back((folder, PathBuf::new())),
+        }
+    }
+    let mut dirs = HashMap::new();
+    loop {
+        while let Some((folder, parent_dir)) = queue.pop_front() {
+            if dirs.contains_key(folder.id.as_str()) {
+                continue;
+            }
+            let dir = parent_dir.join(names.unique(&parent_dir, &folder.title, ""));
+            for child in children.get(folder.id.as_str()).into_iter().flatten() {
+                queue.push_back((child, dir.clone()));
+            }
+            dirs.insert(folder.id.as_str(), dir);
+        }
+        match folders
+            .iter()
+            .find(|folder| !dirs.contains_key(folder.id.as_str()))
+        {
+            Some(folder) => queue.push_back((folder, PathBuf::new())),
+            None => break,
+        }
+    }
+    dirs
+}
+
+/// The title of the resource with its extension, once.
+fn resource_file_name(resource: &Resource, names: &mut DirNames, dir: &Path) -> String {
+    let extension = if resource.file_extension.is_empty() {
+        String::new()
+    } else {
+        format!(".{}", resource.file_extension)
+    };
+    let title = match resource.title.len().checked_sub(extension.len()) {
+        Some(end)
+            if !extension.is_empty()
+                && resource.title.is_char_boundary(end)
+                && resource.title[end..].eq_ignore_ascii_case(&extension) =>
+        {
+            &resource.title[..end]
+        }
+        _ => resource.title.as_str(),
+    };
+    let title = if title.trim().is_empty() {
+        resource.id.as_str()
+    } else {
+        title
+    };
+    names.unique(dir, title, &extension)
+}
+
+impl Transaction<'_> {
+    /// Writes the folders as directories and the notes as Markdown files with a YAML front matter,
+    /// into `dir`. The linked resources are copied into `EXPORT_RESOURCE_DIR` and the `:/<id>` links
+    /// to exported notes and resources become relative paths. Existing files with the same names
+    /// are overwritten.
+    pub fn export_markdown(&mut self, dir: &Path) -> DatabaseResult<MarkdownExportReport> {
+        use crate::schema::{notes, resources};
+        let mut report = MarkdownExportReport::default();
+        let mut names = DirNames::default();
+        let root = PathBuf::new();
+        let resource_dir = PathBuf::from(EXPORT_RESOURCE_DIR);
+        names.reserve(&root, EXPORT_RESOURCE_DIR);
+
+        let folders = self.load_folders()?;
+        let folder_dirs = folder_dirs(&folders, &mut names);
+        for folder_dir in folder_dirs.values() {
+            fs::create_dir_all(dir.join(folder_dir))?;
+        }
+        report.folder_count = folder_dirs.len();
+
+        let note_ids: Vec<String> = notes::table
+            .select(notes::id)
+            .order((notes::title, notes::id))
+            .load(self.conn)?;
+        let mut notes = Vec::with_capacity(note_ids.len());
+        for id in note_ids.iter() {
+            notes.push(self.load_note(id)?);
+        }
+        let conflict_dir = if notes.iter().any(|note| note.is_conflict) {
+            Some(root.join(names.unique(&root, EXPORT_CONFLICT_DIR, "")))
+        } else {
+            None
+        };
+        let mut note_paths: HashMap<&str, PathBuf> = HashMap::new();
+        for note in notes.iter() {
+            let note_dir = match (&conflict_dir, note.parent_id.as_deref()) {
+                (Some(conflict_dir), _) if note.is_conflict => conflict_dir.clone(),
+                (_, Some(parent_id)) => folder_dirs.get(parent_id).cloned().unwrap_or_default(),
+                (_, None) => root.clone(),
+            };
+            let name = names.unique(&note_dir, &note.title, ".md");
+            note_paths.insert(note.id.as_str(), note_dir.join(name));
+        }
+
+        let resources: HashMap<String, Resource> = resources::table
+            .load::<Resource>(self.conn)?
+            .into_iter()
+            .map(|resource| (resource.id.clone(), resource))
+            .collect();
+        let mut resource_paths: HashMap<&str, PathBuf> = HashMap::new();
+        let mut tag_paths: HashMap<String, String> = HashMap::new();
+        for note in notes.iter() {
+            for id in extract_link_ids(&note.body) {
+                let resource = match resources.get(&id) {
+                    Some(resource) => resource,
+                    None => continue,
+                };
+                if resource_paths.contains_key(id.as_str())
+                    || report.missing_resources.contains(&id)
+                {
+                    continue;
+                }
+                let file = resource.resource_file_path(&self.db.resource_path);
+                if resource.encryption_blob_encrypted || !file.exists() {
+                    report.missing_resources.push(id);
+                    continue;
+                }
+                let path =
+                    resource_dir.join(resource_file_name(resource, &mut names, &resource_dir));
+                fs::create_dir_all(dir.join(&resource_dir))?;
+                fs::copy(&file, dir.join(&path))?;
+                resource_paths.insert(resource.id.as_str(), path);
+            }
+
+            let path = &note_paths[note.id.as_str()];
+            let note_dir = path.parent().unwrap_or(&root);
+            let body = replace_links(&note.body, |id| {
+                resource_paths
+                    .get(id)
+                    .or_else(|| note_paths.get(id))
+                    .map(|target| relative_link(note_dir, target))
+            });
+            let mut tags = Vec::new();
+            for tag in self.get_note_tags(&note.id)? {
+                let tag_path = match tag_paths.get(&tag.id) {
+                    Some(tag_path) => tag_path.clone(),
+                    None => {
+                        let tag_path = self.load_tag_path(&tag.id)?;
+                        tag_paths.insert(tag.id.clone(), tag_path.clone());
+                        tag_path
+                    }
+                };
+                tags.push(tag_path);
+            }
+            tags.sort();
+            let front_matter = FrontMatter::new(note, tags).to_yaml();
+            fs::create_dir_all(dir.join(note_dir))?;
+            fs::write(dir.join(path), format!("---\n{front_matter}---\n\n{body}"))?;
+        }
+        report.note_count = notes.len();
+        report.resource_count = resource_paths.len();
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{file_name_of_title, relative_link, yaml_string, DirNames};
+
+    #[test]
+    fn test_export_file_names() {
+        assert_eq!("a_b_ c", file_name_of_title(" a/b: c. "));
+        assert_eq!("Untitled", file_name_of_title("..."));
+        assert_eq!(200, file_name_of_title(&"x".repeat(300)).len());
+        let cjk = file_name_of_title(&"笔记".repeat(100));
+        assert_eq!(198, cjk.len());
+        assert!(cjk.chars().all(|c| c == '笔' || c == '记'));
+        let mut names = DirNames::default();
+        names.unique(Path::new(""), &cjk, ".md");
+        let numbered = names.unique(Path::new(""), &cjk, ".md");
+        assert!(numbered.ends_with(" (2).md") && numbered.len() <= 255);
+        let mut names = DirNames::default();
+        let dir = Path::new("Notes");
+        assert_eq!("Plan.md", names.unique(dir, "Plan", ".md"));
+        assert_eq!("plan (2).md", names.unique(dir, "plan", ".md"));
+        assert_eq!("Plan", names.unique(dir, "Plan", ""));
+        assert_eq!("Plan.md", names.unique(Path::new(""), "Plan", ".md"));
+        assert_eq!(
+            "../../_resources/my%20photo%20%282%29.png",
+            relative_link(
+                Path::new("Notes/Work"),
+                Path::new("_resources/my photo (2).png")
+            )
+        );
+        assert_eq!(
+            "Caf%C3%A9.md",
+            relative_link(Path::new(""), Path::new("Café.md"))
+        );
+    }
+
+    #[test]
+    fn test_yaml_string() {
+        assert_eq!("work/project", yaml_string("work/project"));
+        assert_eq!("https://example.com", yaml_string("https://example.com"));
+        assert_eq!("3 ideas", yaml_string("3 ideas"));
+        assert_eq!("''", yaml_string(""));
+        assert_eq!("'Plan: Q1'", yaml_string("Plan: Q1"));
+        assert_eq!("'it''s #1'", yaml_string("it's #1"));
+        assert_eq!("'- item'", yaml_string("- item"));
+        assert_eq!("'Yes'", yaml_string("Yes"));
+        assert_eq!("'2024-01-31'", yaml_string("2024-01-31"));
+        assert_eq!("'1e3'", yaml_string("1e3"));
+        assert_eq!("' padded'", yaml_string(" padded"));
+        assert_eq!(r#""a\"b\n\u0007""#, yaml_string("a\"b\n\u{7}"));
+    }
+}
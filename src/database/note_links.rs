@@ -0,0 +1,102 @@
+use std::ops::Range;
+
+/// Bump it whenever `extract_link_ids` changes, the links of every note are extracted again on the next start.
+///
+/// 2: fills `note_resources`.
+pub(crate) const NOTE_LINKS_VERSION: &str = "2";
+
+/// Length of the hexadecimal ids of Joplin items.
+const ID_LENGTH: usize = 32;
+
+pub(crate) fn is_item_id(id: &str) -> bool {
+    id.len() == ID_LENGTH && id.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// Ids referenced as `:/<id>` by Markdown links and images, reference definitions and HTML attributes,
+/// e.g. `[text](:/<id>)` or `<img src=":/<id>">`. They are lowercased and deduplicated, in order.
+pub(crate) fn extract_link_ids(body: &str) -> Vec<String> {
+    let mut ids = Vec::new();
+    for range in link_ranges(body) {
+        let id = body[range.start + 2..range.end].to_ascii_lowercase();
+        if !ids.contains(&id) {
+            ids.push(id);
+        }
+    }
+    ids
+}
+
+/// Replaces the `:/<id>` links found by `extract_link_ids`, `f` gets the lowercased id and the
+/// links it returns `None` for are kept.
+pub(crate) fn replace_links(body: &str, mut f: impl FnMut(&str) -> Option<String>) -> String {
+    let mut replaced = String::with_capacity(body.len());
+    let mut last = 0;
+    for range in link_ranges(body) {
+        let id = body[range.start + 2..range.end].to_ascii_lowercase();
+        if let Some(link) = f(&id) {
+            replaced.push_str(&body[last..range.start]);
+            replaced.push_str(&link);
+            last = range.end;
+        }
+    }
+    replaced.push_str(&body[last..]);
+    replaced
+}
+
+/// The ranges of the `:/<id>` links.
+fn link_ranges(body: &str) -> Vec<Range<usize>> {
+    let bytes = body.as_bytes();
+    let mut ranges = Vec::new();
+    for (start, _) in body.match_indices(":/") {
+        let preceded = start == 0
+            || matches!(bytes[start - 1], b'(' | b'"' | b'\'' | b'<')
+            || bytes[start - 1].is_ascii_whitespace();
+        let id_start = start + 2;
+        let id_end = id_start + ID_LENGTH;
+        let is_id = matches!(
+            bytes.get(id_start..id_end),
+            Some(id) if id.iter().all(u8::is_ascii_hexdigit)
+        );
+        // Anchors like `:/<id>#heading` still link to the note.
+        let followed = !matches!(bytes.get(id_end), Some(b) if b.is_ascii_alphanumeric());
+        if preceded && is_id && followed {
+            ranges.push(start..id_end);
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract_link_ids, replace_links};
+
+    #[test]
+    fn test_extract_link_ids() {
+        let note = "0123456789abcdef0123456789abcdef";
+        let resource = "fedcba9876543210fedcba9876543210";
+        let body = format!(
+            "[note](:/{note}) ![image](:/{resource} \"title\")\n\
+             [again](:/{note}#heading) <img src=\":/{}\"/>\n\
+             [ref]: :/{resource}\n\
+             not links: http://host:/{note} :/{note}0 :/0123 [x](:/{})",
+            resource.to_uppercase(),
+            &note[1..],
+        );
+        assert_eq!(vec![note, resource], extract_link_ids(&body));
+        assert_eq!(vec![note], extract_link_ids(&format!(":/{note}")));
+        assert!(extract_link_ids("").is_empty());
+    }
+
+    #[test]
+    fn test_replace_links() {
+        let note = "0123456789abcdef0123456789abcdef";
+        let other = "fedcba9876543210fedcba9876543210";
+        let body = format!(
+            "[a](:/{}#top) [b](:/{other}) x:/{note}",
+            note.to_uppercase()
+        );
+        assert_eq!(
+            format!("[a](a.md#top) [b](:/{other}) x:/{note}"),
+            replace_links(&body, |id| (id == note).then(|| "a.md".to_string()))
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use chrono::{Local, NaiveDate, NaiveDateTime, TimeZone};
+use diesel::Queryable;
+
+use crate::DateTimeTimestamp;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteSortField {
+    Title,
+    CreatedTime,
+    UpdatedTime,
+    /// The custom order set by `Database::reorder_note`, descending puts the first note first.
+    Order,
+}
+
+impl NoteSortField {
+    pub(crate) fn column(&self) -> &'static str {
+        match self {
+            Self::Title => "title COLLATE NOCASE",
+            Self::CreatedTime => "user_created_time",
+            Self::UpdatedTime => "user_updated_time",
+            Self::Order => "`order`",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortDirection {
+    Ascending,
+    Descending,
+}
+
+impl SortDirection {
+    pub(crate) fn keyword(&self) -> &'static str {
+        match self {
+            Self::Ascending => "ASC",
+            Self::Descending => "DESC",
+        }
+    }
+}
+
+/// Lists the notes of a folder without loading their bodies, conflict notes are left out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoteListRequest {
+    /// `None` lists the notes of every folder.
+    pub parent_id: Option<String>,
+    pub sort_field: NoteSortField,
+    pub sort_direction: SortDirection,
+    pub offset: i64,
+    /// `None` returns every note after `offset`.
+    pub limit: Option<i64>,
+    /// Number of characters of the body returned in `NoteListItem::preview`, `None` skips it.
+    pub preview_length: Option<usize>,
+}
+
+impl NoteListRequest {
+    pub fn new(parent_id: Option<&str>) -> Self {
+        Self {
+            parent_id: parent_id.map(str::to_string),
+            sort_field: NoteSortField::UpdatedTime,
+            sort_direction: SortDirection::Descending,
+            offset: 0,
+            limit: None,
+            preview_length: None,
+        }
+    }
+}
+
+#[derive(Queryable, Debug, Clone, PartialEq, Eq)]
+pub struct NoteListItem {
+    pub id: String,
+    pub parent_id: Option<String>,
+    pub title: String,
+    pub user_created_time: DateTimeTimestamp,
+    pub user_updated_time: DateTimeTimestamp,
+    pub is_todo: bool,
+    pub todo_due: DateTimeTimestamp,
+    pub todo_completed: DateTimeTimestamp,
+    /// The start of the body on a single line.
+    pub preview: Option<String>,
+}
+
+/// Uncompleted to-dos returned by `Database::load_todos`, ordered by due date.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TodoFilter {
+    /// Due before now.
+    Overdue,
+    /// Due during the current local day, including the ones already overdue.
+    DueToday,
+    /// Due from now on.
+    Upcoming,
+}
+
+#[derive(Debug, Clone)]
+pub struct NoteListResponse {
+    /// Number of notes ignoring `offset` and `limit`.
+    pub total_count: i64,
+    pub notes: Vec<NoteListItem>,
+}
+
+/// Start and end of the local day containing `time`.
+pub(crate) fn local_day_bounds(time: DateTimeTimestamp) -> (DateTimeTimestamp, DateTimeTimestamp) {
+    let date = local_date(time);
+    let next_day = date.succ_opt().expect("local_day_bounds error");
+    (local_midnight(date), local_midnight(next_day))
+}
+
+pub(crate) fn local_date(time: DateTimeTimestamp) -> NaiveDate {
+    NaiveDateTime::from_timestamp_millis(time.timestamp_millis())
+        .map(|utc| Local.from_utc_datetime(&utc).date_naive())
+        .expect("local_date error")
+}
+
+pub(crate) fn local_midnight(date: NaiveDate) -> DateTimeTimestamp {
+    let midnight = date.and_hms_opt(0, 0, 0).expect("midnight exists");
+    // Days starting in a DST gap begin at the first valid time.
+    let start = Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .unwrap_or_else(|| Local.from_utc_datetime(&midnight));
+    DateTimeTimestamp::from_timestamp_millis(start.timestamp_millis())
+}
+
+/// Collapses whitespace so the preview fits on one line, then keeps `length` characters.
+pub(crate) fn preview_text(body: &str, length: usize) -> String {
+    body.split_whitespace()
+        .flat_map(|word| std::iter::once(' ').chain(word.chars()))
+        .skip(1)
+        .take(length)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::preview_text;
+
+    #[test]
+    fn test_preview_text() {
+        assert_eq!("a b c", preview_text("  a\n\n b\tc  ", 10));
+        assert_eq!("中文 t", preview_text("中文\ntest", 4));
+        assert_eq!("", preview_text("", 4));
+    }
+}
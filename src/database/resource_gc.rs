@@ -0,0 +1,37 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::Resource;
+
+use super::note_links::is_item_id;
+
+/// Returned by `Database::delete_orphan_resources`.
+#[derive(Debug, Clone, Default)]
+pub struct ResourceGcReport {
+    /// Recorded in `deleted_items`, so they are deleted from the sync target too.
+    pub deleted_resources: Vec<Resource>,
+    /// Files of the resource directory without a resource, e.g. left by deletions pulled from other devices.
+    pub deleted_files: Vec<PathBuf>,
+    /// Size of the removed files.
+    pub reclaimed_bytes: u64,
+}
+
+/// Returns the size of the removed file, a missing file counts as empty.
+pub(crate) fn remove_file(path: &Path) -> io::Result<u64> {
+    let size = match fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    fs::remove_file(path)?;
+    Ok(size)
+}
+
+/// The id of the resource stored in the file, if it is named like `Resource::resource_file_path`.
+pub(crate) fn resource_id_of_file(path: &Path) -> Option<&str> {
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .filter(|stem| is_item_id(stem))
+}
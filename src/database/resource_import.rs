@@ -0,0 +1,156 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{new_id, Resource};
+
+/// Enough for the magic bytes of every format known by `infer`.
+const SNIFF_SIZE: usize = 8192;
+
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+const DEFAULT_MIME: &str = "application/octet-stream";
+
+/// Returned by `Database::import_resource`.
+#[derive(Debug, Clone)]
+pub struct ImportedResource {
+    pub resource: Resource,
+    /// An existing resource had the same content, nothing was copied.
+    pub reused: bool,
+    /// To insert in the body of a note, see `Resource::markdown_link`.
+    pub markdown_link: String,
+}
+
+/// A resource file written under a temporary name, it is removed when dropped unless persisted.
+pub(crate) struct ImportedFile {
+    temp_path: Option<PathBuf>,
+    pub(crate) mime: String,
+    pub(crate) file_extension: String,
+    pub(crate) size: u64,
+    /// Hexadecimal SHA-256 of the content.
+    pub(crate) hash: String,
+}
+
+impl ImportedFile {
+    /// Copies `reader` into `resource_dir` while hashing it, `file_name` gives the extension
+    /// and the type when it cannot be sniffed from the content.
+    pub(crate) fn write(
+        mut reader: impl Read,
+        file_name: &str,
+        resource_dir: &Path,
+    ) -> io::Result<Self> {
+        fs::create_dir_all(resource_dir)?;
+        // Not named like a resource file, so `delete_orphan_resources` leaves it alone.
+        let temp_path = resource_dir.join(format!(".{}.part", new_id()));
+        let mut file = File::create(&temp_path)?;
+        let mut imported = Self {
+            temp_path: Some(temp_path),
+            mime: String::new(),
+            file_extension: String::new(),
+            size: 0,
+            hash: String::new(),
+        };
+        let mut head = Vec::with_capacity(SNIFF_SIZE);
+        (&mut reader)
+            .take(SNIFF_SIZE as u64)
+            .read_to_end(&mut head)?;
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0; COPY_BUFFER_SIZE];
+        let mut chunk = head.as_slice();
+        while !chunk.is_empty() {
+            file.write_all(chunk)?;
+            hasher.update(chunk);
+            imported.size += chunk.len() as u64;
+            let read = loop {
+                match reader.read(&mut buffer) {
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    result => break result?,
+                }
+            };
+            chunk = &buffer[..read];
+        }
+        file.sync_all()?;
+        imported.hash = format!("{:x}", hasher.finalize());
+        (imported.mime, imported.file_extension) = detect_type(&head, file_name);
+        Ok(imported)
+    }
+
+    /// Moves the file to `path`, the rename is atomic on the same file system.
+    pub(crate) fn persist(mut self, path: &Path) -> io::Result<()> {
+        if let Some(temp_path) = self.temp_path.take() {
+            fs::rename(temp_path, path)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for ImportedFile {
+    fn drop(&mut self) {
+        if let Some(temp_path) = self.temp_path.take() {
+            if let Err(e) = fs::remove_file(&temp_path) {
+                log::warn!("failed to remove {}: {}", temp_path.display(), e);
+            }
+        }
+    }
+}
+
+/// Hexadecimal SHA-256 of the file, like `ImportedFile::hash`.
+pub(crate) fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// The MIME type sniffed from the magic bytes, or guessed from the extension of `file_name`,
+/// and the lowercase extension of the file, or the usual one of the sniffed type.
+fn detect_type(head: &[u8], file_name: &str) -> (String, String) {
+    let extension = Path::new(file_name)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase)
+        .unwrap_or_default();
+    match infer::get(head) {
+        Some(kind) if extension.is_empty() => {
+            (kind.mime_type().to_string(), kind.extension().to_string())
+        }
+        Some(kind) => (kind.mime_type().to_string(), extension),
+        None => (mime_of_extension(&extension).to_string(), extension),
+    }
+}
+
+/// For the formats without magic bytes, mostly text.
+fn mime_of_extension(extension: &str) -> &'static str {
+    match extension {
+        "txt" | "text" | "log" => "text/plain",
+        "md" | "markdown" => "text/markdown",
+        "html" | "htm" => "text/html",
+        "xhtml" => "application/xhtml+xml",
+        "css" => "text/css",
+        "csv" => "text/csv",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "js" => "text/javascript",
+        _ => DEFAULT_MIME,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{detect_type, DEFAULT_MIME};
+
+    #[test]
+    fn test_detect_type() {
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        let pair = |mime: &str, extension: &str| (mime.to_string(), extension.to_string());
+        assert_eq!(pair("image/png", "png"), detect_type(png, "photo"));
+        assert_eq!(pair("image/png", "png"), detect_type(png, "photo.PNG"));
+        assert_eq!(pair("image/png", "bin"), detect_type(png, "photo.bin"));
+        assert_eq!(pair("text/markdown", "md"), detect_type(b"# Title", "a.md"));
+        assert_eq!(pair(DEFAULT_MIME, ""), detect_type(b"plain", "README"));
+    }
+}
@@ -0,0 +1,116 @@
+use std::{fs::File, io::Read, path::Path};
+
+/// Bump it whenever the extracted text changes, every resource is indexed again on the next start.
+pub(crate) const RESOURCE_TEXT_VERSION: &str = "1";
+
+/// Larger files are only indexed up to this size.
+const MAX_TEXT_SIZE: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextFormat {
+    Plain,
+    Html,
+}
+
+impl TextFormat {
+    fn of(mime: &str, file_extension: &str) -> Option<Self> {
+        let mime = mime.split(';').next().unwrap_or_default().trim();
+        match mime.to_ascii_lowercase().as_str() {
+            "text/html" | "application/xhtml+xml" => return Some(Self::Html),
+            "text/plain" | "text/markdown" | "text/x-markdown" | "text/csv"
+            | "application/json" => return Some(Self::Plain),
+            _ => {}
+        }
+        match file_extension.to_ascii_lowercase().as_str() {
+            "html" | "htm" | "xhtml" => Some(Self::Html),
+            "txt" | "md" | "markdown" | "csv" | "json" => Some(Self::Plain),
+            _ => None,
+        }
+    }
+}
+
+/// Returns `None` for resources that are not text based.
+pub(crate) fn extract_text(
+    mime: &str,
+    file_extension: &str,
+    path: &Path,
+) -> std::io::Result<Option<String>> {
+    let format = match TextFormat::of(mime, file_extension) {
+        Some(format) => format,
+        None => return Ok(None),
+    };
+    let mut bytes = Vec::new();
+    File::open(path)?
+        .take(MAX_TEXT_SIZE)
+        .read_to_end(&mut bytes)?;
+    let text = String::from_utf8_lossy(&bytes);
+    Ok(Some(match format {
+        TextFormat::Plain => text.into_owned(),
+        TextFormat::Html => html_to_text(&text),
+    }))
+}
+
+fn html_to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let tag_end = rest.find('>').map_or(rest.len(), |i| i + 1);
+        let tag = rest[1..tag_end].to_ascii_lowercase();
+        rest = &rest[tag_end..];
+        for skipped in ["script", "style"] {
+            if tag.starts_with(skipped) {
+                let close = format!("</{skipped}");
+                rest = match rest.to_ascii_lowercase().find(&close) {
+                    Some(i) => &rest[i..],
+                    None => "",
+                };
+            }
+        }
+        text.push(' ');
+    }
+    text.push_str(rest);
+    decode_entities(&text)
+}
+
+fn decode_entities(text: &str) -> String {
+    const ENTITIES: [(&str, &str); 6] = [
+        ("&nbsp;", " "),
+        ("&lt;", "<"),
+        ("&gt;", ">"),
+        ("&quot;", "\""),
+        ("&#39;", "'"),
+        ("&amp;", "&"),
+    ];
+    ENTITIES.iter().fold(text.to_string(), |text, (entity, c)| {
+        text.replace(entity, c)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{html_to_text, TextFormat};
+
+    #[test]
+    fn test_text_format() {
+        assert_eq!(
+            Some(TextFormat::Plain),
+            TextFormat::of("text/plain; charset=utf-8", "")
+        );
+        assert_eq!(
+            Some(TextFormat::Plain),
+            TextFormat::of("application/octet-stream", "MD")
+        );
+        assert_eq!(Some(TextFormat::Html), TextFormat::of("text/html", "html"));
+        assert_eq!(None, TextFormat::of("image/png", "png"));
+    }
+
+    #[test]
+    fn test_html_to_text() {
+        let html = "<html><head><style>p { color: red; }</style></head><body><p>Fish &amp; chips</p><script>let a = 1 < 2;</script>menu</body></html>";
+        let text = html_to_text(html);
+        let words: Vec<&str> = text.split_whitespace().collect();
+        assert_eq!(vec!["Fish", "&", "chips", "menu"], words);
+    }
+}
@@ -0,0 +1,224 @@
+use diesel::{
+    sql_types::{BigInt, Double, Integer, Text},
+    QueryableByName,
+};
+
+use crate::{ModelType, NoteSearchResult};
+
+use super::tokenizer::is_latin;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchBodyOption {
+    Highlight,
+    /// FTS5 only accepts `1..=64` tokens, larger values are clamped.
+    Snippet {
+        max_tokens: u8,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchHighlight {
+    pub open: String,
+    pub close: String,
+    pub ellipsis: String,
+}
+
+impl Default for SearchHighlight {
+    fn default() -> Self {
+        Self {
+            open: "<b>".to_string(),
+            close: "</b>".to_string(),
+            ellipsis: "…".to_string(),
+        }
+    }
+}
+
+/// Scores of demoted notes are multiplied by it.
+pub(crate) const DEMOTION_FACTOR: f64 = 0.5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchRanking {
+    /// bm25 weight of title matches, e.g. `10.0` ranks title hits above body hits.
+    pub title_weight: f64,
+    /// bm25 weight of body matches.
+    pub body_weight: f64,
+    /// Notes updated this many days ago get half the boost of notes updated now, `None` disables it.
+    pub recency_days: Option<f64>,
+    pub demote_conflicts: bool,
+    pub demote_completed_todos: bool,
+}
+
+impl Default for SearchRanking {
+    fn default() -> Self {
+        Self {
+            title_weight: 1.0,
+            body_weight: 1.0,
+            recency_days: None,
+            demote_conflicts: false,
+            demote_completed_todos: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchNotesRequest {
+    pub search_term: String,
+    pub body_option: Option<SearchBodyOption>,
+    pub highlight: SearchHighlight,
+    pub offset: i64,
+    /// `None` returns every match after `offset`.
+    pub limit: Option<i64>,
+    /// Retries with terms corrected from `notes_fts_vocab` when nothing matches.
+    pub fuzzy: bool,
+    /// Also matches notes linking to a text resource containing the term. The term is not in their
+    /// body, so `SearchBodyOption::Snippet` gives a snippet of the resource text and `Highlight` the
+    /// whole body.
+    pub include_resources: bool,
+    pub ranking: SearchRanking,
+}
+
+impl SearchNotesRequest {
+    pub fn new(search_term: impl Into<String>) -> Self {
+        Self {
+            search_term: search_term.into(),
+            body_option: None,
+            highlight: SearchHighlight::default(),
+            offset: 0,
+            limit: None,
+            fuzzy: false,
+            include_resources: false,
+            ranking: SearchRanking::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchNotesResponse {
+    /// Number of matches ignoring `offset` and `limit`.
+    pub total_count: i64,
+    pub notes: Vec<NoteSearchResult>,
+    /// "Did you mean" search terms, `notes` are the matches of the first one.
+    pub suggestions: Vec<String>,
+}
+
+/// Searches notes, folders, tags and resources at once, e.g. for quick open.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchItemsRequest {
+    pub search_term: String,
+    pub highlight: SearchHighlight,
+    pub offset: i64,
+    /// `None` returns every match after `offset`.
+    pub limit: Option<i64>,
+}
+
+impl SearchItemsRequest {
+    pub fn new(search_term: impl Into<String>) -> Self {
+        Self {
+            search_term: search_term.into(),
+            highlight: SearchHighlight::default(),
+            offset: 0,
+            limit: None,
+        }
+    }
+}
+
+#[derive(QueryableByName, Debug, Clone, PartialEq)]
+pub struct SearchItem {
+    /// One of `Note`, `Folder`, `Tag` and `Resource`.
+    #[diesel(sql_type = Integer)]
+    pub item_type: ModelType,
+    #[diesel(sql_type = Text)]
+    pub id: String,
+    /// Highlighted title.
+    #[diesel(sql_type = Text)]
+    pub title: String,
+    /// Highlighted excerpt of the best matching column, e.g. the body of a note.
+    #[diesel(sql_type = Text)]
+    pub snippet: String,
+    /// bm25 score divided by the one of the best match of the same type, 1.0 is the best and
+    /// better matches are higher. See `Database::search_items`.
+    #[diesel(sql_type = Double)]
+    pub score: f64,
+}
+
+/// Length of `SearchItem::snippet`.
+pub(crate) const SEARCH_ITEM_SNIPPET_TOKENS: i32 = 16;
+
+/// FTS5 tables of the items returned by `Database::search_items`.
+pub(crate) const ITEMS_FTS_TABLES: [(ModelType, &str); 4] = [
+    (ModelType::Note, "notes_fts"),
+    (ModelType::Folder, "folders_fts"),
+    (ModelType::Tag, "tags_fts"),
+    (ModelType::Resource, "resources_fts"),
+];
+
+#[derive(QueryableByName)]
+pub(crate) struct SearchCount {
+    #[diesel(sql_type = BigInt)]
+    pub count: i64,
+}
+
+#[derive(QueryableByName, Debug)]
+pub(crate) struct VocabTerm {
+    #[diesel(sql_type = Text)]
+    pub term: String,
+    /// Number of notes containing the term.
+    #[diesel(sql_type = BigInt)]
+    pub doc: i64,
+}
+
+/// Only plain Latin words are corrected, operators, phrases and prefixes are left as is.
+pub(crate) fn fuzzy_word(word: &str) -> Option<String> {
+    if matches!(word, "AND" | "OR" | "NOT" | "NEAR") {
+        return None;
+    }
+    if !word.chars().all(char::is_alphanumeric) || !is_latin(word) {
+        return None;
+    }
+    Some(word.to_lowercase())
+}
+
+pub(crate) fn max_edit_distance(word: &str) -> usize {
+    match word.chars().count() {
+        0..=2 => 0,
+        3..=5 => 1,
+        _ => 2,
+    }
+}
+
+/// Picks the closest term, the most common one wins a tie.
+pub(crate) fn best_correction<'a>(word: &str, terms: &'a [VocabTerm]) -> Option<&'a str> {
+    let max_distance = max_edit_distance(word);
+    terms
+        .iter()
+        .map(|t| (strsim::damerau_levenshtein(word, &t.term), t))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by(|(d1, t1), (d2, t2)| d1.cmp(d2).then(t2.doc.cmp(&t1.doc)))
+        .map(|(_, t)| t.term.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{best_correction, fuzzy_word, VocabTerm};
+
+    #[test]
+    fn test_best_correction() {
+        let terms: Vec<VocabTerm> = [("hello", 1), ("help", 3), ("world", 2)]
+            .into_iter()
+            .map(|(term, doc)| VocabTerm {
+                term: term.to_string(),
+                doc,
+            })
+            .collect();
+        assert_eq!(Some("hello"), best_correction("helol", &terms));
+        assert_eq!(Some("world"), best_correction("wrold", &terms));
+        assert_eq!(Some("help"), best_correction("helo", &terms));
+        assert_eq!(None, best_correction("rust", &terms));
+        assert_eq!(None, best_correction("he", &terms));
+
+        assert_eq!(Some("hello".to_string()), fuzzy_word("Hello"));
+        assert_eq!(None, fuzzy_word("OR"));
+        assert_eq!(None, fuzzy_word("hel*"));
+        assert_eq!(None, fuzzy_word("中国"));
+    }
+}
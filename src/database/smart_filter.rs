@@ -0,0 +1,324 @@
+use chrono::{Datelike, Days, Months, NaiveDate};
+
+use crate::DateTimeTimestamp;
+
+use super::{
+    note_list::{local_date, local_midnight},
+    DatabaseError, DatabaseResult,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeRange {
+    /// Inclusive.
+    pub start: Option<DateTimeTimestamp>,
+    /// Exclusive.
+    pub end: Option<DateTimeTimestamp>,
+}
+
+impl TimeRange {
+    pub fn is_empty(&self) -> bool {
+        self.start.is_none() && self.end.is_none()
+    }
+}
+
+/// The parsed `SmartFilter::query`, e.g. `rust tag:work -tag:done notebook:Inbox due:day+7`.
+///
+/// Filters are `key:value` words, a leading `-` negates them and values containing spaces are quoted:
+/// - `tag:` a tag title or path, also matching the tags nested under it;
+/// - `notebook:` a folder title, also matching its subfolders;
+/// - `created:`, `updated:` and `due:` on or after a date, before it when negated. Dates are
+///   `YYYY`, `YYYYMM`, `YYYYMMDD` or relative like `day`, `day-1`, `week+2`, `month` and `year`;
+/// - `type:note` or `type:todo`;
+/// - `iscompleted:0` or `iscompleted:1`.
+///
+/// The other words are searched in the titles and bodies: a quoted phrase matches consecutive words,
+/// `word*` matches a prefix and `-word` excludes the notes containing it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SmartFilterQuery {
+    /// FTS5 query a note must match, empty matches every note.
+    pub text: String,
+    /// FTS5 query a note must not match, empty excludes none.
+    pub excluded_text: String,
+    /// A note must have all of them.
+    pub tags: Vec<String>,
+    pub excluded_tags: Vec<String>,
+    /// A note must be in one of them.
+    pub notebooks: Vec<String>,
+    pub created: TimeRange,
+    pub updated: TimeRange,
+    /// Only to-dos with a due date match it.
+    pub due: TimeRange,
+    pub is_todo: Option<bool>,
+    /// Only to-dos match it.
+    pub is_completed: Option<bool>,
+}
+
+impl SmartFilterQuery {
+    pub fn parse(query: &str) -> DatabaseResult<Self> {
+        Self::parse_at(query, DateTimeTimestamp::now())
+    }
+
+    /// Relative dates are resolved from `now`.
+    pub(crate) fn parse_at(query: &str, now: DateTimeTimestamp) -> DatabaseResult<Self> {
+        let mut parsed = Self::default();
+        let mut text = Vec::new();
+        let mut excluded_text = Vec::new();
+        for word in split_words(query) {
+            let (negated, filter) = match word.strip_prefix('-') {
+                Some(filter) if !filter.is_empty() => (true, filter),
+                _ => (false, word),
+            };
+            let (key, value) = filter.split_once(':').unwrap_or_default();
+            let key = key.to_lowercase();
+            if !matches!(
+                key.as_str(),
+                "tag" | "notebook" | "created" | "updated" | "due" | "type" | "iscompleted"
+            ) {
+                let terms = if negated {
+                    &mut excluded_text
+                } else {
+                    &mut text
+                };
+                terms.extend(fts_term(filter));
+                continue;
+            }
+            let value = value.trim_matches('"');
+            if value.is_empty() {
+                return Err(invalid(format!("missing value of {key:?}")));
+            }
+            match key.as_str() {
+                "tag" if negated => parsed.excluded_tags.push(value.to_string()),
+                "tag" => parsed.tags.push(value.to_string()),
+                "notebook" if negated => {
+                    return Err(invalid("\"notebook\" cannot be negated".to_string()))
+                }
+                "notebook" => parsed.notebooks.push(value.to_string()),
+                "created" | "updated" | "due" => {
+                    let time = parse_date(value, now)?;
+                    let range = match key.as_str() {
+                        "created" => &mut parsed.created,
+                        "updated" => &mut parsed.updated,
+                        _ => &mut parsed.due,
+                    };
+                    if negated {
+                        range.end = Some(time);
+                    } else {
+                        range.start = Some(time);
+                    }
+                }
+                "type" => {
+                    let is_todo = match value.to_lowercase().as_str() {
+                        "todo" => true,
+                        "note" => false,
+                        _ => return Err(invalid(format!("unknown type {value:?}"))),
+                    };
+                    parsed.is_todo = Some(is_todo != negated);
+                }
+                _ => {
+                    let is_completed = match value {
+                        "1" => true,
+                        "0" => false,
+                        _ => {
+                            return Err(invalid(format!(
+                                "iscompleted must be 0 or 1, not {value:?}"
+                            )))
+                        }
+                    };
+                    parsed.is_completed = Some(is_completed != negated);
+                }
+            }
+        }
+        parsed.text = text.join(" ");
+        parsed.excluded_text = excluded_text.join(" OR ");
+        Ok(parsed)
+    }
+}
+
+/// The word as an FTS5 string, so operators and column filters like `foo:bar` are searched as text.
+fn fts_term(word: &str) -> Option<String> {
+    let (word, prefix) = match word.strip_suffix('*') {
+        Some(word) => (word, "*"),
+        None => (word, ""),
+    };
+    let word = word.trim_matches('"');
+    if word.is_empty() {
+        return None;
+    }
+    Some(format!("\"{}\"{prefix}", word.replace('"', "\"\"")))
+}
+
+fn invalid(message: String) -> DatabaseError {
+    DatabaseError::InvalidSmartFilter(message)
+}
+
+/// Splits on whitespace outside of double quotes, the quotes are kept.
+fn split_words(query: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = None;
+    let mut quoted = false;
+    for (i, c) in query.char_indices() {
+        if c == '"' {
+            quoted = !quoted;
+        }
+        if c.is_whitespace() && !quoted {
+            if let Some(start) = start.take() {
+                words.push(&query[start..i]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(start) = start {
+        words.push(&query[start..]);
+    }
+    words
+}
+
+/// Local midnight of the date, or of the first day of the week, month or year.
+fn parse_date(value: &str, now: DateTimeTimestamp) -> DatabaseResult<DateTimeTimestamp> {
+    let error = || invalid(format!("invalid date {value:?}"));
+    if value.bytes().all(|b| b.is_ascii_digit()) {
+        let number = |range: std::ops::Range<usize>| -> DatabaseResult<u32> {
+            value[range].parse().map_err(|_| error())
+        };
+        let (year, month, day) = match value.len() {
+            4 => (number(0..4)?, 1, 1),
+            6 => (number(0..4)?, number(4..6)?, 1),
+            8 => (number(0..4)?, number(4..6)?, number(6..8)?),
+            _ => return Err(error()),
+        };
+        let date = NaiveDate::from_ymd_opt(year as i32, month, day).ok_or_else(error)?;
+        return Ok(local_midnight(date));
+    }
+    let split = value.find(['+', '-']).unwrap_or(value.len());
+    let (unit, offset) = value.split_at(split);
+    let offset: i64 = match offset {
+        "" => 0,
+        offset => offset
+            .strip_prefix('+')
+            .unwrap_or(offset)
+            .parse()
+            .map_err(|_| error())?,
+    };
+    let today = local_date(now);
+    let shift_days = |date: NaiveDate, days: i64| {
+        let magnitude = Days::new(days.unsigned_abs());
+        if days < 0 {
+            date.checked_sub_days(magnitude)
+        } else {
+            date.checked_add_days(magnitude)
+        }
+    };
+    let shift_months = |date: NaiveDate, months: i64| {
+        let magnitude = Months::new(u32::try_from(months.unsigned_abs()).ok()?);
+        if months < 0 {
+            date.checked_sub_months(magnitude)
+        } else {
+            date.checked_add_months(magnitude)
+        }
+    };
+    let date = match unit.to_lowercase().as_str() {
+        "day" => shift_days(today, offset),
+        "week" => {
+            let monday = shift_days(today, -i64::from(today.weekday().num_days_from_monday()));
+            monday.and_then(|monday| shift_days(monday, offset.checked_mul(7)?))
+        }
+        "month" => today
+            .with_day(1)
+            .and_then(|first| shift_months(first, offset)),
+        "year" => today
+            .with_ordinal(1)
+            .and_then(|first| shift_months(first, offset.checked_mul(12)?)),
+        _ => None,
+    };
+    date.map(local_midnight).ok_or_else(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::{SmartFilterQuery, TimeRange};
+    use crate::{
+        database::note_list::{local_day_bounds, local_midnight},
+        DatabaseError, DateTimeTimestamp,
+    };
+
+    #[test]
+    fn test_parse_smart_filter_query() {
+        let now = local_midnight(NaiveDate::from_ymd_opt(2024, 3, 14).unwrap());
+        let date = |y, m, d| local_midnight(NaiveDate::from_ymd_opt(y, m, d).unwrap());
+        let query = SmartFilterQuery::parse_at(
+            r#"rust "async fn" tag:work -tag:"on hold" notebook:Inbox created:202401 -due:day+1 TYPE:todo iscompleted:0 http://x"#,
+            now,
+        )
+        .unwrap();
+        assert_eq!(
+            SmartFilterQuery {
+                text: r#""rust" "async fn" "http://x""#.to_string(),
+                excluded_text: String::new(),
+                tags: vec!["work".to_string()],
+                excluded_tags: vec!["on hold".to_string()],
+                notebooks: vec!["Inbox".to_string()],
+                created: TimeRange {
+                    start: Some(date(2024, 1, 1)),
+                    end: None,
+                },
+                updated: TimeRange::default(),
+                due: TimeRange {
+                    start: None,
+                    end: Some(local_day_bounds(now).1),
+                },
+                is_todo: Some(true),
+                is_completed: Some(false),
+            },
+            query
+        );
+        let start = |value: &str| {
+            SmartFilterQuery::parse_at(&format!("updated:{value}"), now)
+                .unwrap()
+                .updated
+                .start
+                .unwrap()
+        };
+        assert_eq!(date(2024, 3, 14), start("day"));
+        assert_eq!(date(2024, 2, 29), start("day-14"));
+        assert_eq!(date(2024, 3, 11), start("week"));
+        assert_eq!(date(2024, 3, 4), start("week-1"));
+        assert_eq!(date(2023, 12, 1), start("month-3"));
+        assert_eq!(date(2025, 1, 1), start("year+1"));
+        assert_eq!(date(2023, 1, 1), start("2023"));
+        assert_eq!(date(2023, 5, 9), start("20230509"));
+        assert_eq!(
+            Some(false),
+            SmartFilterQuery::parse_at("-type:todo", now)
+                .unwrap()
+                .is_todo
+        );
+        let query =
+            SmartFilterQuery::parse_at(r#"foo:bar -done -"on hold" as* a"b"c -"#, now).unwrap();
+        assert_eq!(r#""foo:bar" "as"* "a""b""c" "-""#, query.text);
+        assert_eq!(r#""done" OR "on hold""#, query.excluded_text);
+        for query in [
+            "tag:",
+            "created:2023051",
+            "updated:20231301",
+            "due:tomorrow",
+            "type:task",
+            "iscompleted:yes",
+            "-notebook:Inbox",
+        ] {
+            assert!(
+                matches!(
+                    SmartFilterQuery::parse_at(query, now),
+                    Err(DatabaseError::InvalidSmartFilter(_))
+                ),
+                "{query}"
+            );
+        }
+        assert_eq!(
+            SmartFilterQuery::default(),
+            SmartFilterQuery::parse_at("  ", DateTimeTimestamp::zero()).unwrap()
+        );
+    }
+}
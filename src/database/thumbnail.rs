@@ -0,0 +1,132 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use image::{ImageFormat, ImageReader};
+
+use crate::{new_id, Resource};
+
+use super::{Database, DatabaseError, DatabaseResult};
+
+/// Larger requested sizes are clamped to it.
+pub const MAX_THUMBNAIL_SIZE: u32 = 1024;
+
+/// The thumbnails of `resource_dir/<id>.<ext>` are cached in `resource_dir-thumbnails`.
+pub(crate) fn thumbnail_dir(resource_dir: &Path) -> PathBuf {
+    let mut name = resource_dir
+        .file_name()
+        .map(|name| name.to_os_string())
+        .unwrap_or_default();
+    name.push("-thumbnails");
+    resource_dir.with_file_name(name)
+}
+
+/// `None` for the resources that cannot be decoded.
+fn image_format(resource: &Resource) -> Option<ImageFormat> {
+    if resource.encryption_blob_encrypted {
+        return None;
+    }
+    match resource.mime.to_ascii_lowercase().as_str() {
+        "image/png" => Some(ImageFormat::Png),
+        "image/jpeg" | "image/jpg" => Some(ImageFormat::Jpeg),
+        "image/gif" => Some(ImageFormat::Gif),
+        "image/webp" => Some(ImageFormat::WebP),
+        _ => None,
+    }
+}
+
+/// The name changes with `updated_time`, so a thumbnail of a previous version is never returned.
+fn thumbnail_file_name(resource: &Resource, size: u32) -> String {
+    format!(
+        "{}_{}_{}.png",
+        resource.id,
+        size,
+        resource.updated_time.timestamp_millis()
+    )
+}
+
+/// Removes the thumbnails of the resource, except the ones of the version `keep`.
+pub(crate) fn remove_thumbnails(
+    resource_dir: &Path,
+    resource_id: &str,
+    keep: Option<&Resource>,
+) -> io::Result<()> {
+    let entries = match fs::read_dir(thumbnail_dir(resource_dir)) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let prefix = format!("{resource_id}_");
+    let kept_suffix =
+        keep.map(|resource| format!("_{}.png", resource.updated_time.timestamp_millis()));
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        let is_kept = matches!(&kept_suffix, Some(suffix) if name.ends_with(suffix.as_str()));
+        if name.starts_with(&prefix) && !is_kept {
+            match fs::remove_file(entry.path()) {
+                // Removed by a concurrent call.
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                result => result?,
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Database {
+    /// A PNG fitting in `size`×`size` pixels of a PNG, JPEG, GIF or WebP resource, smaller images
+    /// are not enlarged. It is generated on the first request and cached until the resource changes.
+    pub fn load_thumbnail(&self, resource_id: &str, size: u32) -> DatabaseResult<PathBuf> {
+        let resource = self.load_resource(resource_id)?;
+        let format = match image_format(&resource) {
+            Some(format) => format,
+            None => return Err(DatabaseError::UnsupportedImage(resource.mime)),
+        };
+        let size = size.clamp(1, MAX_THUMBNAIL_SIZE);
+        let dir = thumbnail_dir(&self.resource_path);
+        let path = dir.join(thumbnail_file_name(&resource, size));
+        if path.exists() {
+            return Ok(path);
+        }
+        fs::create_dir_all(&dir)?;
+        remove_thumbnails(&self.resource_path, &resource.id, Some(&resource))?;
+        let mut reader = ImageReader::open(resource.resource_file_path(&self.resource_path))?;
+        reader.set_format(format);
+        let image = reader.decode()?;
+        let thumbnail = if image.width() <= size && image.height() <= size {
+            image
+        } else {
+            image.thumbnail(size, size)
+        };
+        // Written aside first, so a concurrent request never reads a partial file.
+        let temp_path = dir.join(format!(".{}.part", new_id()));
+        if let Err(e) = thumbnail.save_with_format(&temp_path, ImageFormat::Png) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(e.into());
+        }
+        fs::rename(&temp_path, &path)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::thumbnail_dir;
+
+    #[test]
+    fn test_thumbnail_dir() {
+        assert_eq!(
+            Path::new("/data/resources-thumbnails"),
+            thumbnail_dir(Path::new("/data/resources"))
+        );
+        assert_eq!(
+            Path::new("/data/resources-thumbnails"),
+            thumbnail_dir(Path::new("/data/resources/"))
+        );
+    }
+}
@@ -0,0 +1,450 @@
+use std::{borrow::Cow, sync::Arc};
+
+use parking_lot::RwLock;
+use pinyin::ToPinyin;
+use rust_stemmers::{Algorithm, Stemmer};
+use serde::{Deserialize, Serialize};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::{
+    jieba_tokenizer::JiebaTokenizerImpl,
+    sqlite3_fts5::{SqliteError, TokenizeReason, Tokenizer},
+};
+
+/// `notes_fts` is declared with `tokenize="jieba"`, so the name is kept for existing databases.
+pub const FTS_TOKENIZER_NAME: &str = "jieba";
+
+/// Bump it whenever the emitted tokens change, the index is rebuilt on the next start.
+pub const FTS_TOKENIZER_VERSION: &str = "2";
+
+pub type SharedTokenizerConfig = Arc<RwLock<TokenizerConfig>>;
+
+/// State shared by the tokenizers of every pooled connection.
+#[derive(Debug, Clone, Default)]
+pub struct FtsTokenizerGlobal {
+    pub config: SharedTokenizerConfig,
+    pub jieba: JiebaTokenizerImpl,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HanSegmenter {
+    Jieba,
+    NGram,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TokenizerConfig {
+    pub han_segmenter: HanSegmenter,
+    /// Used for kana, Hangul and `HanSegmenter::NGram`.
+    pub ngram_size: usize,
+    pub case_folding: bool,
+    pub remove_diacritics: bool,
+    /// Latin words are indexed by their Porter stem, the original word is kept as a colocated token.
+    pub english_stemming: bool,
+    pub english_stop_words: bool,
+    /// Han words are also indexed by their full pinyin and pinyin initials, e.g. `zhongguo` and `zg`.
+    pub pinyin: bool,
+}
+
+impl Default for TokenizerConfig {
+    fn default() -> Self {
+        Self {
+            han_segmenter: HanSegmenter::Jieba,
+            ngram_size: 2,
+            case_folding: false,
+            remove_diacritics: false,
+            english_stemming: false,
+            english_stop_words: false,
+            pinyin: false,
+        }
+    }
+}
+
+pub struct FtsTokenizer {
+    config: SharedTokenizerConfig,
+    inner: MultilingualTokenizerImpl,
+}
+
+impl Tokenizer for FtsTokenizer {
+    type Global = FtsTokenizerGlobal;
+
+    fn new(global: &Self::Global, _args: Vec<String>) -> Result<Self, SqliteError> {
+        Ok(Self {
+            config: global.config.clone(),
+            inner: MultilingualTokenizerImpl::new(global.jieba.clone()),
+        })
+    }
+
+    fn tokenize<TKF>(
+        &mut self,
+        reason: TokenizeReason,
+        text: &[u8],
+        mut push_token: TKF,
+    ) -> Result<(), SqliteError>
+    where
+        TKF: FnMut(&[u8], std::ops::Range<usize>, bool) -> Result<(), SqliteError>,
+    {
+        let text = String::from_utf8_lossy(text);
+        let config = self.config.read().clone();
+        let tokens = self.inner.tokenize(&config, &reason, &text);
+        for token in tokens {
+            let range = token.start..token.end;
+            push_token(token.word.as_bytes(), range, token.colocated)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FtsToken<'a> {
+    /// Normalized word of the token
+    pub word: Cow<'a, str>,
+    /// Bytes start position of the token
+    pub start: usize,
+    /// Bytes end position of the token
+    pub end: usize,
+    /// Whether the token is a synonym of the previous one
+    pub colocated: bool,
+}
+
+impl<'a> FtsToken<'a> {
+    fn new(word: &'a str, start: usize, end: usize) -> Self {
+        Self {
+            word: Cow::Borrowed(word),
+            start,
+            end,
+            colocated: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Script {
+    Han,
+    Kana,
+    Hangul,
+    Other,
+}
+
+impl Script {
+    fn of(c: char) -> Self {
+        match c {
+            '\u{3005}' | '\u{3007}' | '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}' => {
+                Self::Han
+            }
+            '\u{F900}'..='\u{FAFF}' | '\u{20000}'..='\u{3134F}' => Self::Han,
+            '\u{3040}'..='\u{30FF}' | '\u{31F0}'..='\u{31FF}' | '\u{FF66}'..='\u{FF9F}' => {
+                Self::Kana
+            }
+            '\u{1100}'..='\u{11FF}' | '\u{3130}'..='\u{318F}' | '\u{A960}'..='\u{A97F}' => {
+                Self::Hangul
+            }
+            '\u{AC00}'..='\u{D7FF}' => Self::Hangul,
+            _ => Self::Other,
+        }
+    }
+}
+
+pub struct MultilingualTokenizerImpl {
+    jieba: JiebaTokenizerImpl,
+    stemmer: Stemmer,
+}
+
+impl MultilingualTokenizerImpl {
+    pub fn new(jieba: JiebaTokenizerImpl) -> Self {
+        Self {
+            jieba,
+            stemmer: Stemmer::create(Algorithm::English),
+        }
+    }
+
+    pub fn tokenize<'a>(
+        &self,
+        config: &TokenizerConfig,
+        reason: &TokenizeReason,
+        text: &'a str,
+    ) -> Vec<FtsToken<'a>> {
+        let tokens = self.segment(config, text);
+        let is_query = matches!(reason, TokenizeReason::Query { .. });
+        // The last token of a prefix query is kept as typed, it matches the colocated original words.
+        let prefix_index = match reason {
+            TokenizeReason::Query { prefix: true } => tokens.len().checked_sub(1),
+            _ => None,
+        };
+        let mut output = Vec::with_capacity(tokens.len());
+        for (i, mut token) in tokens.into_iter().enumerate() {
+            normalize(config, &mut token.word);
+            if config.pinyin && !is_query && is_han(&token.word) {
+                let synonyms = pinyin_synonyms(&token);
+                output.push(token);
+                output.extend(synonyms);
+                continue;
+            }
+            if !is_latin(&token.word) || prefix_index == Some(i) {
+                output.push(token);
+                continue;
+            }
+            if config.english_stop_words && is_english_stop_word(&token.word) {
+                continue;
+            }
+            if config.english_stemming {
+                let stem = self.stemmer.stem(&token.word).into_owned();
+                if stem != token.word {
+                    output.push(FtsToken {
+                        word: Cow::Owned(stem),
+                        start: token.start,
+                        end: token.end,
+                        colocated: false,
+                    });
+                    if !is_query {
+                        token.colocated = true;
+                        output.push(token);
+                    }
+                    continue;
+                }
+            }
+            output.push(token);
+        }
+        output
+    }
+
+    fn segment<'a>(&self, config: &TokenizerConfig, text: &'a str) -> Vec<FtsToken<'a>> {
+        let mut tokens = Vec::new();
+        for (script, offset, run) in script_runs(text) {
+            match script {
+                Script::Han if config.han_segmenter == HanSegmenter::Jieba => {
+                    for token in self.jieba.tokenize(run) {
+                        tokens.push(FtsToken::new(
+                            token.word,
+                            offset + token.start,
+                            offset + token.end,
+                        ));
+                    }
+                }
+                Script::Han | Script::Kana | Script::Hangul => {
+                    push_ngrams(&mut tokens, run, offset, config.ngram_size);
+                }
+                Script::Other => {
+                    for (start, word) in run.unicode_word_indices() {
+                        let start = offset + start;
+                        tokens.push(FtsToken::new(word, start, start + word.len()));
+                    }
+                }
+            }
+        }
+        tokens
+    }
+}
+
+/// Splits the text into `(script, byte offset, run)` where every run is written in one script.
+fn script_runs(text: &str) -> Vec<(Script, usize, &str)> {
+    let mut runs = Vec::new();
+    let mut current: Option<(Script, usize)> = None;
+    for (i, c) in text.char_indices() {
+        let script = Script::of(c);
+        match current {
+            Some((s, _)) if s == script => {}
+            Some((s, start)) => {
+                runs.push((s, start, &text[start..i]));
+                current = Some((script, i));
+            }
+            None => current = Some((script, i)),
+        }
+    }
+    if let Some((s, start)) = current {
+        runs.push((s, start, &text[start..]));
+    }
+    runs
+}
+
+fn push_ngrams<'a>(tokens: &mut Vec<FtsToken<'a>>, run: &'a str, offset: usize, n: usize) {
+    let n = n.max(1);
+    let mut boundaries: Vec<usize> = run.char_indices().map(|(i, _)| i).collect();
+    boundaries.push(run.len());
+    let char_count = boundaries.len() - 1;
+    if char_count <= n {
+        tokens.push(FtsToken::new(run, offset, offset + run.len()));
+        return;
+    }
+    for i in 0..=(char_count - n) {
+        let (start, end) = (boundaries[i], boundaries[i + n]);
+        tokens.push(FtsToken::new(
+            &run[start..end],
+            offset + start,
+            offset + end,
+        ));
+    }
+}
+
+fn normalize(config: &TokenizerConfig, word: &mut Cow<str>) {
+    if (config.case_folding || is_latin(word)) && word.chars().any(char::is_uppercase) {
+        *word = Cow::Owned(word.to_lowercase());
+    }
+    if config.remove_diacritics && !word.is_ascii() {
+        let stripped: String = word
+            .nfd()
+            .filter(|c| !is_combining_mark(*c))
+            .nfc()
+            .collect();
+        if stripped != **word {
+            *word = Cow::Owned(stripped);
+        }
+    }
+}
+
+/// Returns the colocated full pinyin and initials tokens of a Han token.
+fn pinyin_synonyms(token: &FtsToken) -> Vec<FtsToken<'static>> {
+    let mut full = String::new();
+    let mut initials = String::new();
+    for pinyin in token.word.as_ref().to_pinyin().flatten() {
+        let plain = pinyin.plain();
+        full.push_str(plain);
+        initials.extend(plain.chars().next());
+    }
+    let mut synonyms = Vec::with_capacity(2);
+    for word in [full, initials] {
+        if !word.is_empty() && synonyms.iter().all(|t: &FtsToken| t.word != word) {
+            synonyms.push(FtsToken {
+                word: Cow::Owned(word),
+                start: token.start,
+                end: token.end,
+                colocated: true,
+            });
+        }
+    }
+    synonyms
+}
+
+fn is_han(word: &str) -> bool {
+    word.chars().any(|c| Script::of(c) == Script::Han)
+}
+
+pub(super) fn is_latin(word: &str) -> bool {
+    word.chars().any(|c| {
+        c.is_ascii_alphabetic() || matches!(c, '\u{C0}'..='\u{24F}' | '\u{1E00}'..='\u{1EFF}')
+    })
+}
+
+fn is_english_stop_word(word: &str) -> bool {
+    ENGLISH_STOP_WORDS.binary_search(&word).is_ok()
+}
+
+/// Sorted, used with `binary_search`.
+#[rustfmt::skip]
+const ENGLISH_STOP_WORDS: &[&str] = &[
+    "a", "about", "above", "after", "again", "against", "all", "am", "an", "and", "any", "are",
+    "as", "at", "be", "because", "been", "before", "being", "below", "between", "both", "but",
+    "by", "can", "did", "do", "does", "doing", "down", "during", "each", "few", "for", "from",
+    "further", "had", "has", "have", "having", "he", "her", "here", "hers", "herself", "him",
+    "himself", "his", "how", "i", "if", "in", "into", "is", "it", "its", "itself", "just", "me",
+    "more", "most", "my", "myself", "no", "nor", "not", "now", "of", "off", "on", "once", "only",
+    "or", "other", "our", "ours", "ourselves", "out", "over", "own", "same", "she", "should", "so",
+    "some", "such", "than", "that", "the", "their", "theirs", "them", "themselves", "then", "there",
+    "these", "they", "this", "those", "through", "to", "too", "under", "until", "up", "very",
+    "was", "we", "were", "what", "when", "where", "which", "while", "who", "whom", "why", "will",
+    "with", "you", "your", "yours", "yourself", "yourselves",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        super::{jieba_tokenizer::JiebaTokenizerImpl, sqlite3_fts5::TokenizeReason},
+        HanSegmenter, MultilingualTokenizerImpl, TokenizerConfig, ENGLISH_STOP_WORDS,
+    };
+
+    fn words(config: &TokenizerConfig, text: &str) -> Vec<String> {
+        words_with_reason(config, TokenizeReason::Document, text)
+    }
+
+    fn words_with_reason(
+        config: &TokenizerConfig,
+        reason: TokenizeReason,
+        text: &str,
+    ) -> Vec<String> {
+        let tokenizer = MultilingualTokenizerImpl::new(JiebaTokenizerImpl::new());
+        tokenizer
+            .tokenize(config, &reason, text)
+            .into_iter()
+            .map(|t| t.word.into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_multilingual_tokenizer_offsets() {
+        let tokenizer = MultilingualTokenizerImpl::new(JiebaTokenizerImpl::new());
+        let text = "我是中国人, hello wörld! ひらがな 한국어";
+        let tokens =
+            tokenizer.tokenize(&TokenizerConfig::default(), &TokenizeReason::Document, text);
+        for token in tokens {
+            assert_eq!(token.word, &text[token.start..token.end]);
+        }
+    }
+
+    #[test]
+    fn test_pinyin_synonyms() {
+        let config = TokenizerConfig {
+            pinyin: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            vec![
+                "我", "wo", "w", "是", "shi", "s", "中国", "zhongguo", "zg", "人", "ren", "r"
+            ],
+            words(&config, "我是中国人")
+        );
+        let query = TokenizeReason::Query { prefix: false };
+        assert_eq!(vec!["中国"], words_with_reason(&config, query, "中国"));
+    }
+
+    #[test]
+    fn test_english_stemming_and_stop_words() {
+        let mut sorted = ENGLISH_STOP_WORDS.to_vec();
+        sorted.sort_unstable();
+        assert_eq!(ENGLISH_STOP_WORDS, sorted.as_slice());
+
+        let config = TokenizerConfig::default();
+        assert_eq!(vec!["the", "note"], words(&config, "The Note"));
+        let config = TokenizerConfig {
+            english_stemming: true,
+            english_stop_words: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            vec!["run", "running", "note", "notes"],
+            words(&config, "The Running Notes")
+        );
+        let query = TokenizeReason::Query { prefix: false };
+        assert_eq!(vec!["run"], words_with_reason(&config, query, "running"));
+        let prefix_query = TokenizeReason::Query { prefix: true };
+        assert_eq!(
+            vec!["runn"],
+            words_with_reason(&config, prefix_query, "Runn")
+        );
+    }
+
+    #[test]
+    fn test_multilingual_tokenizer_scripts() {
+        let config = TokenizerConfig::default();
+        assert_eq!(vec!["中国", "hello"], words(&config, "中国hello"));
+        assert_eq!(vec!["ひら", "らが", "がな"], words(&config, "ひらがな"));
+        assert_eq!(vec!["한국", "국어", "공부"], words(&config, "한국어 공부"));
+        let config = TokenizerConfig {
+            han_segmenter: HanSegmenter::NGram,
+            ..Default::default()
+        };
+        assert_eq!(vec!["中国", "国人"], words(&config, "中国人"));
+    }
+
+    #[test]
+    fn test_multilingual_tokenizer_normalize() {
+        let config = TokenizerConfig {
+            case_folding: true,
+            remove_diacritics: true,
+            ..Default::default()
+        };
+        assert_eq!(vec!["creme", "brulee"], words(&config, "Crème Brûlée"));
+        assert_eq!(vec!["한국"], words(&config, "한국"));
+    }
+}
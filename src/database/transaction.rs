@@ -0,0 +1,177 @@
+use std::{path::Path, time::Duration};
+
+use diesel::SqliteConnection;
+
+use crate::{
+    sync::ForSyncSerializer, AbbrNote, Alarm, DateTimeTimestamp, DeletedItem, Folder, ItemChange,
+    Note, NoteLink, NoteResource, NoteTag, Placement, Resource, Setting, SmartFilter, Status,
+    SyncItem, Tag, TagNode,
+};
+
+use super::{
+    Database, DatabaseChange, DatabaseError, DatabaseResult, MarkdownExportReport, NoteListItem,
+    NoteListRequest, NoteListResponse, TodoFilter, UpdateSource,
+};
+
+/// Handle passed to `Database::transaction`, it exposes the same CRUD as `Database` on a single connection.
+pub struct Transaction<'a> {
+    pub(super) db: &'a Database,
+    pub(super) conn: &'a mut SqliteConnection,
+    /// Sent to the subscribers after the commit.
+    pub(super) changes: Vec<DatabaseChange>,
+}
+
+impl Database {
+    /// Runs `f` in an immediate transaction, it is rolled back when `f` returns an error.
+    ///
+    /// Use the handle inside `f`, `Database` methods would wait for the transaction to finish.
+    pub fn transaction<T, F>(&self, f: F) -> DatabaseResult<T>
+    where
+        F: FnOnce(&mut Transaction) -> DatabaseResult<T>,
+    {
+        let mut conn = self.connection_pool.get()?;
+        let (result, changes) = conn.immediate_transaction(|conn| {
+            let mut tx = Transaction {
+                db: self,
+                conn,
+                changes: Vec::new(),
+            };
+            let result = f(&mut tx)?;
+            Ok::<_, DatabaseError>((result, tx.changes))
+        })?;
+        for change in changes {
+            // Only fails without subscribers.
+            let _ = self.changes.send(change);
+        }
+        Ok(result)
+    }
+
+    /// Like `transaction` without `BEGIN`, for reads that don't need to block writers.
+    fn read<T, F>(&self, f: F) -> DatabaseResult<T>
+    where
+        F: FnOnce(&mut Transaction) -> DatabaseResult<T>,
+    {
+        let mut conn = self.connection_pool.get()?;
+        f(&mut Transaction {
+            db: self,
+            conn: &mut conn,
+            changes: Vec::new(),
+        })
+    }
+}
+
+/// Forwards `Database` methods to the `Transaction` methods of the same name.
+macro_rules! forward_to_transaction {
+    ($via:ident => $(pub fn $name:ident(&self $(, $arg:ident: $ty:ty)*) -> $ret:ty;)*) => {
+        $(
+            pub fn $name(&self $(, $arg: $ty)*) -> $ret {
+                self.$via(|tx| tx.$name($($arg),*))
+            }
+        )*
+    };
+}
+
+impl Database {
+    forward_to_transaction! { transaction =>
+        pub fn insert_root_folder(&self, title: impl Into<String>) -> DatabaseResult<Folder>;
+        pub fn insert_folder_with_parent(&self, title: impl Into<String>, parent_id: impl Into<String>) -> DatabaseResult<Folder>;
+        pub fn replace_folder(&self, folder: &Folder, update_source: UpdateSource) -> DatabaseResult<()>;
+        pub fn move_folder(&self, id: &str, parent_id: Option<&str>) -> DatabaseResult<()>;
+        pub fn delete_folder(&self, id: &str, update_source: UpdateSource) -> DatabaseResult<()>;
+        pub fn insert_note_with_parent(&self, title: impl Into<String>, body: impl Into<String>, parent_id: impl Into<String>) -> DatabaseResult<Note>;
+        pub fn replace_note(&self, note: &Note, update_source: UpdateSource) -> DatabaseResult<()>;
+        pub fn update_note_body(&self, id: &str, body: &str) -> DatabaseResult<()>;
+        pub fn update_note_title(&self, id: &str, title: &str) -> DatabaseResult<()>;
+        pub fn set_note_todo(&self, id: &str, is_todo: bool) -> DatabaseResult<()>;
+        pub fn complete_todo(&self, id: &str, completed: bool) -> DatabaseResult<()>;
+        pub fn set_todo_due(&self, id: &str, due: Option<DateTimeTimestamp>) -> DatabaseResult<()>;
+        pub fn move_note(&self, id: &str, parent_id: &str) -> DatabaseResult<()>;
+        pub fn move_notes(&self, notes_id: &[&str], parent_id: &str) -> DatabaseResult<()>;
+        pub fn reorder_note(&self, id: &str, anchor_id: &str, placement: Placement) -> DatabaseResult<()>;
+        pub fn delete_note(&self, id: &str, update_source: UpdateSource) -> DatabaseResult<()>;
+        pub fn delete_notes(&self, notes_id: &[&str]) -> DatabaseResult<()>;
+        pub fn set_alarm_fired(&self, id: i64) -> DatabaseResult<()>;
+        pub fn reindex_note_links(&self) -> DatabaseResult<()>;
+        pub fn delete_item_changes_until(&self, counter: i64) -> DatabaseResult<()>;
+        pub fn set_sync_item_up_to_data(&self, item_id: &str) -> DatabaseResult<()>;
+        pub fn delete_sync_item(&self, item_id: &str) -> DatabaseResult<()>;
+        pub fn delete_sync_items(&self, item_id: &[&str]) -> DatabaseResult<()>;
+        pub fn delete_deleted_item(&self, deleted_item: DeletedItem) -> DatabaseResult<()>;
+        pub fn replace_setting(&self, key: &str, value: &str) -> DatabaseResult<()>;
+        pub fn delete_setting(&self, key: &str) -> DatabaseResult<()>;
+        pub fn get_client_id(&self) -> DatabaseResult<String>;
+        pub fn replace_tag(&self, tag: &Tag, update_source: UpdateSource) -> DatabaseResult<()>;
+        pub fn insert_tag_path(&self, path: &str) -> DatabaseResult<Tag>;
+        pub fn move_tag(&self, id: &str, parent_id: Option<&str>) -> DatabaseResult<()>;
+        pub fn rename_tag(&self, id: &str, title: &str) -> DatabaseResult<Tag>;
+        pub fn merge_tags(&self, sources: &[&str], target: &str) -> DatabaseResult<()>;
+        pub fn merge_duplicate_tags(&self) -> DatabaseResult<usize>;
+        pub fn delete_unused_tags(&self) -> DatabaseResult<usize>;
+        pub fn delete_tag(&self, id: &str, update_source: UpdateSource) -> DatabaseResult<()>;
+        pub fn add_tag_on_note(&self, note_id: &str, tag_id: &str) -> DatabaseResult<()>;
+        pub fn replace_note_tag(&self, note_tag: &NoteTag, update_source: UpdateSource) -> DatabaseResult<()>;
+        pub fn delete_note_tag(&self, id: &str, update_source: UpdateSource) -> DatabaseResult<()>;
+        pub fn delete_note_tag_by_note_id_and_tag_id(&self, note_id: &str, tag_id: &str) -> DatabaseResult<()>;
+        pub fn delete_note_tags(&self, ids: &[&str], update_source: UpdateSource) -> DatabaseResult<()>;
+        pub fn delete_note_tags_by_note_id(&self, note_id: &str, update_source: UpdateSource) -> DatabaseResult<()>;
+        pub fn delete_note_tag_by_note_ids(&self, note_id: &[&str], update_source: UpdateSource) -> DatabaseResult<()>;
+        pub fn insert_smart_filter(&self, title: &str, query: &str) -> DatabaseResult<SmartFilter>;
+        pub fn replace_smart_filter(&self, smart_filter: &SmartFilter, update_source: UpdateSource) -> DatabaseResult<()>;
+        pub fn delete_smart_filter(&self, id: &str, update_source: UpdateSource) -> DatabaseResult<()>;
+        pub fn replace_resource(&self, resource: &Resource, update_source: UpdateSource) -> DatabaseResult<()>;
+        pub fn reindex_resource_texts(&self) -> DatabaseResult<()>;
+        pub fn delete_resource(&self, id: &str, update_source: UpdateSource) -> DatabaseResult<()>;
+    }
+
+    forward_to_transaction! { read =>
+        pub fn load_folders(&self) -> DatabaseResult<Vec<Folder>>;
+        pub fn load_folder(&self, id: &str) -> DatabaseResult<Folder>;
+        pub fn load_subfolders(&self, id: &str) -> DatabaseResult<Vec<Folder>>;
+        pub fn folder_count(&self) -> DatabaseResult<i64>;
+        pub fn load_abbr_notes(&self, parent_id: Option<&str>) -> DatabaseResult<Vec<AbbrNote>>;
+        pub fn load_note_list(&self, request: &NoteListRequest) -> DatabaseResult<NoteListResponse>;
+        pub fn load_todos(&self, filter: TodoFilter) -> DatabaseResult<Vec<NoteListItem>>;
+        pub fn load_abbr_conflict_notes(&self) -> DatabaseResult<Vec<AbbrNote>>;
+        pub fn conflict_note_exists(&self) -> DatabaseResult<bool>;
+        pub fn load_note(&self, id: &str) -> DatabaseResult<Note>;
+        pub fn note_count(&self) -> DatabaseResult<i64>;
+        pub fn load_note_links(&self, note_id: &str) -> DatabaseResult<Vec<NoteLink>>;
+        pub fn load_linked_notes(&self, note_id: &str) -> DatabaseResult<Vec<AbbrNote>>;
+        pub fn load_backlinks(&self, id: &str) -> DatabaseResult<Vec<AbbrNote>>;
+        pub fn load_broken_links(&self) -> DatabaseResult<Vec<NoteLink>>;
+        pub fn load_note_resources(&self, note_id: &str) -> DatabaseResult<Vec<NoteResource>>;
+        pub fn load_alarms(&self) -> DatabaseResult<Vec<Alarm>>;
+        pub fn load_next_alarm(&self) -> DatabaseResult<Option<Alarm>>;
+        pub fn load_due_alarms(&self, time: DateTimeTimestamp) -> DatabaseResult<Vec<Alarm>>;
+        pub fn load_item_changes_since(&self, counter: i64, limit: Option<i64>) -> DatabaseResult<Vec<ItemChange>>;
+        pub fn latest_item_change_counter(&self) -> DatabaseResult<i64>;
+        pub fn load_sync_item(&self, item_id: &str) -> DatabaseResult<SyncItem>;
+        pub fn load_sync_items(&self, item_ids: &[&str]) -> DatabaseResult<Vec<SyncItem>>;
+        pub fn load_all_sync_items(&self) -> DatabaseResult<Vec<SyncItem>>;
+        pub fn load_need_upload_sync_items(&self) -> DatabaseResult<Vec<SyncItem>>;
+        pub fn load_sync_item_content(&self, sync_item: &SyncItem) -> DatabaseResult<ForSyncSerializer>;
+        pub fn load_deleted_items(&self) -> DatabaseResult<Vec<DeletedItem>>;
+        pub fn get_setting_value(&self, key: &str) -> DatabaseResult<Option<Setting>>;
+        pub fn load_tag(&self, id: &str) -> DatabaseResult<Tag>;
+        pub fn load_all_tags(&self) -> DatabaseResult<Vec<Tag>>;
+        pub fn load_tags(&self, ids: &[&str]) -> DatabaseResult<Vec<Tag>>;
+        pub fn tag_count(&self) -> DatabaseResult<i64>;
+        pub fn load_tag_path(&self, id: &str) -> DatabaseResult<String>;
+        pub fn load_tag_tree(&self) -> DatabaseResult<Vec<TagNode>>;
+        pub fn load_abbr_notes_with_tag(&self, tag_id: &str, include_descendants: bool) -> DatabaseResult<Vec<AbbrNote>>;
+        pub fn load_note_tag(&self, id: &str) -> DatabaseResult<NoteTag>;
+        pub fn load_all_note_tags(&self) -> DatabaseResult<Vec<NoteTag>>;
+        pub fn load_note_tag_on_note(&self, note_id: &str, tag_id: &str) -> DatabaseResult<NoteTag>;
+        pub fn get_note_tags(&self, note_id: &str) -> DatabaseResult<Vec<Tag>>;
+        pub fn note_tag_count(&self) -> DatabaseResult<i64>;
+        pub fn load_smart_filter(&self, id: &str) -> DatabaseResult<SmartFilter>;
+        pub fn load_smart_filters(&self) -> DatabaseResult<Vec<SmartFilter>>;
+        pub fn evaluate_smart_filter(&self, id: &str) -> DatabaseResult<Vec<NoteListItem>>;
+        pub fn evaluate_smart_filter_query(&self, query: &str) -> DatabaseResult<Vec<NoteListItem>>;
+        pub fn load_resource(&self, id: &str) -> DatabaseResult<Resource>;
+        pub fn resource_count(&self) -> DatabaseResult<i64>;
+        pub fn load_orphan_resources(&self, grace_period: Duration) -> DatabaseResult<Vec<Resource>>;
+        pub fn export_markdown(&self, dir: &Path) -> DatabaseResult<MarkdownExportReport>;
+        pub fn status(&self) -> DatabaseResult<Status>;
+    }
+}
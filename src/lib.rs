@@ -1,3 +1,4 @@
+mod alarm_scheduler;
 pub mod database;
 mod models;
 mod schema;
@@ -9,7 +10,15 @@ use std::{
     sync::Arc,
 };
 
-pub use database::{Database, DatabaseError, DatabaseResult, SearchBodyOption, UpdateSource};
+pub use alarm_scheduler::{AlarmEvent, AlarmScheduler};
+pub use database::{
+    AsyncDatabase, Database, DatabaseChange, DatabaseError, DatabaseResult, HanSegmenter,
+    ImportedResource, MarkdownExportReport, NoteListItem, NoteListRequest, NoteListResponse,
+    NoteSortField, ResourceGcReport, SearchBodyOption, SearchHighlight, SearchItem,
+    SearchItemsRequest, SearchNotesRequest, SearchNotesResponse, SearchRanking, SmartFilterQuery,
+    SortDirection, TimeRange, TodoFilter, TokenizerConfig, Transaction, UpdateSource,
+    EXPORT_RESOURCE_DIR, MAX_THUMBNAIL_SIZE,
+};
 pub use models::*;
 use parking_lot::RwLock;
 use sync::{
@@ -20,6 +29,7 @@ use sync::{
 #[derive(Debug)]
 pub struct RuslinData {
     pub db: Arc<Database>,
+    pub async_db: AsyncDatabase,
     pub sync_config: RwLock<Option<SyncConfig>>,
     pub resource_dir: PathBuf,
 }
@@ -34,6 +44,7 @@ impl RuslinData {
             None => None,
         });
         Ok(Self {
+            async_db: AsyncDatabase::new(db.clone()),
             db,
             sync_config,
             resource_dir: resource_dir.to_path_buf(),
@@ -59,7 +70,8 @@ impl RuslinData {
 
     pub async fn synchronize(&self, from_start: bool) -> SyncResult<SyncInfo> {
         let file_api_driver = self.get_file_api_driver().await?;
-        let synchronizer = Synchronizer::new(self.db.clone(), &self.resource_dir, file_api_driver);
+        let synchronizer =
+            Synchronizer::new(self.async_db.clone(), &self.resource_dir, file_api_driver);
         synchronizer.check_target_info_support().await?;
         synchronizer.start(from_start).await
     }
@@ -93,14 +105,16 @@ impl RuslinData {
                 let file_api_driver = Box::new(FileApiDriverJoplinServer::new(api));
                 file_api_driver.check_config().await?;
                 let synchronizer =
-                    Synchronizer::new(self.db.clone(), &self.resource_dir, file_api_driver);
+                    Synchronizer::new(self.async_db.clone(), &self.resource_dir, file_api_driver);
                 synchronizer.check_target_info_support().await?;
             }
         };
-        self.db.replace_setting(
-            Setting::FILE_API_SYNC_CONFIG,
-            &serde_json::to_string(&sync_config).expect("sync_config to_string error"),
-        )?;
+        self.async_db
+            .replace_setting(
+                Setting::FILE_API_SYNC_CONFIG.to_string(),
+                serde_json::to_string(&sync_config).expect("sync_config to_string error"),
+            )
+            .await?;
         self.sync_config.write().replace(sync_config);
         Ok(())
     }
@@ -1,13 +1,20 @@
+mod alarm;
 mod date_time;
 mod deleted_item;
 mod folder;
+mod item_change;
 mod note;
+mod note_link;
+mod note_resource;
 mod resource;
 mod setting;
+mod smart_filter;
 mod status;
 mod sync_item;
 mod tag;
+mod user_dictionary_word;
 
+pub use alarm::Alarm;
 pub use date_time::*;
 pub use deleted_item::{DeletedItem, NewDeletedItem};
 use diesel::{
@@ -19,13 +26,18 @@ use diesel::{
     AsExpression, FromSqlRow,
 };
 pub use folder::Folder;
-pub use note::{notes_fts, AbbrNote, Note, NoteFts};
+pub use item_change::{ChangeKind, ItemChange, NewItemChange};
+pub use note::{notes_fts, AbbrNote, Note, NoteFts, NoteSearchResult, Placement};
+pub use note_link::NoteLink;
+pub use note_resource::NoteResource;
 pub use resource::Resource;
 use serde_repr::{Deserialize_repr, Serialize_repr};
 pub use setting::{NewSetting, Setting};
+pub use smart_filter::SmartFilter;
 pub use status::Status;
 pub use sync_item::{NewSyncItem, SyncItem, SyncTarget};
-pub use tag::{NoteTag, NoteTagId, Tag};
+pub use tag::{NoteTag, NoteTagId, Tag, TagNode, TAG_PATH_SEPARATOR};
+pub use user_dictionary_word::UserDictionaryWord;
 
 #[derive(
     Eq,
@@ -49,14 +61,14 @@ pub enum ModelType {
     Tag = 5,
     NoteTag = 6,
     // Search = 7,
-    // Alarm = 8,
+    Alarm = 8,
     // MasterKey = 9,
-    // ItemChange = 10,
-    // NoteResource = 11,
+    ItemChange = 10,
+    NoteResource = 11,
     // ResourceLocalState = 12,
     // Revision = 13,
     // Migration = 14,
-    // SmartFilter = 15,
+    SmartFilter = 15,
     // Command = 16,
     Unsupported = -1,
 }
@@ -69,11 +81,26 @@ impl From<i32> for ModelType {
             4 => ModelType::Resource,
             5 => ModelType::Tag,
             6 => ModelType::NoteTag,
+            8 => ModelType::Alarm,
+            10 => ModelType::ItemChange,
+            11 => ModelType::NoteResource,
+            15 => ModelType::SmartFilter,
             _ => ModelType::Unsupported,
         }
     }
 }
 
+impl ModelType {
+    /// The path of an item on the sync target. Joplin clients only sync the `<id>.md` files, so the
+    /// types they don't know use `<id>.ruslin.md`, which they skip.
+    pub fn item_filepath(&self, item_id: &str) -> String {
+        match self {
+            Self::SmartFilter => format!("{item_id}.ruslin.md"),
+            _ => format!("{item_id}.md"),
+        }
+    }
+}
+
 impl FromSql<Integer, Sqlite> for ModelType {
     fn from_sql(bytes: RawValue<Sqlite>) -> deserialize::Result<Self> {
         match i32::from_sql(bytes)? {
@@ -82,6 +109,10 @@ impl FromSql<Integer, Sqlite> for ModelType {
             4 => Ok(ModelType::Resource),
             5 => Ok(ModelType::Tag),
             6 => Ok(ModelType::NoteTag),
+            8 => Ok(ModelType::Alarm),
+            10 => Ok(ModelType::ItemChange),
+            11 => Ok(ModelType::NoteResource),
+            15 => Ok(ModelType::SmartFilter),
             x => Err(format!("Unrecognized variant {x}").into()),
         }
     }
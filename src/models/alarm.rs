@@ -0,0 +1,15 @@
+use diesel::prelude::*;
+
+use crate::{schema::alarms, DateTimeTimestamp};
+
+/// Derived from the due date of an uncompleted to-do, local only like in Joplin.
+#[derive(Clone, Identifiable, Queryable, PartialEq, Eq, Debug)]
+#[diesel(primary_key(id))]
+#[diesel(table_name = alarms)]
+pub struct Alarm {
+    pub id: i64,
+    pub note_id: String,
+    pub trigger_time: DateTimeTimestamp,
+    /// Zero until the alarm has been fired by `AlarmScheduler`.
+    pub fired_time: DateTimeTimestamp,
+}
@@ -45,6 +45,10 @@ impl DateTimeTimestamp {
         Self(0)
     }
 
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+
     pub fn from_timestamp_millis(t: i64) -> Self {
         Self(t)
     }
@@ -15,7 +15,7 @@ pub struct DeletedItem {
 
 impl DeletedItem {
     pub fn filepath(&self) -> String {
-        format!("{}.md", self.item_id)
+        self.item_type.item_filepath(&self.item_id)
     }
 }
 
@@ -0,0 +1,101 @@
+use crate::{schema::item_changes, DateTimeTimestamp, ModelType, UpdateSource};
+use diesel::{
+    backend::RawValue,
+    deserialize::{self, FromSql},
+    prelude::*,
+    serialize::{self, IsNull, Output, ToSql},
+    sql_types::Integer,
+    sqlite::Sqlite,
+    AsExpression, FromSqlRow,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Integer)]
+#[repr(i32)]
+pub enum ChangeKind {
+    Created = 1,
+    Updated = 2,
+    Deleted = 3,
+}
+
+impl FromSql<Integer, Sqlite> for ChangeKind {
+    fn from_sql(bytes: RawValue<Sqlite>) -> deserialize::Result<Self> {
+        match i32::from_sql(bytes)? {
+            1 => Ok(ChangeKind::Created),
+            2 => Ok(ChangeKind::Updated),
+            3 => Ok(ChangeKind::Deleted),
+            x => Err(format!("Unrecognized variant {x}").into()),
+        }
+    }
+}
+
+impl ToSql<Integer, Sqlite> for ChangeKind {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(*self as i32);
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<Integer, Sqlite> for UpdateSource {
+    fn from_sql(bytes: RawValue<Sqlite>) -> deserialize::Result<Self> {
+        match i32::from_sql(bytes)? {
+            1 => Ok(UpdateSource::LocalEdit),
+            2 => Ok(UpdateSource::RemoteSync),
+            x => Err(format!("Unrecognized variant {x}").into()),
+        }
+    }
+}
+
+impl ToSql<Integer, Sqlite> for UpdateSource {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Sqlite>) -> serialize::Result {
+        out.set_value(match self {
+            UpdateSource::LocalEdit => 1,
+            UpdateSource::RemoteSync => 2,
+        });
+        Ok(IsNull::No)
+    }
+}
+
+#[derive(Clone, Identifiable, Queryable, PartialEq, Eq, Debug)]
+#[diesel(primary_key(id))]
+#[diesel(table_name = item_changes)]
+pub struct ItemChange {
+    /// Monotonic counter, see `Database::load_item_changes_since`.
+    pub id: i64,
+    pub item_type: ModelType,
+    pub item_id: String,
+    pub change_type: ChangeKind,
+    pub source: UpdateSource,
+    pub created_time: DateTimeTimestamp,
+}
+
+#[derive(Debug, Insertable)]
+#[diesel(table_name = item_changes)]
+pub struct NewItemChange<'a> {
+    pub item_type: ModelType,
+    pub item_id: &'a str,
+    pub change_type: ChangeKind,
+    pub source: UpdateSource,
+    pub created_time: DateTimeTimestamp,
+}
+
+impl<'a> NewItemChange<'a> {
+    pub fn new_items(
+        item_type: ModelType,
+        item_ids: &[&'a str],
+        change_type: ChangeKind,
+        source: UpdateSource,
+    ) -> Vec<Self> {
+        let created_time = DateTimeTimestamp::now();
+        item_ids
+            .iter()
+            .map(|item_id| Self {
+                item_type,
+                item_id,
+                change_type,
+                source,
+                created_time,
+            })
+            .collect()
+    }
+}
@@ -46,6 +46,35 @@ impl PartialEq for NoteFts {
     }
 }
 
+#[derive(Clone, Identifiable, QueryableByName, Debug, Serialize, Deserialize)]
+#[diesel(primary_key(id))]
+#[diesel(table_name = notes)]
+pub struct NoteSearchResult {
+    pub id: String,
+    pub parent_id: Option<String>,
+    pub title: String,
+    pub body: String,
+    pub user_updated_time: DateTimeTimestamp,
+    /// Ranking score, better matches are lower.
+    #[diesel(sql_type = diesel::sql_types::Double)]
+    pub score: f64,
+}
+
+impl PartialEq for NoteSearchResult {
+    fn eq(&self, other: &NoteSearchResult) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for NoteSearchResult {}
+
+/// Where `Database::reorder_note` puts a note relative to the anchor note.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    Before,
+    After,
+}
+
 #[derive(
     Clone, Identifiable, Insertable, AsChangeset, Queryable, Debug, Serialize, Deserialize,
 )]
@@ -65,8 +94,10 @@ pub struct Note {
     pub author: String,
     pub source_url: String,
     pub is_todo: bool,
-    pub todo_due: bool,
-    pub todo_completed: bool,
+    /// Zero when the to-do has no due date.
+    pub todo_due: DateTimeTimestamp,
+    /// Zero while the to-do is not completed.
+    pub todo_completed: DateTimeTimestamp,
     pub source: String,
     pub source_application: String,
     pub application_data: String,
@@ -111,8 +142,8 @@ impl Note {
             author: "".to_string(),
             source_url: "".to_string(),
             is_todo: false,
-            todo_due: false,
-            todo_completed: false,
+            todo_due: DateTimeTimestamp::zero(),
+            todo_completed: DateTimeTimestamp::zero(),
             source: "ruslin".to_string(),
             source_application: "app.ruslin.default".to_string(),
             application_data: "".to_string(),
@@ -197,8 +228,8 @@ impl SerializeForSync for Note {
         ser.serialize_str("author", &self.author);
         ser.serialize_str("source_url", &self.source_url);
         ser.serialize_bool("is_todo", self.is_todo);
-        ser.serialize_bool("todo_due", self.todo_due);
-        ser.serialize_bool("todo_completed", self.todo_completed);
+        ser.serialize_i64("todo_due", self.todo_due.timestamp_millis());
+        ser.serialize_i64("todo_completed", self.todo_completed.timestamp_millis());
         ser.serialize_str("source", &self.source);
         ser.serialize_str("source_application", &self.source_application);
         ser.serialize_str("application_data", &self.application_data);
@@ -234,8 +265,10 @@ impl DeserializeForSync for Note {
             author: des.get_opt_string("author").unwrap_or_default(),
             source_url: des.get_opt_string("source_url").unwrap_or_default(),
             is_todo: des.get_bool("is_todo")?,
-            todo_due: des.get_bool("todo_due")?,
-            todo_completed: des.get_bool("todo_completed")?,
+            todo_due: DateTimeTimestamp::from_timestamp_millis(des.get_i64("todo_due")?),
+            todo_completed: DateTimeTimestamp::from_timestamp_millis(
+                des.get_i64("todo_completed")?,
+            ),
             source: des.get_opt_string("source").unwrap_or_default(),
             source_application: des.get_opt_string("source_application").unwrap_or_default(),
             application_data: des.get_opt_string("application_data").unwrap_or_default(),
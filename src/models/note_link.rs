@@ -0,0 +1,11 @@
+use diesel::prelude::*;
+
+use crate::schema::note_links;
+
+/// A `:/<id>` reference in the body of a note, to a note or a resource. Derived from the body, not synced.
+#[derive(Clone, Insertable, Queryable, PartialEq, Eq, Hash, Debug)]
+#[diesel(table_name = note_links)]
+pub struct NoteLink {
+    pub source_id: String,
+    pub target_id: String,
+}
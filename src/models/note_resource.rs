@@ -0,0 +1,17 @@
+use diesel::prelude::*;
+
+use crate::{schema::note_resources, DateTimeTimestamp};
+
+/// A resource referenced by the body of a note, local only like in Joplin.
+#[derive(Clone, Identifiable, Queryable, PartialEq, Eq, Debug)]
+#[diesel(primary_key(id))]
+#[diesel(table_name = note_resources)]
+pub struct NoteResource {
+    pub id: i64,
+    pub note_id: String,
+    pub resource_id: String,
+    /// Cleared when the note stops referencing the resource or is deleted.
+    pub is_associated: bool,
+    /// When the association last changed, see `Database::load_orphan_resources`.
+    pub last_seen_time: DateTimeTimestamp,
+}
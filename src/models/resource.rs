@@ -75,6 +75,23 @@ impl Resource {
             .with_extension(&self.file_extension)
     }
 
+    /// `![title](:/id)` for images and `[title](:/id)` otherwise, like the links inserted by Joplin.
+    pub fn markdown_link(&self) -> String {
+        let mut title = String::with_capacity(self.title.len());
+        for c in self.title.chars() {
+            if matches!(c, '[' | ']' | '\\') {
+                title.push('\\');
+            }
+            title.push(c);
+        }
+        let prefix = if self.mime.starts_with("image/") {
+            "!"
+        } else {
+            ""
+        };
+        format!("{prefix}[{title}](:/{})", self.id)
+    }
+
     pub fn remote_path(&self) -> String {
         format!(".resource/{}", self.id)
     }
@@ -198,5 +215,18 @@ type_: 4";
             .unwrap_or_else(|_| panic!("unwrap error in {}:{}", file!(), line!()));
         let des_resource = Resource::dserialize(&des).expect("dserialize failed");
         assert_eq!(resource, des_resource);
+        assert_eq!(
+            "![example.png](:/a8693477a4a343f781f6e562b3148290)",
+            resource.markdown_link()
+        );
+        let pdf = Resource {
+            title: "notes [draft].pdf".to_string(),
+            mime: "application/pdf".to_string(),
+            ..resource
+        };
+        assert_eq!(
+            "[notes \\[draft\\].pdf](:/a8693477a4a343f781f6e562b3148290)",
+            pdf.markdown_link()
+        );
     }
 }
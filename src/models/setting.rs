@@ -13,6 +13,10 @@ impl Setting {
     pub const FILE_API_SYNC_CONFIG: &'static str = "file_api.sync_config";
     pub const FILE_API_DELTA_CONTEXT: &'static str = "file_api.delta_context";
     pub const CLIENT_ID: &'static str = "client_id";
+    pub const FTS_TOKENIZER_CONFIG: &'static str = "fts.tokenizer_config";
+    pub const FTS_TOKENIZER_VERSION: &'static str = "fts.tokenizer_version";
+    pub const FTS_RESOURCE_TEXT_VERSION: &'static str = "fts.resource_text_version";
+    pub const NOTE_LINKS_VERSION: &'static str = "note_links.version";
 }
 
 #[derive(Debug, Insertable)]
@@ -0,0 +1,167 @@
+use std::hash::{Hash, Hasher};
+
+use crate::{
+    new_id,
+    schema::smart_filters,
+    sync::{DeserializeForSync, ForSyncSerializer, SerializeForSync, SyncResult},
+    DateTimeTimestamp, ModelType,
+};
+use diesel::prelude::*;
+
+/// A saved search, see `SmartFilterQuery` for the syntax of `query`.
+///
+/// Synced as `<id>.ruslin.md`, so Joplin clients sharing the target skip it, see `ModelType::item_filepath`.
+#[derive(Clone, Identifiable, Insertable, Queryable, Eq, Debug)]
+#[diesel(primary_key(id))]
+#[diesel(table_name = smart_filters)]
+pub struct SmartFilter {
+    pub id: String,
+    pub title: String,
+    pub query: String,
+    pub created_time: DateTimeTimestamp,
+    pub updated_time: DateTimeTimestamp,
+    pub user_created_time: DateTimeTimestamp,
+    pub user_updated_time: DateTimeTimestamp,
+    pub encryption_cipher_text: String,
+    pub encryption_applied: bool,
+}
+
+impl SmartFilter {
+    pub fn new(title: impl Into<String>, query: impl Into<String>) -> Self {
+        let dt = DateTimeTimestamp::now();
+        Self {
+            id: new_id(),
+            title: title.into(),
+            query: query.into(),
+            created_time: dt,
+            updated_time: dt,
+            user_created_time: dt,
+            user_updated_time: dt,
+            encryption_cipher_text: String::new(),
+            encryption_applied: false,
+        }
+    }
+
+    pub fn updated(&self) -> Self {
+        let mut it = self.clone();
+        let dt = DateTimeTimestamp::now();
+        it.updated_time = dt;
+        it.user_updated_time = dt;
+        it
+    }
+
+    pub const SELECTION: (
+        smart_filters::columns::id,
+        smart_filters::columns::title,
+        smart_filters::columns::query,
+        smart_filters::columns::created_time,
+        smart_filters::columns::updated_time,
+        smart_filters::columns::user_created_time,
+        smart_filters::columns::user_updated_time,
+        smart_filters::columns::encryption_cipher_text,
+        smart_filters::columns::encryption_applied,
+    ) = (
+        smart_filters::id,
+        smart_filters::title,
+        smart_filters::query,
+        smart_filters::created_time,
+        smart_filters::updated_time,
+        smart_filters::user_created_time,
+        smart_filters::user_updated_time,
+        smart_filters::encryption_cipher_text,
+        smart_filters::encryption_applied,
+    );
+}
+
+impl Hash for SmartFilter {
+    fn hash<H: Hasher>(&self, hasher: &mut H) {
+        self.id.hash(hasher);
+    }
+}
+
+impl PartialEq for SmartFilter {
+    fn eq(&self, other: &SmartFilter) -> bool {
+        self.id == other.id
+    }
+}
+
+impl SerializeForSync for SmartFilter {
+    fn serialize(&self) -> ForSyncSerializer {
+        let mut ser = ForSyncSerializer::new(Some(&self.title), Some(&self.query));
+        ser.serialize_str("id", &self.id);
+        ser.serialize_datetime("created_time", self.created_time);
+        ser.serialize_datetime("updated_time", self.updated_time);
+        ser.serialize_datetime("user_created_time", self.user_created_time);
+        ser.serialize_datetime("user_updated_time", self.user_updated_time);
+        ser.serialize_str("encryption_cipher_text", &self.encryption_cipher_text);
+        ser.serialize_bool("encryption_applied", self.encryption_applied);
+        ser.serialize_type("type_", ModelType::SmartFilter);
+        ser
+    }
+}
+
+impl DeserializeForSync for SmartFilter {
+    fn dserialize(des: &crate::sync::ForSyncDeserializer) -> SyncResult<Self> {
+        assert!(des.r#type == ModelType::SmartFilter);
+        Ok(Self {
+            id: des.get_string("id")?,
+            title: des.title.to_string(),
+            query: des.body.clone().unwrap_or_default(),
+            created_time: des.get_date_time_timestamp("created_time")?,
+            updated_time: des.get_date_time_timestamp("updated_time")?,
+            user_created_time: des.get_date_time_timestamp("user_created_time")?,
+            user_updated_time: des.get_date_time_timestamp("user_updated_time")?,
+            encryption_cipher_text: des
+                .get_opt_string("encryption_cipher_text")
+                .unwrap_or_default(),
+            encryption_applied: des.get_bool("encryption_applied")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::{
+        sync::{DeserializeForSync, ForSyncDeserializer, SerializeForSync},
+        DateTimeRFC333, DateTimeTimestamp, SmartFilter,
+    };
+
+    #[test]
+    fn test_serialize_and_dserialize_smart_filter() {
+        let dt = DateTimeRFC333::from_raw_str("2022-11-20T05:27:50.593Z");
+        let dt: DateTimeTimestamp = dt.into();
+        let smart_filter = SmartFilter {
+            id: "0185b3b4d7a47e2a9b1cd3b1a2a4f0c1".to_string(),
+            title: "Work to-dos".to_string(),
+            query: "tag:work type:todo iscompleted:0".to_string(),
+            created_time: dt,
+            updated_time: dt,
+            user_created_time: dt,
+            user_updated_time: dt,
+            encryption_cipher_text: String::new(),
+            encryption_applied: false,
+        };
+        let expected_str = "Work to-dos
+
+tag:work type:todo iscompleted:0
+
+id: 0185b3b4d7a47e2a9b1cd3b1a2a4f0c1
+created_time: 2022-11-20T05:27:50.593Z
+updated_time: 2022-11-20T05:27:50.593Z
+user_created_time: 2022-11-20T05:27:50.593Z
+user_updated_time: 2022-11-20T05:27:50.593Z
+encryption_cipher_text: 
+encryption_applied: 0
+type_: 15";
+        assert_eq!(expected_str, smart_filter.serialize().as_str());
+        let des = ForSyncDeserializer::from_str(expected_str)
+            .unwrap_or_else(|_| panic!("unwrap error in {}:{}", file!(), line!()));
+        let des_smart_filter = SmartFilter::dserialize(&des)
+            .unwrap_or_else(|_| panic!("unwrap error in {}:{}", file!(), line!()));
+        assert_eq!(smart_filter.title, des_smart_filter.title);
+        assert_eq!(smart_filter.query, des_smart_filter.query);
+        assert_eq!(smart_filter.updated_time, des_smart_filter.updated_time);
+    }
+}
@@ -81,7 +81,7 @@ pub struct SyncItem {
 
 impl SyncItem {
     pub fn filepath(&self) -> String {
-        format!("{}.md", self.item_id)
+        self.item_type.item_filepath(&self.item_id)
     }
 
     pub fn never_synced(&self) -> bool {
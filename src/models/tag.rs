@@ -1,4 +1,7 @@
-use std::hash::{Hash, Hasher};
+use std::{
+    collections::{HashMap, HashSet},
+    hash::{Hash, Hasher},
+};
 
 use crate::{
     new_id,
@@ -41,6 +44,13 @@ impl Tag {
         }
     }
 
+    pub fn new_with_parent(title: &str, parent_id: Option<String>) -> Self {
+        Self {
+            parent_id,
+            ..Self::new(title)
+        }
+    }
+
     pub fn updated(&self) -> Self {
         let mut it = self.clone();
         let dt = DateTimeTimestamp::now();
@@ -74,6 +84,62 @@ impl Tag {
     );
 }
 
+/// Separates the titles of nested tags, e.g. `project/alpha`.
+pub const TAG_PATH_SEPARATOR: char = '/';
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TagNode {
+    pub tag: Tag,
+    pub children: Vec<TagNode>,
+}
+
+impl TagNode {
+    /// Tags whose parent is missing, or which are part of a cycle, become roots. Siblings are sorted by title.
+    pub fn build_tree(mut tags: Vec<Tag>) -> Vec<TagNode> {
+        tags.sort_by_cached_key(|tag| tag.title.to_lowercase());
+        let mut children: HashMap<&str, Vec<&Tag>> = HashMap::new();
+        let ids: HashSet<&str> = tags.iter().map(|tag| tag.id.as_str()).collect();
+        for tag in tags.iter() {
+            if let Some(parent_id) = tag.parent_id.as_deref().filter(|id| ids.contains(id)) {
+                children.entry(parent_id).or_default().push(tag);
+            }
+        }
+        let mut visited = HashSet::new();
+        let mut roots: Vec<TagNode> = tags
+            .iter()
+            .filter(|tag| match tag.parent_id.as_deref() {
+                Some(parent_id) => !ids.contains(parent_id),
+                None => true,
+            })
+            .map(|tag| Self::build_node(tag, &children, &mut visited))
+            .collect();
+        for tag in tags.iter() {
+            if !visited.contains(tag.id.as_str()) {
+                roots.push(Self::build_node(tag, &children, &mut visited));
+            }
+        }
+        roots
+    }
+
+    fn build_node<'a>(
+        tag: &'a Tag,
+        children: &HashMap<&str, Vec<&'a Tag>>,
+        visited: &mut HashSet<&'a str>,
+    ) -> TagNode {
+        visited.insert(&tag.id);
+        let mut nodes = Vec::new();
+        for child in children.get(tag.id.as_str()).into_iter().flatten() {
+            if !visited.contains(child.id.as_str()) {
+                nodes.push(Self::build_node(child, children, visited));
+            }
+        }
+        TagNode {
+            tag: tag.clone(),
+            children: nodes,
+        }
+    }
+}
+
 impl Hash for Tag {
     fn hash<H: Hasher>(&self, hasher: &mut H) {
         self.id.hash(hasher);
@@ -203,7 +269,8 @@ impl DeserializeForSync for Tag {
                 .unwrap_or_default(),
             encryption_applied: des.get_bool("encryption_applied")?,
             is_shared: des.get_bool("is_shared")?,
-            parent_id: des.get_opt_string("parent_id"),
+            // Joplin writes an empty `parent_id` for root tags.
+            parent_id: des.get_opt_string("parent_id").filter(|id| !id.is_empty()),
         })
     }
 }
@@ -251,7 +318,7 @@ mod tests {
     use std::str::FromStr;
 
     use crate::{
-        models::tag::{NoteTag, Tag},
+        models::tag::{NoteTag, Tag, TagNode},
         sync::{DeserializeForSync, ForSyncDeserializer, SerializeForSync},
         DateTimeRFC333, DateTimeTimestamp,
     };
@@ -294,6 +361,35 @@ type_: 5";
         assert_eq!(tag, des_tag);
     }
 
+    #[test]
+    fn test_build_tag_tree() {
+        let tag = |title: &str, parent: Option<&Tag>| {
+            Tag::new_with_parent(title, parent.map(|p| p.id.clone()))
+        };
+        let project = tag("project", None);
+        let beta = tag("beta", Some(&project));
+        let alpha = tag("Alpha", Some(&project));
+        let orphan = tag("orphan", Some(&Tag::new("deleted")));
+        let mut cycle_a = tag("cycle_a", None);
+        let cycle_b = tag("cycle_b", Some(&cycle_a));
+        cycle_a.parent_id = Some(cycle_b.id.clone());
+        let tree = TagNode::build_tree(vec![
+            beta.clone(),
+            orphan.clone(),
+            project.clone(),
+            alpha.clone(),
+            cycle_a.clone(),
+            cycle_b.clone(),
+        ]);
+        let titles = |nodes: &[TagNode]| -> Vec<String> {
+            nodes.iter().map(|n| n.tag.title.clone()).collect()
+        };
+        assert_eq!(vec!["orphan", "project", "cycle_a"], titles(&tree));
+        assert_eq!(vec!["Alpha", "beta"], titles(&tree[1].children));
+        assert_eq!(vec!["cycle_b"], titles(&tree[2].children));
+        assert!(tree[2].children[0].children.is_empty());
+    }
+
     #[test]
     fn test_serialize_and_dserialize_note_tag() {
         let dt = DateTimeRFC333::from_raw_str("2023-01-01T02:33:24.063Z");
@@ -0,0 +1,23 @@
+use crate::schema::user_dictionary_words;
+use diesel::prelude::*;
+
+#[derive(Clone, Identifiable, Queryable, Insertable, PartialEq, Eq, Debug)]
+#[diesel(primary_key(word))]
+#[diesel(table_name = user_dictionary_words)]
+pub struct UserDictionaryWord {
+    pub word: String,
+    /// Jieba estimates a frequency that keeps the word together when it is `None`.
+    pub freq: Option<i32>,
+    /// Part of speech tag, e.g. `n`.
+    pub tag: Option<String>,
+}
+
+impl UserDictionaryWord {
+    pub fn new(word: impl Into<String>) -> Self {
+        Self {
+            word: word.into(),
+            freq: None,
+            tag: None,
+        }
+    }
+}
@@ -1,5 +1,14 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    alarms (id) {
+        id -> BigInt,
+        note_id -> Text,
+        trigger_time -> BigInt,
+        fired_time -> BigInt,
+    }
+}
+
 diesel::table! {
     deleted_items (id) {
         id -> Integer,
@@ -27,6 +36,34 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    item_changes (id) {
+        id -> BigInt,
+        item_type -> Integer,
+        item_id -> Text,
+        change_type -> Integer,
+        source -> Integer,
+        created_time -> BigInt,
+    }
+}
+
+diesel::table! {
+    note_links (source_id, target_id) {
+        source_id -> Text,
+        target_id -> Text,
+    }
+}
+
+diesel::table! {
+    note_resources (id) {
+        id -> BigInt,
+        note_id -> Text,
+        resource_id -> Text,
+        is_associated -> Bool,
+        last_seen_time -> BigInt,
+    }
+}
+
 diesel::table! {
     note_tags (id) {
         id -> Text,
@@ -57,8 +94,8 @@ diesel::table! {
         author -> Text,
         source_url -> Text,
         is_todo -> Bool,
-        todo_due -> Bool,
-        todo_completed -> Bool,
+        todo_due -> BigInt,
+        todo_completed -> BigInt,
         source -> Text,
         source_application -> Text,
         application_data -> Text,
@@ -75,6 +112,13 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    resource_hashes (resource_id) {
+        resource_id -> Text,
+        hash -> Text,
+    }
+}
+
 diesel::table! {
     resources (id) {
         id -> Text,
@@ -103,6 +147,20 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    smart_filters (id) {
+        id -> Text,
+        title -> Text,
+        query -> Text,
+        created_time -> BigInt,
+        updated_time -> BigInt,
+        user_created_time -> BigInt,
+        user_updated_time -> BigInt,
+        encryption_cipher_text -> Text,
+        encryption_applied -> Bool,
+    }
+}
+
 diesel::table! {
     sync_items (id) {
         id -> Integer,
@@ -133,13 +191,28 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    user_dictionary_words (word) {
+        word -> Text,
+        freq -> Nullable<Integer>,
+        tag -> Nullable<Text>,
+    }
+}
+
 diesel::allow_tables_to_appear_in_same_query!(
+    alarms,
     deleted_items,
     folders,
+    item_changes,
+    note_links,
+    note_resources,
     note_tags,
     notes,
+    resource_hashes,
     resources,
     settings,
+    smart_filters,
     sync_items,
     tags,
+    user_dictionary_words,
 );
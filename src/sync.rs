@@ -21,8 +21,8 @@ pub use serializer::{ForSyncSerializer, SerializeForSync};
 use tokio::{task::JoinSet, time::Instant};
 
 use crate::{
-    Database, DateTimeTimestamp, Folder, ModelType, Note, NoteTag, Resource, Setting, SyncItem,
-    Tag, UpdateSource,
+    AsyncDatabase, DateTimeTimestamp, Folder, ModelType, Note, NoteTag, Resource, Setting,
+    SmartFilter, SyncItem, Tag, UpdateSource,
 };
 
 use self::sync_target_info::SyncTargetInfo;
@@ -59,7 +59,7 @@ pub struct SyncInfo {
 }
 
 pub struct Synchronizer {
-    db: Arc<Database>,
+    db: AsyncDatabase,
     resource_dir: PathBuf,
     file_api_driver: Arc<Box<dyn FileApiDriver>>,
     // lock_handler: LockHandler,
@@ -74,13 +74,13 @@ pub struct Synchronizer {
 
 impl Synchronizer {
     pub fn new(
-        db: Arc<Database>,
+        db: impl Into<AsyncDatabase>,
         resource_dir: &Path,
         file_api_driver: Box<dyn FileApiDriver>,
     ) -> Self {
         let file_api_driver = Arc::new(file_api_driver);
         Self {
-            db,
+            db: db.into(),
             resource_dir: resource_dir.to_path_buf(),
             file_api_driver: file_api_driver.clone(),
             // lock_handler: LockHandler::new(file_api_driver),
@@ -120,6 +120,10 @@ impl Synchronizer {
         self.delete_remote(&mut sync_info).await?;
         self.upload(&mut sync_info).await?;
         self.delta(&mut sync_info, from_scratch).await?;
+        let merged_tags = self.db.merge_duplicate_tags().await?;
+        if merged_tags > 0 {
+            log::info!(target: LOG_TARGET, "merged {} duplicate tags", merged_tags);
+        }
         let elapsed = now.elapsed();
         sync_info.elapsed_time = elapsed.as_secs_f64();
         let elapsed = if elapsed.as_secs() >= 1 {
@@ -141,7 +145,7 @@ impl Synchronizer {
             target: LOG_TARGET,
             "starting the delete remote content task"
         );
-        let deleted_items = self.db.load_deleted_items()?;
+        let deleted_items = self.db.load_deleted_items().await?;
         let mut task_set = JoinSet::new();
         for item in deleted_items {
             let file_api_driver = self.file_api_driver.clone();
@@ -166,7 +170,7 @@ impl Synchronizer {
                 deleted_item.item_id,
                 deleted_item.item_type
             );
-            self.db.delete_deleted_item(deleted_item)?;
+            self.db.delete_deleted_item(deleted_item).await?;
             sync_info.delete_remote_count += 1;
         }
         Ok(())
@@ -174,7 +178,7 @@ impl Synchronizer {
 
     async fn upload(&self, sync_info: &mut SyncInfo) -> SyncResult<()> {
         log::info!(target: LOG_TARGET, "starting the upload local content task");
-        let need_upload_sync_items = self.db.load_need_upload_sync_items()?;
+        let need_upload_sync_items = self.db.load_need_upload_sync_items().await?;
         for item in need_upload_sync_items {
             let stat = self.file_api_driver.stat(&item.filepath()).await?;
             if stat.is_some() {
@@ -192,9 +196,10 @@ impl Synchronizer {
                     );
                     match remote_des.r#type {
                         ModelType::Note => {
-                            let local_note = self.db.load_note(&item.item_id)?;
+                            let local_note = self.db.load_note(item.item_id.clone()).await?;
                             let remote_note = Note::dserialize(&remote_des)?;
-                            self.create_conflict_note(&local_note, Some(&remote_note))?;
+                            self.create_conflict_note(&local_note, Some(&remote_note))
+                                .await?;
                             self.write_remote_to_local(&remote_des).await?;
                             sync_info.conflict_note_count += 1;
                         }
@@ -207,6 +212,10 @@ impl Synchronizer {
                         ModelType::Tag
                         | ModelType::NoteTag
                         | ModelType::Folder
+                        | ModelType::SmartFilter
+                        | ModelType::Alarm
+                        | ModelType::NoteResource
+                        | ModelType::ItemChange
                         | ModelType::Unsupported => {
                             // take the remote version
                             self.write_remote_to_local(&remote_des).await?;
@@ -222,7 +231,7 @@ impl Synchronizer {
                     );
                     // Case 2: remote.updated_time < local.sync_time -> updateRemote
                     self.upload_resource_if_needed(&item).await?;
-                    let upload_content = self.db.load_sync_item_content(&item)?;
+                    let upload_content = self.db.load_sync_item_content(item.clone()).await?;
                     self.file_api_driver
                         .put_text(&item.filepath(), upload_content.as_str())
                         .await?;
@@ -237,7 +246,7 @@ impl Synchronizer {
                 );
                 // Case 3: remote == None && first sync -> createRemote
                 self.upload_resource_if_needed(&item).await?;
-                let upload_content = self.db.load_sync_item_content(&item)?;
+                let upload_content = self.db.load_sync_item_content(item.clone()).await?;
                 self.file_api_driver
                     .put_text(&item.filepath(), upload_content.as_str())
                     .await?;
@@ -251,21 +260,25 @@ impl Synchronizer {
                 );
                 match item.item_type {
                     ModelType::Note => {
-                        let local_note = self.db.load_note(&item.item_id)?;
-                        self.create_conflict_note(&local_note, None)?;
-                        self.delete_local_by_sync(&item)?;
+                        let local_note = self.db.load_note(item.item_id.clone()).await?;
+                        self.create_conflict_note(&local_note, None).await?;
+                        self.delete_local_by_sync(&item).await?;
                         sync_info.conflict_note_count += 1;
                     }
                     ModelType::Resource => {
                         // TODO: handle conflict
-                        self.delete_local_by_sync(&item)?;
+                        self.delete_local_by_sync(&item).await?;
                         sync_info.other_conflict_count += 1;
                     }
                     ModelType::Tag
                     | ModelType::NoteTag
                     | ModelType::Folder
+                    | ModelType::SmartFilter
+                    | ModelType::Alarm
+                    | ModelType::NoteResource
+                    | ModelType::ItemChange
                     | ModelType::Unsupported => {
-                        self.delete_local_by_sync(&item)?;
+                        self.delete_local_by_sync(&item).await?;
                         sync_info.other_conflict_count += 1;
                     }
                 }
@@ -277,8 +290,10 @@ impl Synchronizer {
     async fn delta(&self, sync_info: &mut SyncInfo, from_scratch: bool) -> SyncResult<()> {
         let mut context = if from_scratch {
             None
-        } else if let Some(delta_context_setting) =
-            self.db.get_setting_value(Setting::FILE_API_DELTA_CONTEXT)?
+        } else if let Some(delta_context_setting) = self
+            .db
+            .get_setting_value(Setting::FILE_API_DELTA_CONTEXT.to_string())
+            .await?
         {
             Some(
                 self.file_api_driver
@@ -305,8 +320,12 @@ impl Synchronizer {
                 }));
             }
 
-            let remote_ids: Vec<&str> = list_result.items.iter().map(|i| i.path_id()).collect();
-            let local_sync_items = self.db.load_sync_items(&remote_ids)?;
+            let remote_ids: Vec<String> = list_result
+                .items
+                .iter()
+                .map(|i| i.path_id().to_string())
+                .collect();
+            let local_sync_items = self.db.load_sync_items(remote_ids).await?;
 
             let tasks = list_result.items.iter().zip(handles.into_iter());
             for (remote_item, handle) in tasks {
@@ -315,7 +334,7 @@ impl Synchronizer {
                     .find(|i| i.item_id == remote_item.path_id());
                 if remote_item.is_deleted {
                     if let Some(local_sync_item) = local_sync_item {
-                        self.delete_local_by_sync(local_sync_item)?;
+                        self.delete_local_by_sync(local_sync_item).await?;
                         sync_info.delete_count += 1;
                     }
                 } else {
@@ -336,10 +355,16 @@ impl Synchronizer {
                 Some(ctx) => {
                     log::debug!(target: LOG_TARGET, "saving delta context: {:?}", ctx);
                     self.db
-                        .replace_setting(Setting::FILE_API_DELTA_CONTEXT, &ctx.to_string())?;
+                        .replace_setting(
+                            Setting::FILE_API_DELTA_CONTEXT.to_string(),
+                            ctx.to_string(),
+                        )
+                        .await?;
                 }
                 None => {
-                    self.db.delete_setting(Setting::FILE_API_DELTA_CONTEXT)?;
+                    self.db
+                        .delete_setting(Setting::FILE_API_DELTA_CONTEXT.to_string())
+                        .await?;
                 }
             }
             if !list_result.has_more {
@@ -351,7 +376,7 @@ impl Synchronizer {
 
     pub async fn upload_resource_if_needed(&self, sync_item: &SyncItem) -> SyncResult<()> {
         if sync_item.item_type == ModelType::Resource {
-            let resource = self.db.load_resource(&sync_item.item_id)?;
+            let resource = self.db.load_resource(sync_item.item_id.clone()).await?;
             let file_path = resource.resource_file_path(&self.resource_dir);
             self.file_api_driver
                 .put_file(&resource.remote_path(), &file_path)
@@ -370,7 +395,7 @@ impl Synchronizer {
                     "pulling note {} to local",
                     note.get_title()
                 );
-                self.db.replace_note(&note, update_source)?;
+                self.db.replace_note(note, update_source).await?;
             }
             ModelType::Folder => {
                 let folder = Folder::dserialize(des)?;
@@ -379,7 +404,7 @@ impl Synchronizer {
                     "pulling folder {} to local",
                     folder.get_title()
                 );
-                self.db.replace_folder(&folder, update_source)?;
+                self.db.replace_folder(folder, update_source).await?;
             }
             ModelType::Resource => {
                 let resource = Resource::dserialize(des)?;
@@ -408,12 +433,12 @@ impl Synchronizer {
                         return Err(e);
                     }
                 }
-                self.db.replace_resource(&resource, update_source)?;
+                self.db.replace_resource(resource, update_source).await?;
             }
             ModelType::Tag => {
                 let tag = Tag::dserialize(des)?;
                 log::debug!(target: LOG_TARGET, "pulling tag {} to local", tag.title);
-                self.db.replace_tag(&tag, update_source)?;
+                self.db.replace_tag(tag, update_source).await?;
             }
             ModelType::NoteTag => {
                 let note_tag = NoteTag::dserialize(des)?;
@@ -422,38 +447,56 @@ impl Synchronizer {
                     "pulling note-tag {} to local",
                     note_tag.id
                 );
-                self.db.replace_note_tag(&note_tag, update_source)?;
+                self.db.replace_note_tag(note_tag, update_source).await?;
+            }
+            ModelType::SmartFilter => {
+                let smart_filter = SmartFilter::dserialize(des)?;
+                log::debug!(
+                    target: LOG_TARGET,
+                    "pulling smart filter {} to local",
+                    smart_filter.title
+                );
+                self.db
+                    .replace_smart_filter(smart_filter, update_source)
+                    .await?;
             }
-            ModelType::Unsupported => {
+            ModelType::Alarm
+            | ModelType::NoteResource
+            | ModelType::ItemChange
+            | ModelType::Unsupported => {
                 log::warn!("skip unsupported type: {}", des.id);
             }
         }
         Ok(())
     }
 
-    fn delete_local_by_sync(&self, sync_item: &SyncItem) -> SyncResult<()> {
+    async fn delete_local_by_sync(&self, sync_item: &SyncItem) -> SyncResult<()> {
         log::debug!(
             target: LOG_TARGET,
             "deleting {}({:?})",
             sync_item.item_id,
             sync_item.item_type
         );
-        let id = &sync_item.item_id;
+        let id = sync_item.item_id.clone();
         let update_source = UpdateSource::RemoteSync;
         match sync_item.item_type {
-            ModelType::Note => self.db.delete_note(id, update_source)?,
-            ModelType::Folder => self.db.delete_folder(id, update_source)?,
-            ModelType::Resource => self.db.delete_resource(id, update_source)?,
-            ModelType::Tag => self.db.delete_tag(id, update_source)?,
-            ModelType::NoteTag => self.db.delete_note_tag(id, update_source)?,
-            ModelType::Unsupported => {
+            ModelType::Note => self.db.delete_note(id, update_source).await?,
+            ModelType::Folder => self.db.delete_folder(id, update_source).await?,
+            ModelType::Resource => self.db.delete_resource(id, update_source).await?,
+            ModelType::Tag => self.db.delete_tag(id, update_source).await?,
+            ModelType::NoteTag => self.db.delete_note_tag(id, update_source).await?,
+            ModelType::SmartFilter => self.db.delete_smart_filter(id, update_source).await?,
+            ModelType::Alarm
+            | ModelType::NoteResource
+            | ModelType::ItemChange
+            | ModelType::Unsupported => {
                 log::warn!("skip unsupported type {}", sync_item.item_id);
             }
         }
         Ok(())
     }
 
-    fn create_conflict_note(
+    async fn create_conflict_note(
         &self,
         local_note: &Note,
         remote_note: Option<&Note>,
@@ -468,7 +511,8 @@ impl Synchronizer {
         }
         let conflict_note = local_note.create_conflict_note();
         self.db
-            .replace_note(&conflict_note, UpdateSource::RemoteSync)?;
+            .replace_note(conflict_note, UpdateSource::RemoteSync)
+            .await?;
         Ok(())
     }
 }
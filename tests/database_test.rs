@@ -1,8 +1,16 @@
-use ruslin_data::{Database, DatabaseResult, Folder, Note, SearchBodyOption, Tag, UpdateSource};
-use std::{ops::Deref, time::Duration};
+use ruslin_data::{
+    sync::{DeserializeForSync, ForSyncDeserializer, SerializeForSync},
+    AbbrNote, AlarmEvent, AlarmScheduler, AsyncDatabase, ChangeKind, Database, DatabaseChange,
+    DatabaseError, DatabaseResult, DateTimeRFC333, DateTimeTimestamp, Folder, ModelType, Note,
+    NoteLink, NoteListRequest, NoteSortField, Placement, Resource, SearchBodyOption,
+    SearchHighlight, SearchItemsRequest, SearchNotesRequest, SearchRanking, Setting, SortDirection,
+    Tag, TodoFilter, TokenizerConfig, UpdateSource, UserDictionaryWord,
+};
+use std::{fs, ops::Deref, path::Path, str::FromStr, sync::Arc, time::Duration};
 use tempfile::TempDir;
+use tokio::sync::mpsc::UnboundedReceiver;
 
-pub struct TestDatabase(pub Database, TempDir);
+pub struct TestDatabase(pub Database, TempDir, TempDir);
 
 impl TestDatabase {
     pub fn temp() -> Self {
@@ -13,7 +21,11 @@ impl TestDatabase {
         let filename = "test.sqlite";
         let db = Database::new_with_filename(temp_dir.path(), temp_resource_dir.path(), filename)
             .unwrap_or_else(|e| panic!("unwrap error {e:?} in {}:{}", file!(), line!()));
-        Self(db, temp_dir)
+        Self(db, temp_dir, temp_resource_dir)
+    }
+
+    pub fn resource_dir(&self) -> &Path {
+        self.2.path()
     }
 }
 
@@ -127,6 +139,720 @@ fn test_load_abbr_notes_order() -> DatabaseResult<()> {
     Ok(())
 }
 
+#[test]
+fn test_move_notes_and_folders() -> DatabaseResult<()> {
+    let db = TestDatabase::temp();
+    // a -> b -> c
+    let folder_a = db.insert_root_folder("folder_a")?;
+    let folder_b = db.insert_folder_with_parent("folder_b", &folder_a.id)?;
+    let folder_c = db.insert_folder_with_parent("folder_c", &folder_b.id)?;
+    let note_1 = db.insert_note_with_parent("1", "", &folder_a.id)?;
+    let note_2 = db.insert_note_with_parent("2", "", &folder_a.id)?;
+    db.move_notes(&[&note_1.id, &note_2.id], &folder_c.id)?;
+    assert_eq!(2, db.load_abbr_notes(Some(&folder_c.id))?.len());
+    assert!(db.load_abbr_notes(Some(&folder_a.id))?.is_empty());
+    assert!(db.move_note(&note_1.id, "missing").is_err());
+
+    assert!(matches!(
+        db.move_folder(&folder_a.id, Some(&folder_c.id)),
+        Err(DatabaseError::FolderCycle)
+    ));
+    assert!(matches!(
+        db.move_folder(&folder_b.id, Some(&folder_b.id)),
+        Err(DatabaseError::FolderCycle)
+    ));
+    db.move_folder(&folder_c.id, None)?;
+    assert_eq!(None, db.load_folder(&folder_c.id)?.parent_id);
+    db.move_folder(&folder_a.id, Some(&folder_c.id))?;
+    assert_eq!(
+        Some(folder_c.id.clone()),
+        db.load_folder(&folder_a.id)?.parent_id
+    );
+    let sync_item = db.load_sync_item(&folder_a.id)?;
+    assert!(sync_item.update_time > sync_item.sync_time);
+
+    // Joplin sends an empty parent for root folders, a stored empty parent is a root too.
+    let payload = Folder::new_root("synced").serialize();
+    assert!(payload.as_str().contains("\nparent_id: \n"));
+    let des = ForSyncDeserializer::from_str(payload.as_str()).expect("serialized folder is valid");
+    let synced = Folder::dserialize(&des).expect("folder deserializes");
+    db.replace_folder(&synced, UpdateSource::RemoteSync)?;
+    let empty_parent = Folder::new("empty parent", Some(String::new()));
+    db.replace_folder(&empty_parent, UpdateSource::RemoteSync)?;
+    // Already at the root, nothing to upload.
+    db.move_folder(&empty_parent.id, None)?;
+    let sync_item = db.load_sync_item(&empty_parent.id)?;
+    assert!(sync_item.update_time <= sync_item.sync_time);
+    for parent in [&synced, &empty_parent] {
+        db.move_folder(&folder_b.id, Some(&parent.id))?;
+        assert_eq!(
+            Some(parent.id.clone()),
+            db.load_folder(&folder_b.id)?.parent_id
+        );
+        assert!(matches!(
+            db.move_folder(&parent.id, Some(&folder_b.id)),
+            Err(DatabaseError::FolderCycle)
+        ));
+    }
+    Ok(())
+}
+
+#[test]
+fn test_reorder_note() -> DatabaseResult<()> {
+    let db = TestDatabase::temp();
+    let folder = db.insert_root_folder("folder")?;
+    let mut ids = Vec::new();
+    for title in ["a", "b", "c"] {
+        let mut note = Note::new_with_parent(&folder.id, title, "");
+        // Equal orders leave no gap, the folder has to be renumbered.
+        note.order = 0;
+        db.replace_note(&note, UpdateSource::LocalEdit)?;
+        ids.push(note.id);
+    }
+    let ordered_titles = |db: &Database| -> DatabaseResult<Vec<String>> {
+        let mut request = NoteListRequest::new(Some(&folder.id));
+        request.sort_field = NoteSortField::Order;
+        Ok(db
+            .load_note_list(&request)?
+            .notes
+            .into_iter()
+            .map(|n| n.title)
+            .collect())
+    };
+    db.reorder_note(&ids[2], &ids[0], Placement::Before)?;
+    db.reorder_note(&ids[1], &ids[0], Placement::After)?;
+    assert_eq!(vec!["c", "a", "b"], ordered_titles(&db)?);
+    db.reorder_note(&ids[2], &ids[0], Placement::After)?;
+    assert_eq!(vec!["a", "c", "b"], ordered_titles(&db)?);
+    db.reorder_note(&ids[1], &ids[0], Placement::Before)?;
+    assert_eq!(vec!["b", "a", "c"], ordered_titles(&db)?);
+
+    let other_folder = db.insert_root_folder("other")?;
+    let other = db.insert_note_with_parent("d", "", &other_folder.id)?;
+    db.reorder_note(&other.id, &ids[0], Placement::After)?;
+    assert_eq!(vec!["b", "a", "d", "c"], ordered_titles(&db)?);
+
+    let root = Note::new(None, "root", "");
+    db.replace_note(&root, UpdateSource::LocalEdit)?;
+    let before = db.load_note(&other.id)?.user_updated_time;
+    db.reorder_note(&other.id, &root.id, Placement::Before)?;
+    let moved = db.load_note(&other.id)?;
+    assert_eq!(None, moved.parent_id);
+    assert!(moved.order > root.order);
+    assert!(moved.user_updated_time >= before);
+    assert_eq!(vec!["b", "a", "c"], ordered_titles(&db)?);
+
+    assert!(db
+        .move_notes(&[&ids[0], "missing"], &other_folder.id)
+        .is_err());
+    assert_eq!(
+        Some(folder.id.as_str()),
+        db.load_note(&ids[0])?.parent_id.as_deref()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_load_note_list() -> DatabaseResult<()> {
+    let db = TestDatabase::temp();
+    let folder = db.insert_root_folder("folder")?;
+    for (i, title) in ["b", "C", "a"].into_iter().enumerate() {
+        let mut note = Note::new_with_parent(&folder.id, title, format!("line {i}\n\n  next"));
+        note.user_created_time = DateTimeTimestamp::from_timestamp_millis(i as i64);
+        note.is_todo = title == "a";
+        db.replace_note(&note, UpdateSource::LocalEdit)?;
+    }
+    db.insert_note_with_parent("other", "", db.insert_root_folder("other")?.id)?;
+
+    let titles = |request: &NoteListRequest| -> DatabaseResult<Vec<String>> {
+        Ok(db
+            .load_note_list(request)?
+            .notes
+            .into_iter()
+            .map(|n| n.title)
+            .collect())
+    };
+    let mut request = NoteListRequest::new(Some(&folder.id));
+    request.sort_field = NoteSortField::Title;
+    request.sort_direction = SortDirection::Ascending;
+    assert_eq!(vec!["a", "b", "C"], titles(&request)?);
+    request.sort_field = NoteSortField::CreatedTime;
+    request.sort_direction = SortDirection::Descending;
+    assert_eq!(vec!["a", "C", "b"], titles(&request)?);
+
+    request.offset = 1;
+    request.limit = Some(1);
+    request.preview_length = Some(8);
+    let response = db.load_note_list(&request)?;
+    assert_eq!(3, response.total_count);
+    assert_eq!(1, response.notes.len());
+    assert_eq!(Some("line 1 n"), response.notes[0].preview.as_deref());
+
+    let response = db.load_note_list(&NoteListRequest::new(None))?;
+    assert_eq!(4, response.total_count);
+    assert!(response.notes.iter().all(|n| n.preview.is_none()));
+    assert_eq!(1, response.notes.iter().filter(|n| n.is_todo).count());
+    Ok(())
+}
+
+#[test]
+fn test_todos() -> DatabaseResult<()> {
+    let db = TestDatabase::temp();
+    let folder = db.insert_root_folder("folder")?;
+    let now = DateTimeTimestamp::now().timestamp_millis();
+    let hour = 60 * 60 * 1000;
+    let mut ids = Vec::new();
+    for (title, due) in [
+        ("overdue", now - hour),
+        ("upcoming", now + hour),
+        ("later", now + 48 * hour),
+    ] {
+        let note = db.insert_note_with_parent(title, "", &folder.id)?;
+        db.set_note_todo(&note.id, true)?;
+        db.set_todo_due(
+            &note.id,
+            Some(DateTimeTimestamp::from_timestamp_millis(due)),
+        )?;
+        ids.push(note.id);
+    }
+    let no_due = db.insert_note_with_parent("no due", "", &folder.id)?;
+    db.set_note_todo(&no_due.id, true)?;
+
+    let titles = |filter| -> DatabaseResult<Vec<String>> {
+        Ok(db
+            .load_todos(filter)?
+            .into_iter()
+            .map(|n| n.title)
+            .collect())
+    };
+    assert_eq!(vec!["overdue"], titles(TodoFilter::Overdue)?);
+    assert_eq!(vec!["upcoming", "later"], titles(TodoFilter::Upcoming)?);
+    let due_today = titles(TodoFilter::DueToday)?;
+    assert!(!due_today.contains(&"later".to_string()));
+
+    db.complete_todo(&ids[0], true)?;
+    let note = db.load_note(&ids[0])?;
+    assert!(!note.todo_completed.is_zero());
+    assert!(titles(TodoFilter::Overdue)?.is_empty());
+    db.complete_todo(&ids[0], false)?;
+    assert!(db.load_note(&ids[0])?.todo_completed.is_zero());
+    db.set_note_todo(&ids[0], false)?;
+    let note = db.load_note(&ids[0])?;
+    assert!(!note.is_todo);
+    assert!(note.todo_due.is_zero());
+
+    let note = db.load_note(&ids[1])?;
+    let des =
+        ForSyncDeserializer::from_str(note.serialize().as_str()).expect("serialized note is valid");
+    let todo_due = Note::dserialize(&des).expect("note deserializes").todo_due;
+    assert_eq!(now + hour, todo_due.timestamp_millis());
+    Ok(())
+}
+
+#[test]
+fn test_smart_filters() -> DatabaseResult<()> {
+    let db = TestDatabase::temp();
+    let inbox = db.insert_root_folder("Inbox")?;
+    let archive = db.insert_folder_with_parent("archive", &inbox.id)?;
+    let other = db.insert_root_folder("Other")?;
+    let work = db.insert_tag_path("work")?;
+    let work_rust = db.insert_tag_path("work/rust")?;
+    let on_hold = db.insert_tag_path("on hold")?;
+    let hour = 60 * 60 * 1000;
+    let now = DateTimeTimestamp::now().timestamp_millis();
+
+    let report = db.insert_note_with_parent("report", "quarterly numbers", &inbox.id)?;
+    db.set_note_todo(&report.id, true)?;
+    db.set_todo_due(
+        &report.id,
+        Some(DateTimeTimestamp::from_timestamp_millis(now - hour)),
+    )?;
+    db.add_tag_on_note(&report.id, &work.id)?;
+    let migration = db.insert_note_with_parent("migration", "async rewrite", &archive.id)?;
+    db.set_note_todo(&migration.id, true)?;
+    db.add_tag_on_note(&migration.id, &work_rust.id)?;
+    let paused = db.insert_note_with_parent("paused", "async later", &inbox.id)?;
+    db.set_note_todo(&paused.id, true)?;
+    db.add_tag_on_note(&paused.id, &work.id)?;
+    db.add_tag_on_note(&paused.id, &on_hold.id)?;
+    let done = db.insert_note_with_parent("done", "shipped", &other.id)?;
+    db.set_note_todo(&done.id, true)?;
+    db.complete_todo(&done.id, true)?;
+    db.add_tag_on_note(&done.id, &work.id)?;
+    let journal = db.insert_note_with_parent("journal", "async thoughts", &other.id)?;
+
+    let ids = |query: &str| -> DatabaseResult<Vec<String>> {
+        let mut ids: Vec<String> = db
+            .evaluate_smart_filter_query(query)?
+            .into_iter()
+            .map(|note| note.id)
+            .collect();
+        ids.sort();
+        Ok(ids)
+    };
+    let sorted = |notes: &[&Note]| {
+        let mut ids: Vec<String> = notes.iter().map(|note| note.id.clone()).collect();
+        ids.sort();
+        ids
+    };
+    assert_eq!(
+        sorted(&[&report, &migration, &paused]),
+        ids("tag:WORK -tag:done type:todo iscompleted:0")?
+    );
+    assert_eq!(
+        sorted(&[&report, &migration]),
+        ids("tag:work -tag:\"on hold\" iscompleted:0")?
+    );
+    assert_eq!(sorted(&[&migration]), ids("tag:work/rust")?);
+    assert_eq!(sorted(&[&migration, &paused]), ids("async notebook:inbox")?);
+    assert_eq!(sorted(&[&journal]), ids("async type:note")?);
+    // Free text is searched as words, never as FTS5 operators or columns.
+    assert_eq!(
+        sorted(&[&migration, &journal]),
+        ids("async -later -tag:done")?
+    );
+    assert!(ids("async http://x")?.is_empty());
+    assert!(ids("foo:bar")?.is_empty());
+    assert_eq!(sorted(&[&report]), ids("quarter*")?);
+    assert!(ids("quarter* NOT")?.is_empty());
+    assert_eq!(sorted(&[&report]), ids("-due:day+1")?);
+    assert_eq!(sorted(&[&done]), ids("iscompleted:1")?);
+    assert_eq!(5, ids("created:day")?.len());
+    assert!(ids("-created:day")?.is_empty());
+    assert!(ids("tag:missing")?.is_empty());
+    assert!(matches!(
+        db.insert_smart_filter("invalid", "due:someday"),
+        Err(DatabaseError::InvalidSmartFilter(_))
+    ));
+
+    let mut smart_filter = db.insert_smart_filter("Open work", "tag:work iscompleted:0")?;
+    db.insert_smart_filter("All work", "tag:work")?;
+    assert_eq!(
+        vec!["All work", "Open work"],
+        db.load_smart_filters()?
+            .iter()
+            .map(|x| x.title.as_str())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(3, db.evaluate_smart_filter(&smart_filter.id)?.len());
+    smart_filter.query = "tag:work iscompleted:0 -tag:\"on hold\"".to_string();
+    db.replace_smart_filter(&smart_filter, UpdateSource::LocalEdit)?;
+    assert_eq!(2, db.evaluate_smart_filter(&smart_filter.id)?.len());
+    let sync_item = db.load_sync_item(&smart_filter.id)?;
+    assert_eq!(ModelType::SmartFilter, sync_item.item_type);
+    assert!(db
+        .load_sync_item_content(&sync_item)?
+        .as_str()
+        .contains("tag:work iscompleted:0 -tag:\"on hold\""));
+    assert!(db
+        .load_need_upload_sync_items()?
+        .iter()
+        .any(|item| item.item_id == smart_filter.id));
+    assert_eq!(
+        format!("{}.ruslin.md", smart_filter.id),
+        sync_item.filepath()
+    );
+    db.delete_smart_filter(&smart_filter.id, UpdateSource::LocalEdit)?;
+    assert_eq!(1, db.load_smart_filters()?.len());
+    let deleted_item = db
+        .load_deleted_items()?
+        .into_iter()
+        .find(|item| item.item_id == smart_filter.id)
+        .expect("the deletion is recorded for sync");
+    assert_eq!(sync_item.filepath(), deleted_item.filepath());
+    Ok(())
+}
+
+#[test]
+fn test_note_links() -> DatabaseResult<()> {
+    let db = TestDatabase::temp();
+    let folder = db.insert_root_folder("folder")?;
+    let target = db.insert_note_with_parent("target", "", &folder.id)?;
+    let resource = Resource::new("image", "image/png", "png", 0);
+    db.replace_resource(&resource, UpdateSource::RemoteSync)?;
+    let missing_id = "0123456789abcdef0123456789abcdef";
+    let source = db.insert_note_with_parent(
+        "source",
+        format!(
+            "[target](:/{}) ![image](:/{}) [missing](:/{missing_id}) [self](:/{{self}})",
+            target.id, resource.id
+        ),
+        &folder.id,
+    )?;
+    let body = db.load_note(&source.id)?.body.replace("{self}", &source.id);
+    db.update_note_body(&source.id, &body)?;
+    assert_eq!(3, db.load_note_links(&source.id)?.len());
+    let titles = |notes: Vec<AbbrNote>| notes.into_iter().map(|n| n.title).collect::<Vec<_>>();
+    assert_eq!(vec!["target"], titles(db.load_linked_notes(&source.id)?));
+    assert_eq!(vec!["source"], titles(db.load_backlinks(&target.id)?));
+    assert_eq!(vec!["source"], titles(db.load_backlinks(&resource.id)?));
+    assert!(db.load_backlinks(&source.id)?.is_empty());
+    assert_eq!(
+        vec![NoteLink {
+            source_id: source.id.clone(),
+            target_id: missing_id.to_string(),
+        }],
+        db.load_broken_links()?
+    );
+
+    // Synced notes are parsed too, and conflict notes are not backlinks.
+    let mut remote = Note::new(
+        Some(folder.id.clone()),
+        "remote",
+        format!("[t](:/{})", target.id),
+    );
+    db.replace_note(&remote, UpdateSource::RemoteSync)?;
+    remote.id = ruslin_data::new_id();
+    remote.is_conflict = true;
+    db.replace_note(&remote, UpdateSource::RemoteSync)?;
+    assert_eq!(
+        vec!["remote", "source"],
+        titles(db.load_backlinks(&target.id)?)
+    );
+
+    db.delete_note(&target.id, UpdateSource::LocalEdit)?;
+    assert_eq!(4, db.load_broken_links()?.len());
+    db.update_note_body(&source.id, "no links")?;
+    assert!(db.load_note_links(&source.id)?.is_empty());
+    db.delete_folder(&folder.id, UpdateSource::LocalEdit)?;
+    assert_eq!(1, db.load_broken_links()?.len());
+    Ok(())
+}
+
+#[test]
+fn test_note_links_reindex() -> DatabaseResult<()> {
+    let test_db = TestDatabase::temp();
+    let target = Note::new(None, "target", "");
+    let source = Note::new(None, "source", format!("[t](:/{})", target.id));
+    test_db.replace_note(&target, UpdateSource::LocalEdit)?;
+    test_db.replace_note(&source, UpdateSource::LocalEdit)?;
+    assert_eq!(1, test_db.load_backlinks(&target.id)?.len());
+    test_db.delete_setting(Setting::NOTE_LINKS_VERSION)?;
+    let db = Database::new_with_filename(test_db.1.path(), test_db.2.path(), "test.sqlite")?;
+    assert_eq!(1, db.load_backlinks(&target.id)?.len());
+    assert!(db.get_setting_value(Setting::NOTE_LINKS_VERSION)?.is_some());
+    Ok(())
+}
+
+#[test]
+fn test_orphan_resources() -> DatabaseResult<()> {
+    let db = TestDatabase::temp();
+    let resource_with_file = |title: &str, content: &str| -> DatabaseResult<Resource> {
+        let resource = Resource::new(title, "image/png", "png", content.len() as i32);
+        fs::write(resource.resource_file_path(db.resource_dir()), content)?;
+        db.replace_resource(&resource, UpdateSource::LocalEdit)?;
+        Ok(resource)
+    };
+    let kept = resource_with_file("kept", "kept")?;
+    let orphan = resource_with_file("orphan", "orphan")?;
+    let unreferenced = resource_with_file("unreferenced", "unreferenced")?;
+    let kept_note = Note::new(None, "kept", format!("![](:/{})", kept.id));
+    let orphan_note = Note::new(None, "orphan", format!("![](:/{})", orphan.id));
+    db.replace_note(&kept_note, UpdateSource::LocalEdit)?;
+    db.replace_note(&orphan_note, UpdateSource::LocalEdit)?;
+    // The note is synced before its resource.
+    let late = Resource::new("late", "image/png", "png", 0);
+    let late_note = Note::new(None, "late", format!("![](:/{})", late.id));
+    db.replace_note(&late_note, UpdateSource::RemoteSync)?;
+    assert!(db.load_note_resources(&late_note.id)?.is_empty());
+    db.replace_resource(&late, UpdateSource::RemoteSync)?;
+    let note_resources = db.load_note_resources(&late_note.id)?;
+    assert_eq!(1, note_resources.len());
+    assert_eq!(late.id, note_resources[0].resource_id);
+    assert!(note_resources[0].is_associated);
+
+    db.delete_note(&orphan_note.id, UpdateSource::LocalEdit)?;
+    assert!(!db.load_note_resources(&orphan_note.id)?[0].is_associated);
+    assert!(db
+        .load_orphan_resources(Duration::from_secs(3600))?
+        .is_empty());
+    std::thread::sleep(Duration::from_millis(5));
+    let orphans = db.load_orphan_resources(Duration::ZERO)?;
+    assert_eq!(vec![orphan.id.clone()], ids(&orphans));
+
+    let stray_file = db
+        .resource_dir()
+        .join(ruslin_data::new_id())
+        .with_extension("png");
+    fs::write(&stray_file, "stray")?;
+    let other_file = db.resource_dir().join("notes.txt");
+    fs::write(&other_file, "other")?;
+    std::thread::sleep(Duration::from_millis(5));
+    let report = db.delete_orphan_resources(Duration::ZERO)?;
+    assert_eq!(vec![orphan.id.clone()], ids(&report.deleted_resources));
+    assert_eq!(vec![stray_file.clone()], report.deleted_files);
+    assert_eq!(
+        ("orphan".len() + "stray".len()) as u64,
+        report.reclaimed_bytes
+    );
+    assert!(!orphan.resource_file_path(db.resource_dir()).exists());
+    assert!(other_file.exists());
+    assert!(db.load_resource(&unreferenced.id).is_ok());
+    assert!(db
+        .load_deleted_items()?
+        .iter()
+        .any(|item| item.item_type == ModelType::Resource && item.item_id == orphan.id));
+    assert!(db.load_note_resources(&orphan_note.id)?.is_empty());
+
+    db.update_note_body(&kept_note.id, "no more image")?;
+    std::thread::sleep(Duration::from_millis(5));
+    assert_eq!(
+        vec![kept.id.clone()],
+        ids(&db
+            .delete_orphan_resources(Duration::ZERO)?
+            .deleted_resources)
+    );
+    Ok(())
+}
+
+fn ids(resources: &[Resource]) -> Vec<String> {
+    resources.iter().map(|r| r.id.clone()).collect()
+}
+
+#[test]
+fn test_import_resource() -> DatabaseResult<()> {
+    let db = TestDatabase::temp();
+    let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR-image";
+    let imported = db.import_resource_bytes(png, "photo")?;
+    assert!(!imported.reused);
+    let resource = &imported.resource;
+    assert_eq!("photo", resource.title);
+    assert_eq!("image/png", resource.mime);
+    assert_eq!("png", resource.file_extension);
+    assert_eq!(png.len() as i32, resource.size);
+    assert_eq!(
+        format!("![photo](:/{})", resource.id),
+        imported.markdown_link
+    );
+    assert_eq!(
+        png.as_slice(),
+        fs::read(resource.resource_file_path(db.resource_dir()))?
+    );
+    assert_eq!(*resource, db.load_resource(&resource.id)?);
+
+    let source_dir = TempDir::new()?;
+    let copy = source_dir.path().join("copy.png");
+    fs::write(&copy, png)?;
+    let again = db.import_resource(&copy)?;
+    assert!(again.reused);
+    assert_eq!(resource.id, again.resource.id);
+
+    let text = source_dir.path().join("notes.md");
+    fs::write(&text, "# Notes")?;
+    let imported_text = db.import_resource(&text)?;
+    assert!(!imported_text.reused);
+    assert_eq!("text/markdown", imported_text.resource.mime);
+    assert_eq!(
+        format!("[notes.md](:/{})", imported_text.resource.id),
+        imported_text.markdown_link
+    );
+    // Only the resource files are left in the resource directory.
+    assert_eq!(2, fs::read_dir(db.resource_dir())?.count());
+
+    db.delete_resource(&resource.id, UpdateSource::LocalEdit)?;
+    assert!(!db.import_resource(&copy)?.reused);
+    assert!(db
+        .import_resource(&source_dir.path().join("missing"))
+        .is_err());
+
+    // A synced resource has no stored hash, it is hashed when a file of its size is imported.
+    let content = b"synced content";
+    let synced = Resource::new("synced", "text/plain", "txt", content.len() as i32);
+    fs::write(synced.resource_file_path(db.resource_dir()), content)?;
+    db.replace_resource(&synced, UpdateSource::RemoteSync)?;
+    let imported = db.import_resource_bytes(content, "copy.txt")?;
+    assert!(imported.reused);
+    assert_eq!(synced.id, imported.resource.id);
+    Ok(())
+}
+
+#[test]
+fn test_thumbnails() -> DatabaseResult<()> {
+    let db = TestDatabase::temp();
+    let source_dir = TempDir::new()?;
+    let photo = source_dir.path().join("photo.png");
+    image::RgbImage::new(40, 20).save(&photo)?;
+    let resource = db.import_resource(&photo)?.resource;
+    let thumbnail = db.load_thumbnail(&resource.id, 10)?;
+    assert!(thumbnail.starts_with(format!("{}-thumbnails", db.resource_dir().display())));
+    assert_eq!((10, 5), image::image_dimensions(&thumbnail)?);
+    assert_eq!(thumbnail, db.load_thumbnail(&resource.id, 10)?);
+    // Small images are not enlarged.
+    assert_eq!(
+        (40, 20),
+        image::image_dimensions(db.load_thumbnail(&resource.id, 100)?)?
+    );
+
+    std::thread::sleep(Duration::from_millis(5));
+    db.replace_resource(&resource, UpdateSource::LocalEdit)?;
+    let updated_thumbnail = db.load_thumbnail(&resource.id, 10)?;
+    assert_ne!(thumbnail, updated_thumbnail);
+    assert!(!thumbnail.exists());
+
+    let text = db.import_resource_bytes(b"text", "notes.txt")?.resource;
+    assert!(matches!(
+        db.load_thumbnail(&text.id, 10),
+        Err(DatabaseError::UnsupportedImage(_))
+    ));
+    db.delete_resource(&resource.id, UpdateSource::LocalEdit)?;
+    assert!(!updated_thumbnail.exists());
+    assert_eq!(
+        0,
+        fs::read_dir(updated_thumbnail.parent().unwrap())?.count()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_export_markdown() -> DatabaseResult<()> {
+    let db = TestDatabase::temp();
+    let work = db.insert_root_folder("Work")?;
+    let projects = db.insert_folder_with_parent("Projects", &work.id)?;
+    let photo = db.import_resource_bytes(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR", "my photo.png")?;
+    let missing = Resource::new("missing", "image/png", "png", 0);
+    db.replace_resource(&missing, UpdateSource::RemoteSync)?;
+    let mut encrypted = Resource::new("secret", "image/png", "png", 6);
+    encrypted.encryption_blob_encrypted = true;
+    fs::write(encrypted.resource_file_path(db.resource_dir()), "cipher")?;
+    db.replace_resource(&encrypted, UpdateSource::RemoteSync)?;
+    let ideas = db.insert_note_with_parent("Ideas", "", &work.id)?;
+    let mut plan = Note::new(
+        Some(projects.id.clone()),
+        "Plan: Q1",
+        format!(
+            "See [ideas](:/{}#top).\n\n{}\n\n![gone](:/{})",
+            ideas.id, photo.markdown_link, missing.id
+        ),
+    );
+    plan.is_todo = true;
+    plan.todo_due = DateTimeTimestamp::from_timestamp_millis(1_700_000_000_000);
+    plan.source_url = "https://example.com".to_string();
+    db.replace_note(&plan, UpdateSource::LocalEdit)?;
+    db.insert_note_with_parent(
+        "plan: q1",
+        format!("![secret](:/{})", encrypted.id),
+        &projects.id,
+    )?;
+    let tag = db.insert_tag_path("work/project")?;
+    db.add_tag_on_note(&plan.id, &tag.id)?;
+
+    let export_dir = TempDir::new()?;
+    let report = db.export_markdown(export_dir.path())?;
+    assert_eq!(2, report.folder_count);
+    assert_eq!(3, report.note_count);
+    assert_eq!(1, report.resource_count);
+    assert_eq!(
+        vec![missing.id.clone(), encrypted.id.clone()],
+        report.missing_resources
+    );
+
+    let root = export_dir.path();
+    assert_eq!(
+        "",
+        fs::read_to_string(root.join("Work/Ideas.md"))?
+            .split("---\n\n")
+            .nth(1)
+            .unwrap()
+    );
+    assert!(root.join("Work/Projects/plan_ q1 (2).md").exists());
+    assert_eq!(
+        fs::read(photo.resource.resource_file_path(db.resource_dir()))?,
+        fs::read(root.join("_resources/my photo.png"))?
+    );
+    let exported = fs::read_to_string(root.join("Work/Projects/Plan_ Q1.md"))?;
+    let expected = format!(
+        "---\n\
+         title: 'Plan: Q1'\n\
+         id: {}\n\
+         created: {}\n\
+         updated: {}\n\
+         tags:\n\
+         - work/project\n\
+         todo: true\n\
+         due: 2023-11-14T22:13:20.000Z\n\
+         source_url: https://example.com\n\
+         ---\n\n\
+         See [ideas](../Ideas.md#top).\n\n\
+         ![my photo.png](../../_resources/my%20photo.png)\n\n\
+         ![gone](:/{})",
+        plan.id,
+        DateTimeRFC333::from(plan.user_created_time).as_string(),
+        DateTimeRFC333::from(db.load_note(&plan.id)?.user_updated_time).as_string(),
+        missing.id,
+    );
+    assert_eq!(expected, exported);
+    Ok(())
+}
+
+#[test]
+fn test_alarms() -> DatabaseResult<()> {
+    let db = TestDatabase::temp();
+    let due = DateTimeTimestamp::from_timestamp_millis(
+        DateTimeTimestamp::now().timestamp_millis() + 60_000,
+    );
+    let mut todo = Note::new(None, "todo", "");
+    todo.is_todo = true;
+    todo.todo_due = due;
+    db.replace_note(&todo, UpdateSource::RemoteSync)?;
+    let alarms = db.load_alarms()?;
+    assert_eq!(1, alarms.len());
+    assert_eq!(due, alarms[0].trigger_time);
+    assert_eq!(Some(alarms[0].clone()), db.load_next_alarm()?);
+    assert!(db.load_due_alarms(DateTimeTimestamp::now())?.is_empty());
+    assert_eq!(1, db.load_due_alarms(due)?.len());
+
+    db.set_alarm_fired(alarms[0].id)?;
+    assert_eq!(None, db.load_next_alarm()?);
+    db.update_note_title(&todo.id, "new title")?;
+    assert!(!db.load_alarms()?[0].fired_time.is_zero());
+    let later = DateTimeTimestamp::from_timestamp_millis(due.timestamp_millis() + 1);
+    db.set_todo_due(&todo.id, Some(later))?;
+    assert_eq!(Some(later), db.load_next_alarm()?.map(|a| a.trigger_time));
+
+    db.complete_todo(&todo.id, true)?;
+    assert!(db.load_alarms()?.is_empty());
+    db.complete_todo(&todo.id, false)?;
+    assert_eq!(1, db.load_alarms()?.len());
+    db.delete_note(&todo.id, UpdateSource::RemoteSync)?;
+    assert!(db.load_alarms()?.is_empty());
+    Ok(())
+}
+
+async fn next_alarm_event(events: &mut UnboundedReceiver<AlarmEvent>) -> AlarmEvent {
+    tokio::time::timeout(Duration::from_secs(10), events.recv())
+        .await
+        .expect("alarm fired")
+        .expect("scheduler running")
+}
+
+#[tokio::test]
+async fn test_alarm_scheduler() -> DatabaseResult<()> {
+    let TestDatabase(db, _temp_dir, _temp_resource_dir) = TestDatabase::temp();
+    let db = AsyncDatabase::new(Arc::new(db));
+    let now = DateTimeTimestamp::now().timestamp_millis();
+    let todo_at = |title: &str, due: i64| {
+        let mut todo = Note::new(None, title, "");
+        todo.is_todo = true;
+        todo.todo_due = DateTimeTimestamp::from_timestamp_millis(due);
+        todo
+    };
+    db.replace_note(todo_at("missed", now - 1000), UpdateSource::LocalEdit)
+        .await?;
+    let (_scheduler, mut events) = AlarmScheduler::start(db.clone());
+    let event = next_alarm_event(&mut events).await;
+    assert_eq!("missed", event.note_title);
+    assert!(event.missed);
+
+    db.replace_note(todo_at("soon", now + 500), UpdateSource::RemoteSync)
+        .await?;
+    let event = next_alarm_event(&mut events).await;
+    assert_eq!("soon", event.note_title);
+    assert!(!event.missed);
+    assert!(DateTimeTimestamp::now() >= event.alarm.trigger_time);
+    assert!(db.load_next_alarm().await?.is_none());
+    Ok(())
+}
+
 #[test]
 fn test_search_notes() -> DatabaseResult<()> {
     let db = TestDatabase::temp();
@@ -207,6 +933,413 @@ fn test_search_option() -> DatabaseResult<()> {
     Ok(())
 }
 
+#[test]
+fn test_search_notes_with_request() -> DatabaseResult<()> {
+    let db = TestDatabase::temp();
+    let folder = db.insert_root_folder("folder")?;
+    for i in 0..5 {
+        db.insert_note_with_parent(format!("abcd {i}"), "efgh abcd", &folder.id)?;
+    }
+    db.insert_note_with_parent("efgh", "", &folder.id)?;
+    let mut request = SearchNotesRequest::new("abcd");
+    request.body_option = Some(SearchBodyOption::Snippet { max_tokens: 255 });
+    request.highlight = SearchHighlight {
+        open: "[".to_string(),
+        close: "]".to_string(),
+        ellipsis: "...".to_string(),
+    };
+    request.offset = 2;
+    request.limit = Some(2);
+    let response = db.search_notes_with_request(&request)?;
+    assert_eq!(5, response.total_count);
+    assert_eq!(2, response.notes.len());
+    for note in response.notes.iter() {
+        assert!(note.title.starts_with("[abcd]"));
+        assert_eq!("efgh [abcd]", note.body);
+        assert_eq!(Some(folder.id.as_str()), note.parent_id.as_deref());
+    }
+    request.offset = 4;
+    let response = db.search_notes_with_request(&request)?;
+    assert_eq!(5, response.total_count);
+    assert_eq!(1, response.notes.len());
+    let response = db.search_notes_with_request(&SearchNotesRequest::new("\"it's\""))?;
+    assert_eq!(0, response.total_count);
+    Ok(())
+}
+
+#[test]
+fn test_search_multilingual_notes() -> DatabaseResult<()> {
+    let db = TestDatabase::temp();
+    db.replace_note(
+        &Note::new(None, "ひらがなのメモ", "한국어 공부 Crème Brûlée"),
+        UpdateSource::LocalEdit,
+    )?;
+    assert_eq!(1, db.search_notes("がな", None)?.len());
+    assert_eq!(1, db.search_notes("한국", None)?.len());
+    assert_eq!(0, db.search_notes("creme", None)?.len());
+    db.replace_tokenizer_config(TokenizerConfig {
+        case_folding: true,
+        remove_diacritics: true,
+        ..Default::default()
+    })?;
+    assert!(db.tokenizer_config().case_folding);
+    assert_eq!(1, db.search_notes("creme", None)?.len());
+    assert_eq!(1, db.search_notes("BRULEE", None)?.len());
+    Ok(())
+}
+
+#[test]
+fn test_search_pinyin() -> DatabaseResult<()> {
+    let db = TestDatabase::temp();
+    let note = Note::new(None, "我是中国人", "");
+    db.replace_note(&note, UpdateSource::LocalEdit)?;
+    assert_eq!(0, db.search_notes("zg", None)?.len());
+    db.replace_tokenizer_config(TokenizerConfig {
+        pinyin: true,
+        ..Default::default()
+    })?;
+    assert_eq!(1, db.search_notes("zg", None)?.len());
+    assert_eq!(1, db.search_notes("zhongguo", None)?.len());
+    assert_eq!(1, db.search_notes("zhong*", None)?.len());
+    let notes = db.search_notes("zg", Some(SearchBodyOption::Highlight))?;
+    assert_eq!("我是<b>中国</b>人", notes[0].title);
+    Ok(())
+}
+
+#[test]
+fn test_search_notes_fuzzy() -> DatabaseResult<()> {
+    let db = TestDatabase::temp();
+    db.replace_note(
+        &Note::new(None, "Grocery list", "apples and bananas"),
+        UpdateSource::LocalEdit,
+    )?;
+    let mut request = SearchNotesRequest::new("banaans");
+    let response = db.search_notes_with_request(&request)?;
+    assert_eq!(0, response.total_count);
+    assert!(response.suggestions.is_empty());
+    request.fuzzy = true;
+    let response = db.search_notes_with_request(&request)?;
+    assert_eq!(1, response.total_count);
+    assert_eq!(vec!["bananas"], response.suggestions);
+    request.search_term = "grocrey apples".to_string();
+    let response = db.search_notes_with_request(&request)?;
+    assert_eq!(vec!["grocery apples"], response.suggestions);
+    assert_eq!(1, response.notes.len());
+    request.search_term = "zzzzzz".to_string();
+    let response = db.search_notes_with_request(&request)?;
+    assert_eq!(0, response.total_count);
+    assert!(response.suggestions.is_empty());
+    Ok(())
+}
+
+#[test]
+fn test_search_items() -> DatabaseResult<()> {
+    let db = TestDatabase::temp();
+    let folder = db.insert_root_folder("Travel plans")?;
+    db.insert_note_with_parent("Travel diary", "day one", &folder.id)?;
+    let tag = Tag::new("travel");
+    db.replace_tag(&tag, UpdateSource::LocalEdit)?;
+    let resource = Resource::new("travel map", "image/png", "png", 0);
+    db.replace_resource(&resource, UpdateSource::RemoteSync)?;
+    db.replace_note(&Note::new(None, "Groceries", ""), UpdateSource::LocalEdit)?;
+
+    let items = db.search_items(&SearchItemsRequest::new("travel"))?;
+    let mut item_types: Vec<i32> = items.iter().map(|i| i.item_type as i32).collect();
+    item_types.sort_unstable();
+    assert_eq!(
+        vec![
+            ModelType::Note as i32,
+            ModelType::Folder as i32,
+            ModelType::Resource as i32,
+            ModelType::Tag as i32
+        ],
+        item_types
+    );
+    let tag_item = items
+        .iter()
+        .find(|i| i.item_type == ModelType::Tag)
+        .unwrap();
+    assert_eq!(tag.id, tag_item.id);
+    assert_eq!("<b>travel</b>", tag_item.title);
+    assert!(items.windows(2).all(|w| w[0].score >= w[1].score));
+    assert!(items.iter().all(|i| i.score > 0.0 && i.score <= 1.0));
+
+    let packing = db.insert_note_with_parent("Packing", "travel light", &folder.id)?;
+    let items = db.search_items(&SearchItemsRequest::new("light"))?;
+    assert_eq!(1, items.len());
+    assert_eq!(packing.id, items[0].id);
+    assert_eq!("Packing", items[0].title);
+    assert_eq!("travel <b>light</b>", items[0].snippet);
+    assert_eq!(1.0, items[0].score);
+
+    let mut request = SearchItemsRequest::new("travel");
+    request.limit = Some(2);
+    assert_eq!(2, db.search_items(&request)?.len());
+
+    db.delete_tag(&tag.id, UpdateSource::LocalEdit)?;
+    assert_eq!(
+        4,
+        db.search_items(&SearchItemsRequest::new("travel"))?.len()
+    );
+    Ok(())
+}
+
+#[test]
+fn test_search_resource_text() -> DatabaseResult<()> {
+    let db = TestDatabase::temp();
+    let text = Resource::new("recipe", "text/html", "html", 0);
+    fs::write(
+        text.resource_file_path(db.resource_dir()),
+        "<p>Preheat the <b>oven</b></p><script>let kitchen = 1;</script>",
+    )?;
+    db.replace_resource(&text, UpdateSource::LocalEdit)?;
+    let image = Resource::new("photo", "image/png", "png", 0);
+    fs::write(image.resource_file_path(db.resource_dir()), "oven")?;
+    db.replace_resource(&image, UpdateSource::LocalEdit)?;
+    let note = Note::new(None, "Dinner", format!("[recipe](:/{})", text.id));
+    db.replace_note(&note, UpdateSource::LocalEdit)?;
+    db.replace_note(
+        &Note::new(None, "Oven cleaning", ""),
+        UpdateSource::LocalEdit,
+    )?;
+
+    let mut request = SearchNotesRequest::new("oven");
+    assert_eq!(1, db.search_notes_with_request(&request)?.total_count);
+    request.include_resources = true;
+    let response = db.search_notes_with_request(&request)?;
+    assert_eq!(2, response.total_count);
+    assert!(response.notes.iter().any(|n| n.id == note.id));
+    request.body_option = Some(SearchBodyOption::Snippet { max_tokens: 8 });
+    let response = db.search_notes_with_request(&request)?;
+    let dinner = response.notes.iter().find(|n| n.id == note.id).unwrap();
+    assert_eq!(
+        "Preheat the <b>oven</b>",
+        dinner.body.split_whitespace().collect::<Vec<_>>().join(" ")
+    );
+    request.search_term = "kitchen".to_string();
+    assert_eq!(0, db.search_notes_with_request(&request)?.total_count);
+
+    let items = db.search_items(&SearchItemsRequest::new("oven"))?;
+    assert!(items.iter().any(|i| i.id == text.id));
+    assert!(!items.iter().any(|i| i.id == image.id));
+    Ok(())
+}
+
+#[test]
+fn test_search_ranking() -> DatabaseResult<()> {
+    let db = TestDatabase::temp();
+    let title_note = Note::new(None, "apple", "pie");
+    db.replace_note(&title_note, UpdateSource::LocalEdit)?;
+    let body_note = Note::new(None, "fruit", "apple apple apple");
+    db.replace_note(&body_note, UpdateSource::LocalEdit)?;
+    let mut todo = Note::new(None, "buy apple", "");
+    todo.is_todo = true;
+    todo.todo_completed = DateTimeTimestamp::now();
+    db.replace_note(&todo, UpdateSource::LocalEdit)?;
+
+    let score_of = |request: &SearchNotesRequest, id: &str| -> DatabaseResult<f64> {
+        let response = db.search_notes_with_request(request)?;
+        assert!(response.notes.windows(2).all(|w| w[0].score <= w[1].score));
+        Ok(response.notes.iter().find(|n| n.id == id).unwrap().score)
+    };
+    let mut request = SearchNotesRequest::new("apple");
+    request.ranking.title_weight = 10.0;
+    assert!(score_of(&request, &title_note.id)? < score_of(&request, &body_note.id)?);
+    request.ranking = SearchRanking {
+        body_weight: 10.0,
+        ..Default::default()
+    };
+    assert!(score_of(&request, &body_note.id)? < score_of(&request, &title_note.id)?);
+
+    request.ranking = SearchRanking::default();
+    let score = score_of(&request, &todo.id)?;
+    request.ranking.demote_completed_todos = true;
+    assert!((score_of(&request, &todo.id)? - score * 0.5).abs() < 1e-9);
+    request.ranking = SearchRanking {
+        recency_days: Some(30.0),
+        ..Default::default()
+    };
+    assert!(score_of(&request, &todo.id)? < score * 1.9);
+    Ok(())
+}
+
+#[test]
+fn test_subscribe_changes() -> DatabaseResult<()> {
+    let db = TestDatabase::temp();
+    let mut changes = db.subscribe_changes();
+    let note = Note::new(None, "title", "body");
+    db.replace_note(&note, UpdateSource::LocalEdit)?;
+    db.update_note_title(&note.id, "new title")?;
+    let tag = Tag::new("tag");
+    db.replace_tag(&tag, UpdateSource::RemoteSync)?;
+    db.delete_note(&note.id, UpdateSource::LocalEdit)?;
+    let change = |item_type, item_id: &str, kind, update_source| DatabaseChange {
+        item_type,
+        item_id: item_id.to_string(),
+        kind,
+        update_source,
+    };
+    let expected = vec![
+        change(
+            ModelType::Note,
+            &note.id,
+            ChangeKind::Created,
+            UpdateSource::LocalEdit,
+        ),
+        change(
+            ModelType::Note,
+            &note.id,
+            ChangeKind::Updated,
+            UpdateSource::LocalEdit,
+        ),
+        change(
+            ModelType::Tag,
+            &tag.id,
+            ChangeKind::Created,
+            UpdateSource::RemoteSync,
+        ),
+        change(
+            ModelType::Note,
+            &note.id,
+            ChangeKind::Deleted,
+            UpdateSource::LocalEdit,
+        ),
+    ];
+    let mut received = Vec::new();
+    while let Ok(change) = changes.try_recv() {
+        received.push(change);
+    }
+    assert_eq!(expected, received);
+    Ok(())
+}
+
+#[test]
+fn test_transaction() -> DatabaseResult<()> {
+    let db = TestDatabase::temp();
+    let mut changes = db.subscribe_changes();
+    let folder = db.transaction(|tx| {
+        let folder = tx.insert_root_folder("folder")?;
+        for i in 0..100 {
+            tx.insert_note_with_parent(format!("title{i}"), "body", &folder.id)?;
+        }
+        assert_eq!(100, tx.note_count()?);
+        Ok(folder)
+    })?;
+    assert_eq!(100, db.note_count()?);
+    assert_eq!(101, db.load_all_sync_items()?.len());
+    let mut received = 0;
+    while changes.try_recv().is_ok() {
+        received += 1;
+    }
+    assert_eq!(101, received);
+
+    let result: DatabaseResult<()> = db.transaction(|tx| {
+        tx.insert_note_with_parent("title", "body", &folder.id)?;
+        tx.delete_folder(&folder.id, UpdateSource::LocalEdit)?;
+        Err(DatabaseError::Unknown)
+    });
+    assert!(matches!(result, Err(DatabaseError::Unknown)));
+    assert_eq!(100, db.note_count()?);
+    assert_eq!(1, db.folder_count()?);
+    assert!(db.load_deleted_items()?.is_empty());
+    assert!(changes.try_recv().is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_async_database() -> DatabaseResult<()> {
+    let TestDatabase(db, _temp_dir, _temp_resource_dir) = TestDatabase::temp();
+    let db = AsyncDatabase::new(Arc::new(db));
+    let folder = db.insert_root_folder("folder".to_string()).await?;
+    let note = db
+        .insert_note_with_parent("title".to_string(), "body".to_string(), folder.id.clone())
+        .await?;
+    db.update_note_body(note.id.clone(), "new body".to_string())
+        .await?;
+    assert_eq!("new body", db.load_note(note.id.clone()).await?.body);
+    let count = db
+        .transaction(move |tx| {
+            tx.delete_note(&note.id, UpdateSource::LocalEdit)?;
+            tx.note_count()
+        })
+        .await?;
+    assert_eq!(0, count);
+    assert_eq!(1, db.load_deleted_items().await?.len());
+    Ok(())
+}
+
+#[test]
+fn test_item_changes() -> DatabaseResult<()> {
+    let db = TestDatabase::temp();
+    assert_eq!(0, db.latest_item_change_counter()?);
+    let note = Note::new(None, "title", "body");
+    db.replace_note(&note, UpdateSource::LocalEdit)?;
+    let counter = db.latest_item_change_counter()?;
+    db.replace_note(&note, UpdateSource::RemoteSync)?;
+    db.delete_note(&note.id, UpdateSource::RemoteSync)?;
+
+    let changes = db.load_item_changes_since(0, None)?;
+    assert_eq!(3, changes.len());
+    assert!(changes.windows(2).all(|w| w[0].id < w[1].id));
+    assert!(changes
+        .iter()
+        .all(|c| c.item_type == ModelType::Note && c.item_id == note.id));
+    assert_eq!(ChangeKind::Created, changes[0].change_type);
+    assert_eq!(UpdateSource::LocalEdit, changes[0].source);
+
+    let changes = db.load_item_changes_since(counter, None)?;
+    assert_eq!(
+        vec![
+            (ChangeKind::Updated, UpdateSource::RemoteSync),
+            (ChangeKind::Deleted, UpdateSource::RemoteSync)
+        ],
+        changes
+            .iter()
+            .map(|c| (c.change_type, c.source))
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(1, db.load_item_changes_since(counter, Some(1))?.len());
+
+    db.delete_item_changes_until(counter)?;
+    assert_eq!(2, db.load_item_changes_since(0, None)?.len());
+    Ok(())
+}
+
+#[test]
+fn test_user_dictionary() -> DatabaseResult<()> {
+    let db = TestDatabase::temp();
+    let note = Note::new(None, "拖拉机学院", "");
+    db.replace_note(&note, UpdateSource::LocalEdit)?;
+    assert_eq!(1, db.search_notes("学院", None)?.len());
+    db.replace_user_dictionary_word(&UserDictionaryWord::new("拖拉机学院"))?;
+    assert_eq!(1, db.load_user_dictionary_words()?.len());
+    assert_eq!(1, db.search_notes("拖拉机学院", None)?.len());
+    assert_eq!(0, db.search_notes("学院", None)?.len());
+    db.delete_user_dictionary_word("拖拉机学院")?;
+    assert!(db.load_user_dictionary_words()?.is_empty());
+    assert_eq!(1, db.search_notes("学院", None)?.len());
+    Ok(())
+}
+
+#[test]
+fn test_search_english_stemming() -> DatabaseResult<()> {
+    let db = TestDatabase::temp();
+    db.replace_tokenizer_config(TokenizerConfig {
+        english_stemming: true,
+        english_stop_words: true,
+        ..Default::default()
+    })?;
+    db.replace_note(
+        &Note::new(None, "Running Notes", "the quick fox"),
+        UpdateSource::LocalEdit,
+    )?;
+    assert_eq!(1, db.search_notes("run", None)?.len());
+    assert_eq!(1, db.search_notes("note", None)?.len());
+    assert_eq!(1, db.search_notes("runn*", None)?.len());
+    assert_eq!(0, db.search_notes("the", None)?.len());
+    Ok(())
+}
+
 #[test]
 fn test_tag() -> DatabaseResult<()> {
     let db = TestDatabase::temp();
@@ -226,6 +1359,210 @@ fn test_tag() -> DatabaseResult<()> {
     Ok(())
 }
 
+#[test]
+fn test_tag_hierarchy() -> DatabaseResult<()> {
+    let db = TestDatabase::temp();
+    let alpha = db.insert_tag_path("project/alpha")?;
+    let project = db.load_tag(alpha.parent_id.as_deref().unwrap())?;
+    assert_eq!("project", project.title);
+    assert_eq!(None, project.parent_id);
+    // Existing tags are matched case-insensitively and empty parts are skipped.
+    let beta = db.insert_tag_path(" Project // beta ")?;
+    assert_eq!(Some(project.id.clone()), beta.parent_id);
+    assert_eq!(alpha.id, db.insert_tag_path("PROJECT/Alpha")?.id);
+    assert_eq!(3, db.tag_count()?);
+    assert!(matches!(
+        db.insert_tag_path(" / "),
+        Err(DatabaseError::InvalidTagPath(_))
+    ));
+    let nested = db.insert_tag_path("project/alpha/nested")?;
+    assert_eq!("project/alpha/nested", db.load_tag_path(&nested.id)?);
+
+    let tree = db.load_tag_tree()?;
+    assert_eq!(1, tree.len());
+    assert_eq!(project.id, tree[0].tag.id);
+    assert_eq!(
+        vec!["alpha", "beta"],
+        tree[0]
+            .children
+            .iter()
+            .map(|node| node.tag.title.as_str())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(nested.id, tree[0].children[0].children[0].tag.id);
+
+    let note1 = Note::new(None, "title1", "body1");
+    let note2 = Note::new(None, "title2", "body2");
+    db.replace_note(&note1, UpdateSource::LocalEdit)?;
+    db.replace_note(&note2, UpdateSource::LocalEdit)?;
+    db.add_tag_on_note(&note1.id, &nested.id)?;
+    db.add_tag_on_note(&note2.id, &beta.id)?;
+    assert!(db.load_abbr_notes_with_tag(&project.id, false)?.is_empty());
+    assert_eq!(2, db.load_abbr_notes_with_tag(&project.id, true)?.len());
+    let notes = db.load_abbr_notes_with_tag(&alpha.id, true)?;
+    assert_eq!(1, notes.len());
+    assert_eq!(note1.id, notes[0].id);
+
+    assert!(matches!(
+        db.move_tag(&project.id, Some(&nested.id)),
+        Err(DatabaseError::TagCycle)
+    ));
+    assert!(matches!(
+        db.move_tag(&alpha.id, Some(&alpha.id)),
+        Err(DatabaseError::TagCycle)
+    ));
+    db.move_tag(&alpha.id, None)?;
+    assert_eq!("alpha/nested", db.load_tag_path(&nested.id)?);
+    assert_eq!(2, db.load_tag_tree()?.len());
+    db.move_tag(&alpha.id, Some(&beta.id))?;
+    assert_eq!("project/beta/alpha/nested", db.load_tag_path(&nested.id)?);
+    db.rename_tag(&beta.id, "gamma")?;
+    assert_eq!("project/gamma/alpha/nested", db.load_tag_path(&nested.id)?);
+    assert!(db.load_sync_item(&alpha.id)?.never_synced());
+
+    // The parent is synced with the tag, an empty one is a root tag.
+    let tag_from = |tag: &Tag| {
+        let des = ForSyncDeserializer::from_str(tag.serialize().as_str())
+            .expect("serialized tag is valid");
+        Tag::dserialize(&des).expect("tag deserializes")
+    };
+    assert_eq!(
+        Some(beta.id.clone()),
+        tag_from(&db.load_tag(&alpha.id)?).parent_id
+    );
+    assert_eq!(None, tag_from(&project).parent_id);
+    Ok(())
+}
+
+#[test]
+fn test_merge_duplicate_tags() -> DatabaseResult<()> {
+    let db = TestDatabase::temp();
+    let work = db.insert_tag_path("work")?;
+    let later = db.insert_tag_path("work/later")?;
+    // Pulled from a device that created the same tags first.
+    let mut remote_work = Tag::new("Work");
+    remote_work.created_time = DateTimeTimestamp::from_timestamp_millis(1_000);
+    db.replace_tag(&remote_work, UpdateSource::RemoteSync)?;
+    let mut remote_later = Tag::new_with_parent("Later", Some(remote_work.id.clone()));
+    remote_later.created_time = DateTimeTimestamp::from_timestamp_millis(1_000);
+    db.replace_tag(&remote_later, UpdateSource::RemoteSync)?;
+    let note1 = db.insert_note_with_parent("1", "", db.insert_root_folder("folder")?.id)?;
+    let note2 = db.insert_note_with_parent("2", "", note1.parent_id.clone().unwrap())?;
+    db.add_tag_on_note(&note1.id, &work.id)?;
+    db.add_tag_on_note(&note1.id, &remote_work.id)?;
+    db.add_tag_on_note(&note2.id, &later.id)?;
+
+    let note_tag_ids = |note_id: &str| -> DatabaseResult<Vec<String>> {
+        Ok(db
+            .get_note_tags(note_id)?
+            .into_iter()
+            .map(|tag| tag.id)
+            .collect())
+    };
+    assert_eq!(2, db.merge_duplicate_tags()?);
+    let mut tags = db.load_all_tags()?;
+    tags.sort_by_key(|tag| tag.title.clone());
+    assert_eq!(
+        vec![remote_later.id.as_str(), remote_work.id.as_str()],
+        tags.iter().map(|tag| tag.id.as_str()).collect::<Vec<_>>()
+    );
+    assert_eq!(vec![remote_work.id.clone()], note_tag_ids(&note1.id)?);
+    assert_eq!(vec![remote_later.id.clone()], note_tag_ids(&note2.id)?);
+    let deleted_ids: Vec<String> = db
+        .load_deleted_items()?
+        .into_iter()
+        .map(|item| item.item_id)
+        .collect();
+    assert!(deleted_ids.contains(&work.id) && deleted_ids.contains(&later.id));
+    assert_eq!(0, db.merge_duplicate_tags()?);
+    Ok(())
+}
+
+#[test]
+fn test_merge_tags() -> DatabaseResult<()> {
+    let db = TestDatabase::temp();
+    let rust = db.insert_tag_path("rust")?;
+    let rust_async = db.insert_tag_path("rust/async")?;
+    // Both devices created the same tag before syncing.
+    let remote_rust = Tag::new("Rust");
+    db.replace_tag(&remote_rust, UpdateSource::RemoteSync)?;
+    let remote_async = Tag::new_with_parent("ASYNC", Some(remote_rust.id.clone()));
+    db.replace_tag(&remote_async, UpdateSource::RemoteSync)?;
+    let remote_web = db.insert_tag_path("Rust/web")?;
+    assert_eq!(rust.id, remote_web.parent_id.unwrap());
+    db.move_tag(&remote_web.id, Some(&remote_rust.id))?;
+    assert!(matches!(
+        db.replace_tag(&Tag::new("RUST"), UpdateSource::LocalEdit),
+        Err(DatabaseError::DuplicateTag(title)) if title == "rust"
+    ));
+
+    let note1 = Note::new(None, "title1", "body1");
+    let note2 = Note::new(None, "title2", "body2");
+    db.replace_note(&note1, UpdateSource::LocalEdit)?;
+    db.replace_note(&note2, UpdateSource::LocalEdit)?;
+    db.add_tag_on_note(&note1.id, &rust.id)?;
+    db.add_tag_on_note(&note1.id, &remote_rust.id)?;
+    db.add_tag_on_note(&note2.id, &remote_rust.id)?;
+    db.add_tag_on_note(&note2.id, &remote_async.id)?;
+    db.add_tag_on_note(&note2.id, &rust_async.id)?;
+    let duplicate_note_tag = db.load_note_tag_on_note(&note1.id, &remote_rust.id)?;
+
+    db.merge_tags(&[&remote_rust.id, &rust.id], &rust.id)?;
+    let tree = db.load_tag_tree()?;
+    assert_eq!(1, tree.len());
+    assert_eq!(rust.id, tree[0].tag.id);
+    assert_eq!(
+        vec![rust_async.id.as_str(), remote_web.id.as_str()],
+        tree[0]
+            .children
+            .iter()
+            .map(|node| node.tag.id.as_str())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!(3, db.note_tag_count()?);
+    assert_eq!(vec![rust.clone()], db.get_note_tags(&note1.id)?);
+    assert_eq!(2, db.get_note_tags(&note2.id)?.len());
+    let deleted_ids: Vec<String> = db
+        .load_deleted_items()?
+        .into_iter()
+        .map(|item| item.item_id)
+        .collect();
+    for id in [&remote_rust.id, &remote_async.id, &duplicate_note_tag.id] {
+        assert!(deleted_ids.contains(id));
+    }
+    assert!(matches!(
+        db.merge_tags(&[&rust.id], &rust_async.id),
+        Err(DatabaseError::TagCycle)
+    ));
+
+    // Renaming to an existing title merges into that tag.
+    let web = db.rename_tag(&rust_async.id, "WEB")?;
+    assert_eq!(remote_web.id, web.id);
+    assert_eq!(2, db.get_note_tags(&note2.id)?.len());
+    assert_eq!("rust/web", db.load_tag_path(&web.id)?);
+    assert_eq!("Web", db.rename_tag(&web.id, "Web")?.title);
+    assert_eq!("Web", db.rename_tag(&web.id, "  Web ")?.title);
+    for title in ["", "   ", "web/async"] {
+        assert!(matches!(
+            db.rename_tag(&web.id, title),
+            Err(DatabaseError::InvalidTagPath(_))
+        ));
+    }
+
+    db.insert_tag_path("unused/nested")?;
+    db.insert_tag_path("rust/unused")?;
+    assert_eq!(5, db.tag_count()?);
+    assert_eq!(3, db.delete_unused_tags()?);
+    assert_eq!(2, db.tag_count()?);
+    // A synced deletion leaves the note tags of the note.
+    db.delete_note(&note2.id, UpdateSource::RemoteSync)?;
+    assert_eq!(3, db.note_tag_count()?);
+    assert_eq!(1, db.delete_unused_tags()?);
+    assert_eq!(vec![rust], db.load_all_tags()?);
+    assert_eq!(2, db.note_tag_count()?);
+    Ok(())
+}
+
 #[test]
 fn test_tag_when_deleting_note() -> DatabaseResult<()> {
     let db = TestDatabase::temp();